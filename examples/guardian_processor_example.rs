@@ -4,11 +4,15 @@ use psy_doge_bridge_wormhole::{
             BTCTransaction, BTCTransactionInputWithoutScript,
             BTCTransactionOutput,
         }
-    }, psy_doge_link::{link_async::DogeLinkElectrsRPCAsync, traits::PsyBitcoinAPIAsync}, secp256k1::signer::{
+    }, psy_doge_link::{
+        coin_selection::{select_coins, CoinSelectionParams, FallbackCoinSelector, P2PKH_SCRIPT_SIG_MAX_SIZE},
+        link_async::DogeLinkElectrsRPCAsync, traits::PsyBitcoinAPIAsync,
+    }, secp256k1::signer::{
         CompressedPublicKeyToP2PKH, MemorySecp256K1Wallet, Secp256K1WalletProvider, SimpleSinglePublicKeySigner, SimpleSingleSigner
     }, tx_store::{memory_cache_transaction_store::DogecoinTransactionProviderWithCache, traits::DogecoinRPCProviderAsync}, wormhole::{
         guardian_processor::WormholeGuardianProcessorAsync,
         p2sh_vaa::{WormholeBitcoinLikeVAAMessage, WormholeBitcoinLikeVAAMetadata},
+        script_template::WORMHOLE_VAA_TEMPLATE_V1,
     }
 };
 use tokio::time::sleep;
@@ -45,44 +49,39 @@ pub fn create_p2pkh_tx<W: Secp256K1WalletProvider>(
     Ok(base_tx)
 }
 
-async fn fund_script<N: DogeNetworkConfig, RPC: PsyBitcoinAPIAsync, W: Secp256K1WalletProvider>(wallet: &W, rpc: &RPC, from_private_key: [u8; 32], to_address: BTCAddress160, amount: u64, fee: u64) -> anyhow::Result<QHash256> {
+async fn fund_script<N: DogeNetworkConfig, RPC: PsyBitcoinAPIAsync, W: Secp256K1WalletProvider>(wallet: &W, rpc: &RPC, from_private_key: [u8; 32], to_address: BTCAddress160, amount: u64, fee_rate: u64) -> anyhow::Result<QHash256> {
     let signer = SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>(from_private_key)?;
 
     let from_address = signer.get_compressed_public_key().to_p2pkh_address::<Hasher>();
     let from_address = BTCAddress160::new_p2pkh(from_address);
 
     let utxos = rpc.get_utxos(from_address).await?;
-    let mut inputs = Vec::new();
-    let mut input_value = 0u64;
-    for utxo in utxos.iter() {
-        let mut hash = utxo.txid.clone();
-        hash.reverse();
-        inputs.push(BTCTransactionInputWithoutScript {
-            hash,
-            index: utxo.vout,
-            sequence: 0xffffffff,
+    let to_script = to_address.to_btc_script();
+    let change_script = from_address.to_btc_script();
+    let params = CoinSelectionParams::new(amount, P2PKH_SCRIPT_SIG_MAX_SIZE, vec![to_script.len()], change_script.len(), fee_rate)
+        .with_relay_policy(N::RELAY_POLICY);
+    let selection = select_coins(&FallbackCoinSelector::default(), &utxos, &params)?;
+    let inputs = selection
+        .selected
+        .iter()
+        .map(|utxo| {
+            let mut hash = utxo.txid;
+            hash.reverse();
+            BTCTransactionInputWithoutScript::new_simple(hash, utxo.vout)
+        })
+        .collect::<Vec<_>>();
+
+    let mut outputs = vec![BTCTransactionOutput {
+        value: amount,
+        script: to_script,
+    }];
+    if selection.has_change() {
+        outputs.push(BTCTransactionOutput {
+            value: selection.change_amount,
+            script: change_script,
         });
-        input_value += utxo.value;
-        if input_value >= amount + fee {
-            break;
-        }
     }
 
-    if input_value < amount + fee {
-        anyhow::bail!("Insufficient funds to fund script");
-    }
-
-    let outputs = vec![
-        BTCTransactionOutput {
-            value: amount,
-            script: to_address.to_btc_script(),
-        },
-        BTCTransactionOutput {
-            value: input_value - amount - fee,
-            script: from_address.to_btc_script(),
-        },
-    ];
-
     let tx = create_p2pkh_tx(wallet, from_address.address, &inputs, outputs)?;
     let result_txid = rpc.send_transaction(&tx).await?;
     Ok(result_txid)
@@ -104,7 +103,7 @@ async fn setup_scenario(fund_to: BTCAddress160, amount: u64) -> anyhow::Result<Q
     );
 
     let rpc_provider = DogeLinkElectrsRPCAsync::<Network>::new("https://doge-electrs-testnet-demo.qed.me");
-    let txid = fund_script::<Network, _, _>(&wallet, &rpc_provider, funder_private_key, fund_to, amount, 100_000).await?;
+    let txid = fund_script::<Network, _, _>(&wallet, &rpc_provider, funder_private_key, fund_to, amount, 1_000).await?;
     println!("Funded script with TXID: {}", hex::encode(txid));
 
 
@@ -144,10 +143,12 @@ async fn main() -> anyhow::Result<()> {
         total_output_amount: 900_000,      // 0.009 DOGE
         max_doge_transaction_fee: 1000_000, // 0.001 DOGE
         min_doge_transaction_fee: 100_000,  // 0.0001 DOGE
+        template_version: WORMHOLE_VAA_TEMPLATE_V1, // P2PKH tail
+        recovery: None,                     // no emergency recovery branch
     };
 
     // 3. Determine the P2SH address where funds are locked.
-    let vaa_p2sh_address = vaa_metadata.get_p2sh_address::<Network, Hasher>(&guardian_pubkey_hash);
+    let vaa_p2sh_address = vaa_metadata.get_p2sh_address::<Network, Hasher>(&guardian_pubkey_hash)?;
     println!(
         "Funds should be locked in P2SH address: {}",
         vaa_p2sh_address.to_address_string::<Network>()
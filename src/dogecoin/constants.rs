@@ -24,7 +24,10 @@ substantial portions of the software:
 with contributions from Carter Feldman (https://x.com/cmpeq)."
 */
 
-use super::network_params::{DogeNetworkParams, DogeNetworkType};
+use super::{
+    network_params::{DogeNetworkParams, DogeNetworkType},
+    relay_policy::{DOGE_DEFAULT_RELAY_POLICY, DogeRelayPolicy},
+};
 /*
 // P2SH: regtest = 0xc4, testnet = 0xc4, mainnet = 0x16
 pub const P2SH_ADDRESS_CHECK58_VERSION: u8 = 0xc4;
@@ -76,6 +79,8 @@ pub trait DogeNetworkConfig {
     const PRIVATE_KEY_VERSION_BYTE: u8;
     const START_ADDRESS_STRING_CHAR: char;
     const START_ADDRESS_STRING_BYTE: u8;
+    // mempool policy used to reject spends that would never be relayed
+    const RELAY_POLICY: DogeRelayPolicy = DOGE_DEFAULT_RELAY_POLICY;
 }
 
 #[derive(Clone, Copy, Default, Ord, PartialEq, Eq, PartialOrd)]
@@ -20,6 +20,13 @@ pub trait DogeHashProvider {
         Self::hash_bytes_sha256(&first_hash)
     }
 
+    // only used by OP_SHA1
+    fn hash_bytes_sha1(data: &[u8]) -> QHash160;
+}
+
+// wormhole envelopes (VAAs) and guardian addresses are keccak256 based
+pub trait Keccak256HashProvider {
+    fn hash_bytes_keccak256(data: &[u8]) -> QHash256;
 }
 
 
@@ -29,6 +36,12 @@ use sha2::{Digest as Sha256Digest, Sha256};
 #[cfg(feature = "hashes")]
 use ripemd::Ripemd160;
 
+#[cfg(feature = "hashes")]
+use sha1::Sha1;
+
+#[cfg(feature = "hashes")]
+use sha3::Keccak256;
+
 #[cfg(feature = "hashes")]
 pub struct CommonDogeHashProvider;
 
@@ -46,4 +59,20 @@ impl DogeHashProvider for CommonDogeHashProvider {
         let result = hasher.finalize();
         result.into()
     }
+    fn hash_bytes_sha1(data: &[u8]) -> QHash160 {
+        let mut hasher = Sha1::new();
+        hasher.update(data);
+        let result = hasher.finalize();
+        result.into()
+    }
+}
+
+#[cfg(feature = "hashes")]
+impl Keccak256HashProvider for CommonDogeHashProvider {
+    fn hash_bytes_keccak256(data: &[u8]) -> QHash256 {
+        let mut hasher = Keccak256::new();
+        hasher.update(data);
+        let result = hasher.finalize();
+        result.into()
+    }
 }
@@ -0,0 +1,220 @@
+use crate::{
+    dogecoin::{
+        address::BTCAddress160,
+        hash::{DogeHashProvider, QHash256},
+        script::{
+            interpreter::get_script_pushes,
+            num::push_script_num,
+            opcodes::{
+                OP_CHECKLOCKTIMEVERIFY, OP_CHECKSIG, OP_DROP, OP_DUP, OP_ELSE, OP_ENDIF, OP_EQUALVERIFY, OP_FALSE,
+                OP_HASH160, OP_IF, OP_SHA256, OP_SIZE, OP_TRUE,
+            },
+        },
+        p2sh_spend::{DogeP2SHSpendBuilder, DogeP2SHSpendPath},
+        transaction::{BTCTransaction, encode_binary_stack_item},
+    },
+};
+
+// secrets are fixed at 32 bytes so the same secret can be revealed on chains with different push size limits
+pub const HTLC_SECRET_SIZE: usize = 32;
+// CHECKLOCKTIMEVERIFY fails for final inputs, so refunds use the highest non-final sequence
+pub const HTLC_REFUND_INPUT_SEQUENCE: u32 = 0xfffffffe;
+
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub enum HTLCHashLock {
+    Sha256(QHash256),
+    Hash160([u8; 20]),
+}
+
+impl HTLCHashLock {
+    pub fn new_sha256<Hasher: DogeHashProvider>(secret: &[u8; HTLC_SECRET_SIZE]) -> Self {
+        Self::Sha256(Hasher::hash_bytes_sha256(secret))
+    }
+    pub fn new_hash160<Hasher: DogeHashProvider>(secret: &[u8; HTLC_SECRET_SIZE]) -> Self {
+        Self::Hash160(Hasher::bitcoin_hash160(secret))
+    }
+    pub fn matches<Hasher: DogeHashProvider>(&self, secret: &[u8]) -> bool {
+        secret.len() == HTLC_SECRET_SIZE
+            && match self {
+                Self::Sha256(hash) => Hasher::hash_bytes_sha256(secret) == *hash,
+                Self::Hash160(hash) => Hasher::bitcoin_hash160(secret) == *hash,
+            }
+    }
+}
+
+/*
+HTLC redeem script:
+    OP_IF
+        OP_SIZE <32> OP_EQUALVERIFY <OP_SHA256|OP_HASH160> <hash> OP_EQUALVERIFY
+        OP_DUP OP_HASH160 <recipient public key hash>
+    OP_ELSE
+        <refund lock time> OP_CHECKLOCKTIMEVERIFY OP_DROP
+        OP_DUP OP_HASH160 <refund public key hash>
+    OP_ENDIF
+    OP_EQUALVERIFY OP_CHECKSIG
+
+claim scriptSig:  <sig> <recipient public key> <secret> OP_TRUE <redeem script>
+refund scriptSig: <sig> <refund public key> OP_FALSE <redeem script>
+*/
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct DogeHTLCParams {
+    pub hash_lock: HTLCHashLock,
+    pub recipient_public_key_hash: [u8; 20],
+    pub refund_public_key_hash: [u8; 20],
+    // block height if below 500_000_000 (LOCKTIME_THRESHOLD), unix timestamp otherwise
+    pub refund_lock_time: u32,
+}
+
+impl DogeHTLCParams {
+    pub fn get_redeem_script(&self) -> Vec<u8> {
+        let mut script = vec![OP_IF, OP_SIZE];
+        script.extend_from_slice(&push_script_num(HTLC_SECRET_SIZE as i64));
+        script.push(OP_EQUALVERIFY);
+        match self.hash_lock {
+            HTLCHashLock::Sha256(hash) => {
+                script.extend_from_slice(&[OP_SHA256, 32]);
+                script.extend_from_slice(&hash);
+            }
+            HTLCHashLock::Hash160(hash) => {
+                script.extend_from_slice(&[OP_HASH160, 20]);
+                script.extend_from_slice(&hash);
+            }
+        }
+        script.extend_from_slice(&[OP_EQUALVERIFY, OP_DUP, OP_HASH160, 20]);
+        script.extend_from_slice(&self.recipient_public_key_hash);
+        script.push(OP_ELSE);
+        script.extend_from_slice(&push_script_num(self.refund_lock_time as i64));
+        script.extend_from_slice(&[OP_CHECKLOCKTIMEVERIFY, OP_DROP, OP_DUP, OP_HASH160, 20]);
+        script.extend_from_slice(&self.refund_public_key_hash);
+        script.extend_from_slice(&[OP_ENDIF, OP_EQUALVERIFY, OP_CHECKSIG]);
+        script
+    }
+    pub fn get_p2sh_address<Hasher: DogeHashProvider>(&self) -> BTCAddress160 {
+        BTCAddress160::new_p2sh(Hasher::bitcoin_hash160(&self.get_redeem_script()))
+    }
+
+    // returns the secret revealed by any input of transaction that claims this HTLC
+    pub fn extract_secret<Hasher: DogeHashProvider>(&self, transaction: &BTCTransaction) -> Option<[u8; HTLC_SECRET_SIZE]> {
+        let redeem_script = self.get_redeem_script();
+        transaction.inputs.iter().find_map(|input| {
+            let pushes = get_script_pushes(&input.script).ok()?;
+            let [_, _, secret, branch, input_redeem_script]: [Vec<u8>; 5] = pushes.try_into().ok()?;
+            if branch != [1u8] || input_redeem_script != redeem_script || !self.hash_lock.matches::<Hasher>(&secret) {
+                return None;
+            }
+            secret.try_into().ok()
+        })
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub enum HTLCSpendPath {
+    Claim { recipient_public_key_hash: [u8; 20], secret: [u8; HTLC_SECRET_SIZE] },
+    Refund { refund_public_key_hash: [u8; 20] },
+}
+
+impl DogeP2SHSpendPath for HTLCSpendPath {
+    fn get_public_key_hash(&self) -> &[u8; 20] {
+        match self {
+            Self::Claim { recipient_public_key_hash, .. } => recipient_public_key_hash,
+            Self::Refund { refund_public_key_hash } => refund_public_key_hash,
+        }
+    }
+    fn get_branch_script(&self) -> Vec<u8> {
+        match self {
+            Self::Claim { secret, .. } => [encode_binary_stack_item(secret), vec![OP_TRUE]].concat(),
+            Self::Refund { .. } => vec![OP_FALSE],
+        }
+    }
+    fn get_input_sequence(&self) -> u32 {
+        match self {
+            Self::Claim { .. } => 0xffffffff,
+            Self::Refund { .. } => HTLC_REFUND_INPUT_SEQUENCE,
+        }
+    }
+}
+
+// Builds claim or refund transactions spending HTLC outputs
+pub type DogeHTLCSpendBuilder = DogeP2SHSpendBuilder<HTLCSpendPath>;
+
+impl DogeHTLCSpendBuilder {
+    pub fn new_claim<Hasher: DogeHashProvider>(
+        params: DogeHTLCParams,
+        secret: [u8; HTLC_SECRET_SIZE],
+    ) -> anyhow::Result<Self> {
+        if !params.hash_lock.matches::<Hasher>(&secret) {
+            anyhow::bail!("secret does not match the HTLC hash lock");
+        }
+        let path = HTLCSpendPath::Claim { recipient_public_key_hash: params.recipient_public_key_hash, secret };
+        Ok(Self::new_for_path(path, params.get_redeem_script(), 0))
+    }
+    pub fn new_refund(params: DogeHTLCParams) -> Self {
+        let path = HTLCSpendPath::Refund { refund_public_key_hash: params.refund_public_key_hash };
+        Self::new_for_path(path, params.get_redeem_script(), params.refund_lock_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dogecoin::{
+            address::AddressToBTCScript,
+            hash::CommonDogeHashProvider,
+            script::interpreter::{STANDARD_SCRIPT_VERIFY_FLAGS, ScriptError, verify_transaction_input},
+            transaction::BTCTransactionInput,
+        },
+        secp256k1::signer::{CompressedPublicKeyToP2PKH, SimpleSinglePublicKeySigner, SimpleSingleSigner},
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+
+    #[test]
+    fn test_htlc_claim_reveals_secret_and_refund_waits_for_lock_time() {
+        let recipient = SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([7u8; 32]).unwrap();
+        let refunder = SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([8u8; 32]).unwrap();
+        let secret = [0x5au8; HTLC_SECRET_SIZE];
+        for hash_lock in [HTLCHashLock::new_sha256::<Hasher>(&secret), HTLCHashLock::new_hash160::<Hasher>(&secret)] {
+            let params = DogeHTLCParams {
+                hash_lock,
+                recipient_public_key_hash: recipient.get_compressed_public_key().to_p2pkh_address::<Hasher>(),
+                refund_public_key_hash: refunder.get_compressed_public_key().to_p2pkh_address::<Hasher>(),
+                refund_lock_time: 1_000,
+            };
+            let spent_output = params.get_p2sh_address::<Hasher>().to_btc_output(1_000_000);
+            let funding_hash = BTCTransaction::from_io(vec![BTCTransactionInput::default()], vec![spent_output.clone()])
+                .get_hash::<Hasher>();
+            let payout = BTCAddress160::new_p2pkh([0xbb; 20]).to_btc_output(900_000);
+
+            let claim = DogeHTLCSpendBuilder::new_claim::<Hasher>(params, secret)
+                .unwrap()
+                .with_input(funding_hash, 0)
+                .with_output(payout.clone())
+                .sign::<Hasher, _>(&recipient)
+                .unwrap();
+            verify_transaction_input::<Hasher>(&claim, 0, &spent_output.script, STANDARD_SCRIPT_VERIFY_FLAGS).unwrap();
+            assert_eq!(params.extract_secret::<Hasher>(&claim), Some(secret));
+            assert!(DogeHTLCSpendBuilder::new_claim::<Hasher>(params, [0u8; HTLC_SECRET_SIZE]).is_err());
+
+            let refund = DogeHTLCSpendBuilder::new_refund(params)
+                .with_input(funding_hash, 0)
+                .with_output(payout.clone())
+                .sign::<Hasher, _>(&refunder)
+                .unwrap();
+            verify_transaction_input::<Hasher>(&refund, 0, &spent_output.script, STANDARD_SCRIPT_VERIFY_FLAGS).unwrap();
+            assert_eq!(params.extract_secret::<Hasher>(&refund), None);
+            // a correctly signed refund below the refund lock time only fails CHECKLOCKTIMEVERIFY
+            let early_refund = DogeHTLCSpendBuilder::new_refund(params)
+                .with_locktime(999)
+                .with_input(funding_hash, 0)
+                .with_output(payout.clone())
+                .sign::<Hasher, _>(&refunder)
+                .unwrap();
+            assert_eq!(
+                verify_transaction_input::<Hasher>(&early_refund, 0, &spent_output.script, STANDARD_SCRIPT_VERIFY_FLAGS),
+                Err(ScriptError::UnsatisfiedLockTime)
+            );
+        }
+    }
+}
@@ -6,3 +6,7 @@ pub mod transaction;
 pub mod varuint;
 pub mod sighash;
 pub mod transaction_layout;
+pub mod script;
+pub mod p2sh_spend;
+pub mod htlc;
+pub mod relay_policy;
@@ -0,0 +1,108 @@
+use crate::{
+    dogecoin::{
+        hash::{DogeHashProvider, QHash256},
+        sighash::{SIGHASH_ALL, SigHashPreimage},
+        transaction::{BTCTransaction, BTCTransactionInput, BTCTransactionOutput, encode_binary_stack_item},
+    },
+    secp256k1::{
+        signature::PsyCompressedSecp256K1Signature, signer::SimpleSingleSigner,
+        verifier::verify_signature_for_key_and_message,
+    },
+};
+
+// one branch of a redeem script that ends in OP_DUP OP_HASH160 <public key hash> OP_EQUALVERIFY OP_CHECKSIG
+pub trait DogeP2SHSpendPath {
+    fn get_public_key_hash(&self) -> &[u8; 20];
+    // the pushes between <public key> and <redeem script> that select this branch
+    fn get_branch_script(&self) -> Vec<u8>;
+    fn get_input_sequence(&self) -> u32;
+}
+
+// Builds a transaction spending P2SH outputs through one branch of their redeem script:
+// scriptSig = <sig> <public key> <branch pushes> <redeem script>
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct DogeP2SHSpendBuilder<Path: DogeP2SHSpendPath> {
+    path: Path,
+    redeem_script: Vec<u8>,
+    transaction: BTCTransaction,
+}
+
+impl<Path: DogeP2SHSpendPath> DogeP2SHSpendBuilder<Path> {
+    pub fn new_for_path(path: Path, redeem_script: Vec<u8>, locktime: u32) -> Self {
+        Self {
+            path,
+            redeem_script,
+            transaction: BTCTransaction {
+                version: 2,
+                inputs: vec![],
+                outputs: vec![],
+                locktime,
+            },
+        }
+    }
+    // overrides the locktime the constructor picked for the branch
+    pub fn with_locktime(mut self, locktime: u32) -> Self {
+        self.transaction.locktime = locktime;
+        self
+    }
+    // hash is the funding transaction hash (not the reversed txid)
+    pub fn with_input(mut self, hash: QHash256, index: u32) -> Self {
+        self.transaction.inputs.push(BTCTransactionInput {
+            hash,
+            index,
+            script: vec![],
+            sequence: self.path.get_input_sequence(),
+        });
+        self
+    }
+    pub fn with_output(mut self, output: BTCTransactionOutput) -> Self {
+        self.transaction.outputs.push(output);
+        self
+    }
+    pub fn get_unsigned_transaction(&self) -> &BTCTransaction {
+        &self.transaction
+    }
+    pub fn get_redeem_script(&self) -> &[u8] {
+        &self.redeem_script
+    }
+    pub fn get_sighashes<Hasher: DogeHashProvider>(&self) -> Vec<QHash256> {
+        (0..self.transaction.inputs.len())
+            .map(|i| {
+                SigHashPreimage::for_transaction_pre_segwit(&self.transaction, i, &self.redeem_script, SIGHASH_ALL)
+                    .get_hash::<Hasher>()
+            })
+            .collect()
+    }
+    pub fn assemble<Hasher: DogeHashProvider>(
+        &self,
+        signatures: &[PsyCompressedSecp256K1Signature],
+    ) -> anyhow::Result<BTCTransaction> {
+        let sighashes = self.get_sighashes::<Hasher>();
+        if signatures.len() != sighashes.len() {
+            anyhow::bail!("expected {} signatures but got {}", sighashes.len(), signatures.len());
+        }
+        let branch_script = self.path.get_branch_script();
+        let encoded_redeem_script = encode_binary_stack_item(&self.redeem_script);
+        let mut transaction = self.transaction.clone();
+        for (input_index, ((input, signature), sighash)) in transaction
+            .inputs
+            .iter_mut()
+            .zip(signatures.iter())
+            .zip(sighashes.iter())
+            .enumerate()
+        {
+            verify_signature_for_key_and_message::<Hasher>(signature, self.path.get_public_key_hash(), sighash)
+                .map_err(|e| anyhow::Error::new(e).context(format!("rejected signature for input {}", input_index)))?;
+            input.script = [signature.to_btc_script(), branch_script.clone(), encoded_redeem_script.clone()].concat();
+        }
+        Ok(transaction)
+    }
+    pub fn sign<Hasher: DogeHashProvider, Signer: SimpleSingleSigner>(&self, signer: &Signer) -> anyhow::Result<BTCTransaction> {
+        let signatures = self
+            .get_sighashes::<Hasher>()
+            .into_iter()
+            .map(|sighash| signer.sign_message(sighash))
+            .collect::<anyhow::Result<Vec<PsyCompressedSecp256K1Signature>>>()?;
+        self.assemble::<Hasher>(&signatures)
+    }
+}
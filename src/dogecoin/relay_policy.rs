@@ -0,0 +1,145 @@
+use crate::dogecoin::{
+    script::opcodes::OP_RETURN, transaction::BTCTransactionOutput, transaction_layout::BTCTransactionLayout,
+};
+
+// dogecoin core 1.14.5+ defaults: -minrelaytxfee = 0.001 DOGE/kB, -harddustlimit = 0.001 DOGE, -dustlimit = 0.01 DOGE
+pub const DOGE_DEFAULT_MIN_RELAY_FEE_PER_KB: u64 = 100_000;
+pub const DOGE_DEFAULT_HARD_DUST_LIMIT: u64 = 100_000;
+pub const DOGE_DEFAULT_SOFT_DUST_LIMIT: u64 = 1_000_000;
+// MAX_STANDARD_TX_SIZE, larger transactions are valid in blocks but not relayed
+pub const DOGE_DEFAULT_MAX_STANDARD_TX_SIZE: usize = 100_000;
+
+pub const DOGE_DEFAULT_RELAY_POLICY: DogeRelayPolicy =
+    DogeRelayPolicy::new(DOGE_DEFAULT_MIN_RELAY_FEE_PER_KB, DOGE_DEFAULT_HARD_DUST_LIMIT);
+
+// The mempool acceptance rules a transaction must pass to be relayed, amounts in koinu
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct DogeRelayPolicy {
+    pub min_relay_fee_per_kb: u64,
+    // spendable outputs below this value are not relayed
+    pub dust_limit: u64,
+    // every spendable output below this value adds soft_dust_limit to the minimum relay fee (GetDogecoinDustFee)
+    pub soft_dust_limit: u64,
+    pub max_standard_tx_size: usize,
+}
+
+impl DogeRelayPolicy {
+    pub const fn new(min_relay_fee_per_kb: u64, dust_limit: u64) -> Self {
+        Self {
+            min_relay_fee_per_kb,
+            dust_limit,
+            soft_dust_limit: DOGE_DEFAULT_SOFT_DUST_LIMIT,
+            max_standard_tx_size: DOGE_DEFAULT_MAX_STANDARD_TX_SIZE,
+        }
+    }
+    // 0 disables the soft dust fee, as in dogecoin core before 1.14.5 with -dustlimit=0
+    pub const fn with_soft_dust_limit(mut self, soft_dust_limit: u64) -> Self {
+        self.soft_dust_limit = soft_dust_limit;
+        self
+    }
+    pub const fn with_max_standard_tx_size(mut self, max_standard_tx_size: usize) -> Self {
+        self.max_standard_tx_size = max_standard_tx_size;
+        self
+    }
+    // pro rata fee for a transaction of size bytes, rounded up
+    pub fn get_min_relay_fee(&self, size: usize) -> u64 {
+        (size as u64).saturating_mul(self.min_relay_fee_per_kb).div_ceil(1000)
+    }
+    // OP_RETURN outputs are unspendable and never dust
+    pub fn is_dust(&self, output: &BTCTransactionOutput) -> bool {
+        output.script.first() != Some(&OP_RETURN) && output.value < self.dust_limit
+    }
+    pub fn is_soft_dust(&self, output: &BTCTransactionOutput) -> bool {
+        output.script.first() != Some(&OP_RETURN) && output.value < self.soft_dust_limit
+    }
+    // the fee added on top of the size based relay fee for outputs below the soft dust limit
+    pub fn get_dust_fee(&self, outputs: &[BTCTransactionOutput]) -> u64 {
+        let count = outputs.iter().filter(|output| self.is_soft_dust(output)).count() as u64;
+        count.saturating_mul(self.soft_dust_limit)
+    }
+    // the smallest spendable output that is neither rejected nor charged the soft dust fee
+    pub fn get_min_output_value(&self) -> u64 {
+        self.dust_limit.max(self.soft_dust_limit)
+    }
+    pub fn get_min_fee(&self, size: usize, outputs: &[BTCTransactionOutput]) -> u64 {
+        self.get_min_relay_fee(size).saturating_add(self.get_dust_fee(outputs))
+    }
+    pub fn check_outputs(&self, outputs: &[BTCTransactionOutput]) -> anyhow::Result<()> {
+        if let Some((index, output)) = outputs.iter().enumerate().find(|(_, output)| self.is_dust(output)) {
+            anyhow::bail!(
+                "output {} of {} koinu is below the dust limit of {}",
+                index,
+                output.value,
+                self.dust_limit
+            );
+        }
+        Ok(())
+    }
+    // layout describes the signed transaction, fee is what the transaction pays
+    pub fn check_transaction(
+        &self,
+        layout: &BTCTransactionLayout,
+        fee: u64,
+        outputs: &[BTCTransactionOutput],
+    ) -> anyhow::Result<()> {
+        let size = layout.get_total_size();
+        if size > self.max_standard_tx_size {
+            anyhow::bail!(
+                "transaction size of {} bytes is above the standard limit of {}",
+                size,
+                self.max_standard_tx_size
+            );
+        }
+        let min_fee = self.get_min_fee(size, outputs);
+        if fee < min_fee {
+            anyhow::bail!(
+                "transaction fee {} is below the minimum relay fee of {} for {} bytes and {} soft dust outputs",
+                fee,
+                min_fee,
+                size,
+                outputs.iter().filter(|output| self.is_soft_dust(output)).count()
+            );
+        }
+        self.check_outputs(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relay_policy_fee_and_dust() {
+        let policy = DOGE_DEFAULT_RELAY_POLICY;
+        assert_eq!(policy.get_min_relay_fee(1000), 100_000);
+        assert_eq!(policy.get_min_relay_fee(1001), 100_100);
+        assert_eq!(DogeRelayPolicy::new(1500, 0).get_min_relay_fee(1), 2);
+
+        let outputs = vec![
+            BTCTransactionOutput { value: 1_000_000, script: vec![0x51] },
+            BTCTransactionOutput { value: 0, script: vec![OP_RETURN, 0x01, 0xff] },
+        ];
+        let layout = BTCTransactionLayout::new(vec![200], vec![1, 3]);
+        let size = layout.get_total_size();
+        policy.check_transaction(&layout, policy.get_min_relay_fee(size), &outputs).unwrap();
+        assert!(policy.check_transaction(&layout, policy.get_min_relay_fee(size) - 1, &outputs).is_err());
+
+        let dust = vec![BTCTransactionOutput { value: 99_999, script: vec![0x51] }];
+        assert!(policy.check_transaction(&layout, 1_000_000, &dust).is_err());
+
+        // each output below the soft dust limit pays the soft dust limit on top of the relay fee
+        let soft_dust = vec![
+            BTCTransactionOutput { value: 999_999, script: vec![0x51] },
+            BTCTransactionOutput { value: 0, script: vec![OP_RETURN, 0x01, 0xff] },
+        ];
+        assert_eq!(policy.get_dust_fee(&soft_dust), 1_000_000);
+        let min_fee = policy.get_min_relay_fee(size) + 1_000_000;
+        policy.check_transaction(&layout, min_fee, &soft_dust).unwrap();
+        assert!(policy.check_transaction(&layout, min_fee - 1, &soft_dust).is_err());
+        let without_soft_dust = policy.with_soft_dust_limit(0);
+        without_soft_dust.check_transaction(&layout, min_fee - 1_000_000, &soft_dust).unwrap();
+
+        let policy = policy.with_max_standard_tx_size(size - 1);
+        assert!(policy.check_transaction(&layout, 1_000_000, &outputs).is_err());
+    }
+}
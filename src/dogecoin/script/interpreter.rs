@@ -0,0 +1,993 @@
+use std::marker::PhantomData;
+
+use k256::ecdsa::{Signature, VerifyingKey, signature::hazmat::PrehashVerifier};
+
+use crate::{
+    dogecoin::{
+        hash::{DogeHashProvider, QHash256},
+        script::{
+            num::{decode_script_num, encode_script_num},
+            opcodes::*,
+        },
+        sighash::{SIGHASH_ANYONECANPAY, SIGHASH_SINGLE, SigHashPreimage},
+        transaction::{BTCTransaction, encode_binary_stack_item},
+    },
+    secp256k1::signature::{decode_der_signature, is_low_s, is_valid_der_signature_encoding},
+};
+
+// script verification flags, same bit positions as dogecoin core (script/interpreter.h)
+pub const SCRIPT_VERIFY_NONE: u32 = 0;
+pub const SCRIPT_VERIFY_P2SH: u32 = 1 << 0;
+pub const SCRIPT_VERIFY_STRICTENC: u32 = 1 << 1;
+pub const SCRIPT_VERIFY_DERSIG: u32 = 1 << 2;
+pub const SCRIPT_VERIFY_LOW_S: u32 = 1 << 3;
+pub const SCRIPT_VERIFY_NULLDUMMY: u32 = 1 << 4;
+pub const SCRIPT_VERIFY_SIGPUSHONLY: u32 = 1 << 5;
+pub const SCRIPT_VERIFY_MINIMALDATA: u32 = 1 << 6;
+pub const SCRIPT_VERIFY_DISCOURAGE_UPGRADABLE_NOPS: u32 = 1 << 7;
+pub const SCRIPT_VERIFY_CLEANSTACK: u32 = 1 << 8;
+pub const SCRIPT_VERIFY_CHECKLOCKTIMEVERIFY: u32 = 1 << 9;
+pub const SCRIPT_VERIFY_NULLFAIL: u32 = 1 << 14;
+
+pub const MANDATORY_SCRIPT_VERIFY_FLAGS: u32 = SCRIPT_VERIFY_P2SH;
+// the pre-segwit part of dogecoin 1.14's relay policy (CHECKSEQUENCEVERIFY is not implemented and is treated as an upgradable NOP)
+pub const STANDARD_SCRIPT_VERIFY_FLAGS: u32 = MANDATORY_SCRIPT_VERIFY_FLAGS
+    | SCRIPT_VERIFY_DERSIG
+    | SCRIPT_VERIFY_STRICTENC
+    | SCRIPT_VERIFY_MINIMALDATA
+    | SCRIPT_VERIFY_NULLDUMMY
+    | SCRIPT_VERIFY_DISCOURAGE_UPGRADABLE_NOPS
+    | SCRIPT_VERIFY_CLEANSTACK
+    | SCRIPT_VERIFY_CHECKLOCKTIMEVERIFY
+    | SCRIPT_VERIFY_LOW_S
+    | SCRIPT_VERIFY_NULLFAIL;
+
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+pub const MAX_STACK_SIZE: usize = 1000;
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+// nLockTime values below this are block heights, values above are unix timestamps
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+const DEFAULT_MAX_NUM_SIZE: usize = 4;
+const LOCKTIME_MAX_NUM_SIZE: usize = 5;
+
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub enum ScriptError {
+    EvalFalse,
+    OpReturn,
+    ScriptSize,
+    PushSize,
+    OpCount,
+    StackSize,
+    SigCount,
+    PubKeyCount,
+    Verify,
+    EqualVerify,
+    CheckSigVerify,
+    CheckMultiSigVerify,
+    NumEqualVerify,
+    BadOpcode(u8),
+    DisabledOpcode(u8),
+    UnsupportedOpcode(u8),
+    InvalidStackOperation,
+    InvalidAltstackOperation,
+    UnbalancedConditional,
+    InvalidNumber,
+    NegativeLockTime,
+    UnsatisfiedLockTime,
+    SigHashType,
+    SigDer,
+    MinimalData,
+    SigPushOnly,
+    SigHighS,
+    SigNullDummy,
+    SigNullFail,
+    PubKeyType,
+    CleanStack,
+    DiscourageUpgradableNops,
+    InputIndexOutOfRange(usize),
+}
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScriptError::EvalFalse => write!(f, "script evaluated without error but finished with a false/empty top stack element"),
+            ScriptError::OpReturn => write!(f, "OP_RETURN was encountered"),
+            ScriptError::ScriptSize => write!(f, "script is too big"),
+            ScriptError::PushSize => write!(f, "push value size limit exceeded"),
+            ScriptError::OpCount => write!(f, "operation limit exceeded"),
+            ScriptError::StackSize => write!(f, "stack size limit exceeded"),
+            ScriptError::SigCount => write!(f, "signature count negative or greater than pubkey count"),
+            ScriptError::PubKeyCount => write!(f, "pubkey count negative or limit exceeded"),
+            ScriptError::Verify => write!(f, "script failed an OP_VERIFY operation"),
+            ScriptError::EqualVerify => write!(f, "script failed an OP_EQUALVERIFY operation"),
+            ScriptError::CheckSigVerify => write!(f, "script failed an OP_CHECKSIGVERIFY operation"),
+            ScriptError::CheckMultiSigVerify => write!(f, "script failed an OP_CHECKMULTISIGVERIFY operation"),
+            ScriptError::NumEqualVerify => write!(f, "script failed an OP_NUMEQUALVERIFY operation"),
+            ScriptError::BadOpcode(opcode) => write!(f, "opcode 0x{:02x} missing or not understood", opcode),
+            ScriptError::DisabledOpcode(opcode) => write!(f, "attempted to use disabled opcode 0x{:02x}", opcode),
+            ScriptError::UnsupportedOpcode(opcode) => write!(f, "opcode 0x{:02x} is not supported by this interpreter", opcode),
+            ScriptError::InvalidStackOperation => write!(f, "operation not valid with the current stack size"),
+            ScriptError::InvalidAltstackOperation => write!(f, "operation not valid with the current altstack size"),
+            ScriptError::UnbalancedConditional => write!(f, "invalid OP_IF construction"),
+            ScriptError::InvalidNumber => write!(f, "script number overflow or non-minimal encoding"),
+            ScriptError::NegativeLockTime => write!(f, "negative locktime"),
+            ScriptError::UnsatisfiedLockTime => write!(f, "locktime requirement not satisfied"),
+            ScriptError::SigHashType => write!(f, "signature hash type missing or not understood"),
+            ScriptError::SigDer => write!(f, "non-canonical DER signature"),
+            ScriptError::MinimalData => write!(f, "data push larger than necessary"),
+            ScriptError::SigPushOnly => write!(f, "only push operators allowed in signatures"),
+            ScriptError::SigHighS => write!(f, "non-canonical signature: S value is unnecessarily high"),
+            ScriptError::SigNullDummy => write!(f, "dummy CHECKMULTISIG argument must be zero"),
+            ScriptError::SigNullFail => write!(f, "signature must be zero for failed CHECK(MULTI)SIG operation"),
+            ScriptError::PubKeyType => write!(f, "public key is neither compressed nor uncompressed"),
+            ScriptError::CleanStack => write!(f, "extra items left on stack after execution"),
+            ScriptError::DiscourageUpgradableNops => write!(f, "NOPx reserved for soft-fork upgrades"),
+            ScriptError::InputIndexOutOfRange(index) => write!(f, "input index {} is out of range", index),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+pub trait SignatureChecker {
+    // signature is DER || sighash type byte
+    fn check_signature(&self, signature: &[u8], public_key: &[u8], script_code: &[u8]) -> bool;
+    fn check_lock_time(&self, lock_time: i64) -> bool;
+}
+
+// checks signatures against the pre-segwit sighash of one input of a transaction
+pub struct TransactionSignatureChecker<'a, Hasher: DogeHashProvider> {
+    transaction: &'a BTCTransaction,
+    input_index: usize,
+    _hasher: PhantomData<Hasher>,
+}
+
+impl<'a, Hasher: DogeHashProvider> TransactionSignatureChecker<'a, Hasher> {
+    pub fn new(transaction: &'a BTCTransaction, input_index: usize) -> Self {
+        Self {
+            transaction,
+            input_index,
+            _hasher: PhantomData,
+        }
+    }
+    fn get_sighash(&self, script_code: &[u8], sighash_type: u32) -> QHash256 {
+        // consensus quirk: SIGHASH_SINGLE without a matching output signs the number 1
+        if (sighash_type & 0x1f) == SIGHASH_SINGLE && self.input_index >= self.transaction.outputs.len() {
+            let mut one = [0u8; 32];
+            one[0] = 1;
+            return one;
+        }
+        SigHashPreimage::get_sighash_for_transaction_pre_segwit::<Hasher>(
+            self.transaction,
+            self.input_index,
+            script_code,
+            sighash_type,
+        )
+    }
+}
+
+impl<Hasher: DogeHashProvider> SignatureChecker for TransactionSignatureChecker<'_, Hasher> {
+    fn check_signature(&self, signature: &[u8], public_key: &[u8], script_code: &[u8]) -> bool {
+        let Some((sighash_type, der)) = signature.split_last() else {
+            return false;
+        };
+        let Ok(public_key) = VerifyingKey::from_sec1_bytes(public_key) else {
+            return false;
+        };
+        let Ok(signature) = Signature::from_der(der) else {
+            return false;
+        };
+        // high-S is only rejected when SCRIPT_VERIFY_LOW_S is set, k256 always requires low-S
+        let signature = signature.normalize_s().unwrap_or(signature);
+        let sighash = self.get_sighash(script_code, *sighash_type as u32);
+        public_key.verify_prehash(&sighash, &signature).is_ok()
+    }
+
+    fn check_lock_time(&self, lock_time: i64) -> bool {
+        let threshold = LOCKTIME_THRESHOLD as i64;
+        let tx_lock_time = self.transaction.locktime as i64;
+        // both must be heights or both must be timestamps
+        if (tx_lock_time < threshold) != (lock_time < threshold) {
+            return false;
+        }
+        if lock_time > tx_lock_time {
+            return false;
+        }
+        // a final input would let the spender bypass nLockTime
+        match self.transaction.inputs.get(self.input_index) {
+            Some(input) => input.sequence != 0xffffffff,
+            None => false,
+        }
+    }
+}
+
+fn read_push_length(script: &[u8], pc: &mut usize, size: usize, opcode: u8) -> Result<usize, ScriptError> {
+    if script.len() < *pc + size {
+        return Err(ScriptError::BadOpcode(opcode));
+    }
+    let mut length = 0usize;
+    for (i, byte) in script[*pc..(*pc + size)].iter().enumerate() {
+        length |= (*byte as usize) << (8 * i);
+    }
+    *pc += size;
+    Ok(length)
+}
+
+// reads one opcode and its push data (empty for non-push opcodes), advancing pc
+fn read_instruction<'a>(script: &'a [u8], pc: &mut usize) -> Result<(u8, &'a [u8]), ScriptError> {
+    let opcode = script[*pc];
+    *pc += 1;
+    let length = match opcode {
+        0x01..=0x4b => opcode as usize,
+        OP_PUSHDATA1 => read_push_length(script, pc, 1, opcode)?,
+        OP_PUSHDATA2 => read_push_length(script, pc, 2, opcode)?,
+        OP_PUSHDATA4 => read_push_length(script, pc, 4, opcode)?,
+        _ => return Ok((opcode, &[])),
+    };
+    if script.len() - *pc < length {
+        return Err(ScriptError::BadOpcode(opcode));
+    }
+    let data = &script[*pc..(*pc + length)];
+    *pc += length;
+    Ok((opcode, data))
+}
+
+pub fn is_push_only(script: &[u8]) -> bool {
+    let mut pc = 0;
+    while pc < script.len() {
+        match read_instruction(script, &mut pc) {
+            Ok((opcode, _)) if opcode <= OP_16 => {}
+            _ => return false,
+        }
+    }
+    true
+}
+
+// returns the stack item pushed by each instruction of a push only script (OP_0, OP_PUSHBYTES/OP_PUSHDATA, OP_1NEGATE, OP_1 .. OP_16)
+pub fn get_script_pushes(script: &[u8]) -> Result<Vec<Vec<u8>>, ScriptError> {
+    let mut pushes = Vec::new();
+    let mut pc = 0;
+    while pc < script.len() {
+        let (opcode, data) = read_instruction(script, &mut pc)?;
+        let item = match opcode {
+            OP_1NEGATE | OP_1..=OP_16 => {
+                encode_script_num(if opcode == OP_1NEGATE { -1 } else { (opcode - OP_1 + 1) as i64 })
+            }
+            _ if opcode <= OP_PUSHDATA4 => data.to_vec(),
+            _ => return Err(ScriptError::SigPushOnly),
+        };
+        pushes.push(item);
+    }
+    Ok(pushes)
+}
+
+// OP_HASH160 <20 bytes> OP_EQUAL
+pub fn is_p2sh_script_pubkey(script: &[u8]) -> bool {
+    script.len() == 23 && script[0] == OP_HASH160 && script[1] == 0x14 && script[22] == OP_EQUAL
+}
+
+fn check_minimal_push(data: &[u8], opcode: u8) -> bool {
+    if data.is_empty() {
+        opcode == OP_0
+    } else if data.len() == 1 && (1..=16).contains(&data[0]) {
+        // should have used OP_1 .. OP_16
+        false
+    } else if data.len() == 1 && data[0] == 0x81 {
+        // should have used OP_1NEGATE
+        false
+    } else if data.len() <= 75 {
+        opcode as usize == data.len()
+    } else if data.len() <= 255 {
+        opcode == OP_PUSHDATA1
+    } else if data.len() <= 65535 {
+        opcode == OP_PUSHDATA2
+    } else {
+        true
+    }
+}
+
+fn cast_to_bool(bytes: &[u8]) -> bool {
+    for (i, byte) in bytes.iter().enumerate() {
+        if *byte != 0 {
+            // negative zero is false
+            return !(i == bytes.len() - 1 && *byte == 0x80);
+        }
+    }
+    false
+}
+
+fn encode_bool(value: bool) -> Vec<u8> {
+    if value { vec![1] } else { vec![] }
+}
+
+fn check_signature_encoding(signature: &[u8], flags: u32) -> Result<(), ScriptError> {
+    // an empty signature is a valid way to fail a CHECK(MULTI)SIG
+    if signature.is_empty() {
+        return Ok(());
+    }
+    if (flags & (SCRIPT_VERIFY_DERSIG | SCRIPT_VERIFY_LOW_S | SCRIPT_VERIFY_STRICTENC)) != 0
+        && !is_valid_der_signature_encoding(signature)
+    {
+        return Err(ScriptError::SigDer);
+    }
+    if (flags & SCRIPT_VERIFY_LOW_S) != 0 {
+        let rs = decode_der_signature(&signature[..signature.len() - 1]).map_err(|_| ScriptError::SigDer)?;
+        if !is_low_s(&rs[32..64].try_into().unwrap()) {
+            return Err(ScriptError::SigHighS);
+        }
+    }
+    if (flags & SCRIPT_VERIFY_STRICTENC) != 0 {
+        let sighash_type = signature[signature.len() - 1] as u32 & !SIGHASH_ANYONECANPAY;
+        if !(1..=3).contains(&sighash_type) {
+            return Err(ScriptError::SigHashType);
+        }
+    }
+    Ok(())
+}
+
+fn check_public_key_encoding(public_key: &[u8], flags: u32) -> Result<(), ScriptError> {
+    if (flags & SCRIPT_VERIFY_STRICTENC) == 0 {
+        return Ok(());
+    }
+    let valid = match public_key.first() {
+        Some(0x04) => public_key.len() == 65,
+        Some(0x02) | Some(0x03) => public_key.len() == 33,
+        _ => false,
+    };
+    if valid { Ok(()) } else { Err(ScriptError::PubKeyType) }
+}
+
+// removes every push of pattern that starts on an instruction boundary (FindAndDelete in dogecoin core)
+fn find_and_delete(script: &[u8], pattern: &[u8]) -> Vec<u8> {
+    if pattern.is_empty() {
+        return script.to_vec();
+    }
+    let mut result = Vec::with_capacity(script.len());
+    let mut pc = 0;
+    loop {
+        while script[pc..].starts_with(pattern) {
+            pc += pattern.len();
+        }
+        if pc >= script.len() {
+            break;
+        }
+        let start = pc;
+        if read_instruction(script, &mut pc).is_err() {
+            result.extend_from_slice(&script[start..]);
+            break;
+        }
+        result.extend_from_slice(&script[start..pc]);
+    }
+    result
+}
+
+// depth 1 is the top of the stack
+fn stack_top(stack: &[Vec<u8>], depth: usize) -> Result<&Vec<u8>, ScriptError> {
+    if depth == 0 || depth > stack.len() {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    Ok(&stack[stack.len() - depth])
+}
+
+fn pop_stack(stack: &mut Vec<Vec<u8>>) -> Result<Vec<u8>, ScriptError> {
+    stack.pop().ok_or(ScriptError::InvalidStackOperation)
+}
+
+fn require_stack(stack: &[Vec<u8>], size: usize) -> Result<usize, ScriptError> {
+    if stack.len() < size {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    Ok(stack.len())
+}
+
+fn is_disabled_opcode(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        OP_CAT | OP_SUBSTR | OP_LEFT | OP_RIGHT | OP_INVERT | OP_AND | OP_OR | OP_XOR | OP_2MUL | OP_2DIV | OP_MUL | OP_DIV | OP_MOD | OP_LSHIFT | OP_RSHIFT
+    )
+}
+
+pub fn eval_script<Hasher: DogeHashProvider, Checker: SignatureChecker>(
+    stack: &mut Vec<Vec<u8>>,
+    script: &[u8],
+    flags: u32,
+    checker: &Checker,
+) -> Result<(), ScriptError> {
+    if script.len() > MAX_SCRIPT_SIZE {
+        return Err(ScriptError::ScriptSize);
+    }
+    let require_minimal = (flags & SCRIPT_VERIFY_MINIMALDATA) != 0;
+    let mut pc = 0;
+    let mut code_separator_pos = 0;
+    let mut exec_stack: Vec<bool> = Vec::new();
+    let mut alt_stack: Vec<Vec<u8>> = Vec::new();
+    let mut op_count = 0usize;
+
+    while pc < script.len() {
+        let executing = exec_stack.iter().all(|x| *x);
+        let (opcode, push_data) = read_instruction(script, &mut pc)?;
+        if push_data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+            return Err(ScriptError::PushSize);
+        }
+        if opcode > OP_16 {
+            op_count += 1;
+            if op_count > MAX_OPS_PER_SCRIPT {
+                return Err(ScriptError::OpCount);
+            }
+        }
+        // disabled opcodes fail even in an unexecuted branch
+        if is_disabled_opcode(opcode) {
+            return Err(ScriptError::DisabledOpcode(opcode));
+        }
+
+        if executing && opcode <= OP_PUSHDATA4 {
+            if require_minimal && !check_minimal_push(push_data, opcode) {
+                return Err(ScriptError::MinimalData);
+            }
+            stack.push(push_data.to_vec());
+        } else if executing || (OP_IF..=OP_ENDIF).contains(&opcode) {
+            match opcode {
+                OP_1NEGATE | OP_1..=OP_16 => {
+                    let value = if opcode == OP_1NEGATE { -1 } else { (opcode - OP_1 + 1) as i64 };
+                    stack.push(encode_script_num(value));
+                }
+                OP_NOP => {}
+                OP_CHECKLOCKTIMEVERIFY => {
+                    if (flags & SCRIPT_VERIFY_CHECKLOCKTIMEVERIFY) == 0 {
+                        if (flags & SCRIPT_VERIFY_DISCOURAGE_UPGRADABLE_NOPS) != 0 {
+                            return Err(ScriptError::DiscourageUpgradableNops);
+                        }
+                    } else {
+                        // 5 byte numbers so timestamps past 2038 can be expressed
+                        let lock_time = decode_script_num(stack_top(stack, 1)?, require_minimal, LOCKTIME_MAX_NUM_SIZE)?;
+                        if lock_time < 0 {
+                            return Err(ScriptError::NegativeLockTime);
+                        }
+                        if !checker.check_lock_time(lock_time) {
+                            return Err(ScriptError::UnsatisfiedLockTime);
+                        }
+                    }
+                }
+                OP_NOP1 | OP_CHECKSEQUENCEVERIFY | OP_NOP4..=OP_NOP10 => {
+                    if (flags & SCRIPT_VERIFY_DISCOURAGE_UPGRADABLE_NOPS) != 0 {
+                        return Err(ScriptError::DiscourageUpgradableNops);
+                    }
+                }
+                OP_IF | OP_NOTIF => {
+                    let mut value = false;
+                    if executing {
+                        let condition = stack.pop().ok_or(ScriptError::UnbalancedConditional)?;
+                        value = cast_to_bool(&condition);
+                        if opcode == OP_NOTIF {
+                            value = !value;
+                        }
+                    }
+                    exec_stack.push(value);
+                }
+                OP_ELSE => {
+                    let branch = exec_stack.last_mut().ok_or(ScriptError::UnbalancedConditional)?;
+                    *branch = !*branch;
+                }
+                OP_ENDIF => {
+                    exec_stack.pop().ok_or(ScriptError::UnbalancedConditional)?;
+                }
+                OP_VERIFY => {
+                    if !cast_to_bool(&pop_stack(stack)?) {
+                        return Err(ScriptError::Verify);
+                    }
+                }
+                OP_RETURN => return Err(ScriptError::OpReturn),
+
+                OP_TOALTSTACK => {
+                    let item = pop_stack(stack)?;
+                    alt_stack.push(item);
+                }
+                OP_FROMALTSTACK => {
+                    let item = alt_stack.pop().ok_or(ScriptError::InvalidAltstackOperation)?;
+                    stack.push(item);
+                }
+                OP_2DROP => {
+                    let len = require_stack(stack, 2)?;
+                    stack.truncate(len - 2);
+                }
+                OP_2DUP | OP_3DUP => {
+                    let count = if opcode == OP_2DUP { 2 } else { 3 };
+                    let len = require_stack(stack, count)?;
+                    stack.extend_from_within((len - count)..len);
+                }
+                OP_2OVER => {
+                    let len = require_stack(stack, 4)?;
+                    stack.extend_from_within((len - 4)..(len - 2));
+                }
+                OP_2ROT => {
+                    let len = require_stack(stack, 6)?;
+                    let items: Vec<Vec<u8>> = stack.drain((len - 6)..(len - 4)).collect();
+                    stack.extend(items);
+                }
+                OP_2SWAP => {
+                    let len = require_stack(stack, 4)?;
+                    stack.swap(len - 4, len - 2);
+                    stack.swap(len - 3, len - 1);
+                }
+                OP_IFDUP => {
+                    let top = stack_top(stack, 1)?.clone();
+                    if cast_to_bool(&top) {
+                        stack.push(top);
+                    }
+                }
+                OP_DEPTH => stack.push(encode_script_num(stack.len() as i64)),
+                OP_DROP => {
+                    pop_stack(stack)?;
+                }
+                OP_DUP => {
+                    let top = stack_top(stack, 1)?.clone();
+                    stack.push(top);
+                }
+                OP_NIP => {
+                    let len = require_stack(stack, 2)?;
+                    stack.remove(len - 2);
+                }
+                OP_OVER => {
+                    let item = stack_top(stack, 2)?.clone();
+                    stack.push(item);
+                }
+                OP_PICK | OP_ROLL => {
+                    require_stack(stack, 2)?;
+                    let n = decode_script_num(&pop_stack(stack)?, require_minimal, DEFAULT_MAX_NUM_SIZE)?;
+                    if n < 0 || n as usize >= stack.len() {
+                        return Err(ScriptError::InvalidStackOperation);
+                    }
+                    let index = stack.len() - 1 - n as usize;
+                    let item = if opcode == OP_ROLL { stack.remove(index) } else { stack[index].clone() };
+                    stack.push(item);
+                }
+                OP_ROT => {
+                    let len = require_stack(stack, 3)?;
+                    let item = stack.remove(len - 3);
+                    stack.push(item);
+                }
+                OP_SWAP => {
+                    let len = require_stack(stack, 2)?;
+                    stack.swap(len - 2, len - 1);
+                }
+                OP_TUCK => {
+                    let len = require_stack(stack, 2)?;
+                    let top = stack[len - 1].clone();
+                    stack.insert(len - 2, top);
+                }
+                OP_SIZE => {
+                    let size = stack_top(stack, 1)?.len();
+                    stack.push(encode_script_num(size as i64));
+                }
+
+                OP_EQUAL | OP_EQUALVERIFY => {
+                    require_stack(stack, 2)?;
+                    let b = pop_stack(stack)?;
+                    let a = pop_stack(stack)?;
+                    if opcode == OP_EQUALVERIFY {
+                        if a != b {
+                            return Err(ScriptError::EqualVerify);
+                        }
+                    } else {
+                        stack.push(encode_bool(a == b));
+                    }
+                }
+
+                OP_1ADD | OP_1SUB | OP_NEGATE | OP_ABS | OP_NOT | OP_0NOTEQUAL => {
+                    let n = decode_script_num(&pop_stack(stack)?, require_minimal, DEFAULT_MAX_NUM_SIZE)?;
+                    let result = match opcode {
+                        OP_1ADD => n + 1,
+                        OP_1SUB => n - 1,
+                        OP_NEGATE => -n,
+                        OP_ABS => n.abs(),
+                        OP_NOT => (n == 0) as i64,
+                        _ => (n != 0) as i64,
+                    };
+                    stack.push(encode_script_num(result));
+                }
+                OP_ADD | OP_SUB | OP_BOOLAND | OP_BOOLOR | OP_NUMEQUAL | OP_NUMEQUALVERIFY | OP_NUMNOTEQUAL
+                | OP_LESSTHAN | OP_GREATERTHAN | OP_LESSTHANOREQUAL | OP_GREATERTHANOREQUAL | OP_MIN | OP_MAX => {
+                    require_stack(stack, 2)?;
+                    let b = decode_script_num(&pop_stack(stack)?, require_minimal, DEFAULT_MAX_NUM_SIZE)?;
+                    let a = decode_script_num(&pop_stack(stack)?, require_minimal, DEFAULT_MAX_NUM_SIZE)?;
+                    let result = match opcode {
+                        OP_ADD => a + b,
+                        OP_SUB => a - b,
+                        OP_BOOLAND => (a != 0 && b != 0) as i64,
+                        OP_BOOLOR => (a != 0 || b != 0) as i64,
+                        OP_NUMEQUAL | OP_NUMEQUALVERIFY => (a == b) as i64,
+                        OP_NUMNOTEQUAL => (a != b) as i64,
+                        OP_LESSTHAN => (a < b) as i64,
+                        OP_GREATERTHAN => (a > b) as i64,
+                        OP_LESSTHANOREQUAL => (a <= b) as i64,
+                        OP_GREATERTHANOREQUAL => (a >= b) as i64,
+                        OP_MIN => a.min(b),
+                        _ => a.max(b),
+                    };
+                    if opcode == OP_NUMEQUALVERIFY {
+                        if result == 0 {
+                            return Err(ScriptError::NumEqualVerify);
+                        }
+                    } else {
+                        stack.push(encode_script_num(result));
+                    }
+                }
+                OP_WITHIN => {
+                    require_stack(stack, 3)?;
+                    let max = decode_script_num(&pop_stack(stack)?, require_minimal, DEFAULT_MAX_NUM_SIZE)?;
+                    let min = decode_script_num(&pop_stack(stack)?, require_minimal, DEFAULT_MAX_NUM_SIZE)?;
+                    let x = decode_script_num(&pop_stack(stack)?, require_minimal, DEFAULT_MAX_NUM_SIZE)?;
+                    stack.push(encode_bool(min <= x && x < max));
+                }
+
+                OP_RIPEMD160 => {
+                    let item = pop_stack(stack)?;
+                    stack.push(Hasher::hash_bytes_ripemd160(&item).to_vec());
+                }
+                OP_SHA256 => {
+                    let item = pop_stack(stack)?;
+                    stack.push(Hasher::hash_bytes_sha256(&item).to_vec());
+                }
+                OP_HASH160 => {
+                    let item = pop_stack(stack)?;
+                    stack.push(Hasher::bitcoin_hash160(&item).to_vec());
+                }
+                OP_HASH256 => {
+                    let item = pop_stack(stack)?;
+                    stack.push(Hasher::bitcoin_hash256(&item).to_vec());
+                }
+                OP_SHA1 => {
+                    let item = pop_stack(stack)?;
+                    stack.push(Hasher::hash_bytes_sha1(&item).to_vec());
+                }
+                OP_CODESEPARATOR => code_separator_pos = pc,
+
+                OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                    require_stack(stack, 2)?;
+                    let public_key = pop_stack(stack)?;
+                    let signature = pop_stack(stack)?;
+                    // a signature can't sign itself
+                    let script_code = find_and_delete(&script[code_separator_pos..], &encode_binary_stack_item(&signature));
+                    check_signature_encoding(&signature, flags)?;
+                    check_public_key_encoding(&public_key, flags)?;
+                    let success = checker.check_signature(&signature, &public_key, &script_code);
+                    if !success && (flags & SCRIPT_VERIFY_NULLFAIL) != 0 && !signature.is_empty() {
+                        return Err(ScriptError::SigNullFail);
+                    }
+                    if opcode == OP_CHECKSIGVERIFY {
+                        if !success {
+                            return Err(ScriptError::CheckSigVerify);
+                        }
+                    } else {
+                        stack.push(encode_bool(success));
+                    }
+                }
+                OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                    // stack: <dummy> <sig 1> .. <sig m> <m> <key 1> .. <key n> <n>
+                    let mut i = 1;
+                    let key_count = decode_script_num(stack_top(stack, i)?, require_minimal, DEFAULT_MAX_NUM_SIZE)?;
+                    if key_count < 0 || key_count as usize > MAX_PUBKEYS_PER_MULTISIG {
+                        return Err(ScriptError::PubKeyCount);
+                    }
+                    let key_count = key_count as usize;
+                    op_count += key_count;
+                    if op_count > MAX_OPS_PER_SCRIPT {
+                        return Err(ScriptError::OpCount);
+                    }
+                    i += 1;
+                    let mut key_depth = i;
+                    i += key_count;
+                    let sig_count = decode_script_num(stack_top(stack, i)?, require_minimal, DEFAULT_MAX_NUM_SIZE)?;
+                    if sig_count < 0 || sig_count as usize > key_count {
+                        return Err(ScriptError::SigCount);
+                    }
+                    let sig_count = sig_count as usize;
+                    i += 1;
+                    let first_sig_depth = i;
+                    let mut sig_depth = i;
+                    i += sig_count;
+                    // the dummy element consumed by the off-by-one bug
+                    require_stack(stack, i)?;
+
+                    let mut script_code = script[code_separator_pos..].to_vec();
+                    for k in 0..sig_count {
+                        let signature = stack_top(stack, first_sig_depth + k)?;
+                        script_code = find_and_delete(&script_code, &encode_binary_stack_item(signature));
+                    }
+
+                    let mut success = true;
+                    let mut keys_left = key_count;
+                    let mut sigs_left = sig_count;
+                    while success && sigs_left > 0 {
+                        let signature = stack_top(stack, sig_depth)?;
+                        let public_key = stack_top(stack, key_depth)?;
+                        check_signature_encoding(signature, flags)?;
+                        check_public_key_encoding(public_key, flags)?;
+                        if checker.check_signature(signature, public_key, &script_code) {
+                            sig_depth += 1;
+                            sigs_left -= 1;
+                        }
+                        key_depth += 1;
+                        keys_left -= 1;
+                        // signatures must be in the same order as their keys
+                        if sigs_left > keys_left {
+                            success = false;
+                        }
+                    }
+
+                    if !success && (flags & SCRIPT_VERIFY_NULLFAIL) != 0 {
+                        for k in 0..sig_count {
+                            if !stack_top(stack, first_sig_depth + k)?.is_empty() {
+                                return Err(ScriptError::SigNullFail);
+                            }
+                        }
+                    }
+                    stack.truncate(stack.len() - (i - 1));
+                    let dummy = pop_stack(stack)?;
+                    if (flags & SCRIPT_VERIFY_NULLDUMMY) != 0 && !dummy.is_empty() {
+                        return Err(ScriptError::SigNullDummy);
+                    }
+                    if opcode == OP_CHECKMULTISIGVERIFY {
+                        if !success {
+                            return Err(ScriptError::CheckMultiSigVerify);
+                        }
+                    } else {
+                        stack.push(encode_bool(success));
+                    }
+                }
+                _ => return Err(ScriptError::BadOpcode(opcode)),
+            }
+        }
+
+        if stack.len() + alt_stack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize);
+        }
+    }
+
+    if !exec_stack.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
+    }
+    Ok(())
+}
+
+// evaluates scriptSig then scriptPubKey, and the serialized redeem script for P2SH outputs
+pub fn verify_script<Hasher: DogeHashProvider, Checker: SignatureChecker>(
+    script_sig: &[u8],
+    script_pubkey: &[u8],
+    flags: u32,
+    checker: &Checker,
+) -> Result<(), ScriptError> {
+    if (flags & SCRIPT_VERIFY_SIGPUSHONLY) != 0 && !is_push_only(script_sig) {
+        return Err(ScriptError::SigPushOnly);
+    }
+    let mut stack = Vec::new();
+    eval_script::<Hasher, Checker>(&mut stack, script_sig, flags, checker)?;
+    let stack_after_script_sig = stack.clone();
+    eval_script::<Hasher, Checker>(&mut stack, script_pubkey, flags, checker)?;
+    if !stack.last().is_some_and(|top| cast_to_bool(top)) {
+        return Err(ScriptError::EvalFalse);
+    }
+
+    if (flags & SCRIPT_VERIFY_P2SH) != 0 && is_p2sh_script_pubkey(script_pubkey) {
+        if !is_push_only(script_sig) {
+            return Err(ScriptError::SigPushOnly);
+        }
+        stack = stack_after_script_sig;
+        let redeem_script = pop_stack(&mut stack)?;
+        eval_script::<Hasher, Checker>(&mut stack, &redeem_script, flags, checker)?;
+        if !stack.last().is_some_and(|top| cast_to_bool(top)) {
+            return Err(ScriptError::EvalFalse);
+        }
+    }
+
+    if (flags & SCRIPT_VERIFY_CLEANSTACK) != 0 && stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    Ok(())
+}
+
+// checks that transaction.inputs[input_index].script satisfies the output it spends
+pub fn verify_transaction_input<Hasher: DogeHashProvider>(
+    transaction: &BTCTransaction,
+    input_index: usize,
+    spent_output_script: &[u8],
+    flags: u32,
+) -> Result<(), ScriptError> {
+    let input = transaction
+        .inputs
+        .get(input_index)
+        .ok_or(ScriptError::InputIndexOutOfRange(input_index))?;
+    let checker = TransactionSignatureChecker::<Hasher>::new(transaction, input_index);
+    verify_script::<Hasher, _>(&input.script, spent_output_script, flags, &checker)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dogecoin::{
+            address::{AddressToBTCScript, BTCAddress160},
+            hash::CommonDogeHashProvider,
+            sighash::SIGHASH_ALL,
+            transaction::{BTCTransactionInput, BTCTransactionOutput},
+        },
+        secp256k1::signer::{SimpleSinglePublicKeySigner, SimpleSingleSigner},
+        wormhole::script_template::construct_wormhole_vaa_script,
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+
+    struct NoSignatureChecker;
+    impl SignatureChecker for NoSignatureChecker {
+        fn check_signature(&self, _signature: &[u8], _public_key: &[u8], _script_code: &[u8]) -> bool {
+            false
+        }
+        fn check_lock_time(&self, _lock_time: i64) -> bool {
+            false
+        }
+    }
+
+    // accepts lock times up to a block height
+    struct HeightLockTimeChecker(i64);
+    impl SignatureChecker for HeightLockTimeChecker {
+        fn check_signature(&self, _signature: &[u8], _public_key: &[u8], _script_code: &[u8]) -> bool {
+            false
+        }
+        fn check_lock_time(&self, lock_time: i64) -> bool {
+            lock_time <= self.0
+        }
+    }
+
+    fn eval(script: &[u8]) -> Result<Vec<Vec<u8>>, ScriptError> {
+        let mut stack = Vec::new();
+        eval_script::<Hasher, _>(&mut stack, script, STANDARD_SCRIPT_VERIFY_FLAGS, &NoSignatureChecker)?;
+        Ok(stack)
+    }
+
+    #[test]
+    fn test_eval_script_basic_opcodes() {
+        // 2 3 ADD 5 EQUAL
+        assert_eq!(eval(&[0x52, 0x53, OP_ADD, 0x55, OP_EQUAL]).unwrap(), vec![vec![1]]);
+        // 1 IF 7 ELSE 8 ENDIF
+        assert_eq!(eval(&[OP_1, OP_IF, 0x57, OP_ELSE, 0x58, OP_ENDIF]).unwrap(), vec![vec![7]]);
+        // 0x81 is -1 and must be pushed with OP_1NEGATE
+        assert_eq!(eval(&[0x01, 0x81]), Err(ScriptError::MinimalData));
+        assert_eq!(eval(&[OP_1, OP_IF]), Err(ScriptError::UnbalancedConditional));
+        assert_eq!(eval(&[OP_0, OP_IF, OP_CAT, OP_ENDIF]), Err(ScriptError::DisabledOpcode(OP_CAT)));
+        assert_eq!(eval(&[OP_DROP]), Err(ScriptError::InvalidStackOperation));
+        assert_eq!(eval(&[OP_NOP4]), Err(ScriptError::DiscourageUpgradableNops));
+        assert_eq!(encode_script_num(-255), vec![0xff, 0x80]);
+        assert_eq!(decode_script_num(&[0xff, 0x80], true, 4), Ok(-255));
+        // "abc" SHA1
+        assert_eq!(
+            eval(&[0x03, b'a', b'b', b'c', OP_SHA1]).unwrap(),
+            vec![hex::decode("a9993e364706816aba3e25717850c26c9cd0d89d").unwrap()]
+        );
+        assert_eq!(hex::encode(Hasher::hash_bytes_sha1(&[])), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex::encode(Hasher::hash_bytes_sha1(&[b'a'; 1000])),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+        );
+    }
+
+    #[test]
+    fn test_eval_checklocktimeverify() {
+        let eval_at_height = |script: &[u8], flags: u32| {
+            let mut stack = Vec::new();
+            eval_script::<Hasher, _>(&mut stack, script, flags, &HeightLockTimeChecker(100)).map(|_| stack)
+        };
+        // <lock time> CHECKLOCKTIMEVERIFY DROP 1
+        let script = |lock_time: i64| {
+            [encode_binary_stack_item(&encode_script_num(lock_time)), vec![OP_CHECKLOCKTIMEVERIFY, OP_DROP, OP_1]].concat()
+        };
+        assert_eq!(eval_at_height(&script(100), STANDARD_SCRIPT_VERIFY_FLAGS).unwrap(), vec![vec![1]]);
+        assert_eq!(eval_at_height(&script(101), STANDARD_SCRIPT_VERIFY_FLAGS), Err(ScriptError::UnsatisfiedLockTime));
+        assert_eq!(eval_at_height(&[OP_1NEGATE, OP_CHECKLOCKTIMEVERIFY], STANDARD_SCRIPT_VERIFY_FLAGS), Err(ScriptError::NegativeLockTime));
+        assert_eq!(eval_at_height(&[OP_CHECKLOCKTIMEVERIFY], STANDARD_SCRIPT_VERIFY_FLAGS), Err(ScriptError::InvalidStackOperation));
+        // before the soft fork it is a NOP, discouraged by policy
+        assert_eq!(eval_at_height(&script(101), SCRIPT_VERIFY_NONE).unwrap(), vec![vec![1]]);
+        assert_eq!(
+            eval_at_height(&script(101), SCRIPT_VERIFY_DISCOURAGE_UPGRADABLE_NOPS),
+            Err(ScriptError::DiscourageUpgradableNops)
+        );
+
+        // the transaction checker compares against nLockTime and requires a non final input
+        let mut transaction = BTCTransaction::from_io(
+            vec![BTCTransactionInput { sequence: 0xfffffffe, ..Default::default() }],
+            vec![],
+        );
+        transaction.locktime = 100;
+        let checker = TransactionSignatureChecker::<Hasher>::new(&transaction, 0);
+        assert!(checker.check_lock_time(100) && !checker.check_lock_time(101));
+        assert!(!checker.check_lock_time(LOCKTIME_THRESHOLD as i64));
+        transaction.inputs[0].sequence = 0xffffffff;
+        assert!(!TransactionSignatureChecker::<Hasher>::new(&transaction, 0).check_lock_time(100));
+    }
+
+    #[test]
+    fn test_verify_checkmultisig_nulldummy_and_cleanstack() {
+        let signers = [7u8, 8, 9].map(|key| {
+            SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([key; 32]).unwrap()
+        });
+        // bare 2-of-3: OP_2 <key 1> <key 2> <key 3> OP_3 OP_CHECKMULTISIG
+        let script_pubkey = [
+            vec![0x52],
+            signers.iter().flat_map(|signer| encode_binary_stack_item(&signer.get_compressed_public_key().0)).collect(),
+            vec![0x53, OP_CHECKMULTISIG],
+        ]
+        .concat();
+        let mut transaction = BTCTransaction::from_io(
+            vec![BTCTransactionInput { hash: [3u8; 32], ..Default::default() }],
+            vec![BTCTransactionOutput { value: 1000, script: BTCAddress160::new_p2pkh([0xbb; 20]).to_btc_script() }],
+        );
+        let sighash = SigHashPreimage::get_sighash_for_transaction_pre_segwit::<Hasher>(&transaction, 0, &script_pubkey, SIGHASH_ALL);
+        let signature_pushes = signers
+            .iter()
+            .map(|signer| signer.sign_message(sighash).unwrap().to_btc_signature_push())
+            .collect::<Vec<Vec<u8>>>();
+        let mut verify = |script_sig: Vec<u8>| {
+            transaction.inputs[0].script = script_sig;
+            verify_transaction_input::<Hasher>(&transaction, 0, &script_pubkey, STANDARD_SCRIPT_VERIFY_FLAGS)
+        };
+
+        verify([vec![OP_0], signature_pushes[0].clone(), signature_pushes[2].clone()].concat()).unwrap();
+        // signatures out of key order fail, NULLFAIL rejects the non empty signatures
+        assert_eq!(
+            verify([vec![OP_0], signature_pushes[2].clone(), signature_pushes[0].clone()].concat()),
+            Err(ScriptError::SigNullFail)
+        );
+        // the dummy element must be empty
+        assert_eq!(
+            verify([vec![OP_1], signature_pushes[0].clone(), signature_pushes[1].clone()].concat()),
+            Err(ScriptError::SigNullDummy)
+        );
+        // an extra item below the dummy is left on the stack
+        assert_eq!(
+            verify([vec![OP_1, OP_0], signature_pushes[0].clone(), signature_pushes[1].clone()].concat()),
+            Err(ScriptError::CleanStack)
+        );
+        transaction.inputs[0].script =
+            [vec![OP_1, OP_0], signature_pushes[0].clone(), signature_pushes[1].clone()].concat();
+        verify_transaction_input::<Hasher>(&transaction, 0, &script_pubkey, SCRIPT_VERIFY_P2SH).unwrap();
+    }
+
+    #[test]
+    fn test_verify_p2sh_vaa_spend() {
+        let signer = SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([7u8; 32]).unwrap();
+        let public_key = signer.get_compressed_public_key().0;
+        let redeem_script = construct_wormhole_vaa_script(1, &[1u8; 32], &[2u8; 32], &Hasher::bitcoin_hash160(&public_key));
+        let script_pubkey = BTCAddress160::new_p2sh(Hasher::bitcoin_hash160(&redeem_script)).to_btc_script();
+
+        let mut transaction = BTCTransaction::from_io(
+            vec![BTCTransactionInput {
+                hash: [3u8; 32],
+                index: 0,
+                script: vec![],
+                sequence: 0xffffffff,
+            }],
+            vec![BTCTransactionOutput {
+                value: 1000,
+                script: BTCAddress160::new_p2pkh([0xbb; 20]).to_btc_script(),
+            }],
+        );
+        let sighash = SigHashPreimage::get_sighash_for_transaction_pre_segwit::<Hasher>(&transaction, 0, &redeem_script, SIGHASH_ALL);
+        let signature = signer.sign_message(sighash).unwrap();
+        transaction.inputs[0].script = [signature.to_btc_script(), encode_binary_stack_item(&redeem_script)].concat();
+        verify_transaction_input::<Hasher>(&transaction, 0, &script_pubkey, STANDARD_SCRIPT_VERIFY_FLAGS).unwrap();
+
+        // the signature commits to the outputs
+        transaction.outputs[0].value += 1;
+        assert_eq!(
+            verify_transaction_input::<Hasher>(&transaction, 0, &script_pubkey, STANDARD_SCRIPT_VERIFY_FLAGS),
+            Err(ScriptError::SigNullFail)
+        );
+        assert_eq!(
+            verify_transaction_input::<Hasher>(&transaction, 0, &script_pubkey, SCRIPT_VERIFY_P2SH),
+            Err(ScriptError::EvalFalse)
+        );
+    }
+}
@@ -0,0 +1,3 @@
+pub mod opcodes;
+pub mod interpreter;
+pub mod num;
@@ -0,0 +1,101 @@
+use crate::dogecoin::script::{
+    interpreter::ScriptError,
+    opcodes::{OP_0, OP_1, OP_1NEGATE, OP_16},
+};
+
+// CScriptNum: minimal little endian sign-magnitude integers as used on the script stack
+
+// minimal little endian sign-magnitude encoding used by script numbers
+pub fn encode_script_num(value: i64) -> Vec<u8> {
+    if value == 0 {
+        return vec![];
+    }
+    let negative = value < 0;
+    let mut absolute = value.unsigned_abs();
+    let mut result = Vec::new();
+    while absolute > 0 {
+        result.push((absolute & 0xff) as u8);
+        absolute >>= 8;
+    }
+    let last = result.len() - 1;
+    if result[last] & 0x80 != 0 {
+        result.push(if negative { 0x80 } else { 0 });
+    } else if negative {
+        result[last] |= 0x80;
+    }
+    result
+}
+
+pub fn decode_script_num(bytes: &[u8], require_minimal: bool, max_size: usize) -> Result<i64, ScriptError> {
+    if bytes.len() > max_size {
+        return Err(ScriptError::InvalidNumber);
+    }
+    let Some(last) = bytes.last() else {
+        return Ok(0);
+    };
+    // the most significant byte may only be 0x00/0x80 if it is needed for the sign bit
+    if require_minimal && (last & 0x7f) == 0 && (bytes.len() <= 1 || (bytes[bytes.len() - 2] & 0x80) == 0) {
+        return Err(ScriptError::InvalidNumber);
+    }
+    let mut result: i64 = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        result |= (*byte as i64) << (8 * i);
+    }
+    if last & 0x80 != 0 {
+        let sign_bit = 0x80i64 << (8 * (bytes.len() - 1));
+        return Ok(-(result & !sign_bit));
+    }
+    Ok(result)
+}
+
+// the push that satisfies MINIMALDATA for value: OP_0, OP_1NEGATE, OP_1..OP_16 or <len> <encode_script_num(value)>
+pub fn push_script_num(value: i64) -> Vec<u8> {
+    match value {
+        0 => vec![OP_0],
+        -1 => vec![OP_1NEGATE],
+        1..=16 => vec![OP_1 - 1 + value as u8],
+        _ => {
+            let bytes = encode_script_num(value);
+            [vec![bytes.len() as u8], bytes].concat()
+        }
+    }
+}
+
+// the inverse of push_script_num, returns the value and the size of the push.
+// non-minimal pushes are rejected so every value has exactly one encoding
+pub fn read_script_num_push(script: &[u8], max_size: usize) -> Option<(i64, usize)> {
+    let (value, size) = match *script.first()? {
+        OP_0 => (0, 1),
+        OP_1NEGATE => (-1, 1),
+        opcode @ OP_1..=OP_16 => ((opcode - OP_1 + 1) as i64, 1),
+        length @ 0x01..=0x08 if length as usize <= max_size => {
+            let bytes = script.get(1..1 + length as usize)?;
+            (decode_script_num(bytes, true, max_size).ok()?, 1 + length as usize)
+        }
+        _ => return None,
+    };
+    if push_script_num(value).len() != size {
+        return None;
+    }
+    Some((value, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_num_push_round_trip() {
+        for value in [0i64, -1, 1, 16, 17, 127, 128, 255, 256, 0x7fff, 0x8000, 0xffff, -255, 500_000_000] {
+            let push = push_script_num(value);
+            assert_eq!(read_script_num_push(&push, 5), Some((value, push.len())));
+        }
+        assert_eq!(push_script_num(128), vec![0x02, 0x80, 0x00]);
+        assert_eq!(push_script_num(0xffff), vec![0x03, 0xff, 0xff, 0x00]);
+        // OP_PUSH1 0x05 instead of OP_5, 0x81 instead of OP_1NEGATE and a padded zero byte
+        assert_eq!(read_script_num_push(&[0x01, 0x05], 5), None);
+        assert_eq!(read_script_num_push(&[0x01, 0x81], 5), None);
+        assert_eq!(read_script_num_push(&[0x02, 0x7f, 0x00], 5), None);
+        assert_eq!(read_script_num_push(&push_script_num(1 << 40), 5), None);
+    }
+}
@@ -0,0 +1,518 @@
+use crate::{
+    dogecoin::{
+        relay_policy::{DOGE_DEFAULT_RELAY_POLICY, DogeRelayPolicy},
+        transaction_layout::BTCTransactionLayout,
+    },
+    psy_doge_link::data::{BTCUTXO, PartialBTCUTXO},
+};
+
+// <73 byte signature push> <34 byte compressed public key push>
+pub const P2PKH_SCRIPT_SIG_MAX_SIZE: usize = 107;
+// OP_DUP OP_HASH160 PUSH20 <hash> OP_EQUALVERIFY OP_CHECKSIG
+pub const P2PKH_SCRIPT_SIZE: usize = 25;
+// OP_HASH160 PUSH20 <hash> OP_EQUAL
+pub const P2SH_SCRIPT_SIZE: usize = 23;
+
+// A utxo that can be fed to a coin selector
+pub trait CoinSelectionUTXO: Clone {
+    fn get_value(&self) -> u64;
+    // confirmation height, None if unconfirmed or unknown
+    fn get_block_height(&self) -> Option<u64>;
+}
+
+impl CoinSelectionUTXO for BTCUTXO {
+    fn get_value(&self) -> u64 {
+        self.value
+    }
+    fn get_block_height(&self) -> Option<u64> {
+        if self.status.confirmed { Some(self.status.block_height) } else { None }
+    }
+}
+
+impl CoinSelectionUTXO for PartialBTCUTXO {
+    fn get_value(&self) -> u64 {
+        self.value
+    }
+    fn get_block_height(&self) -> Option<u64> {
+        None
+    }
+}
+
+// what a selector sees of a utxo, in the order of the list passed to select_coins
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct CoinSelectionCandidate {
+    pub value: u64,
+    pub block_height: Option<u64>,
+}
+
+// The transaction being funded: every input carries a scriptSig of at most input_script_size bytes
+// and change (if any) is paid to a script of change_script_size bytes after the destination outputs
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct CoinSelectionParams {
+    pub target_amount: u64,
+    pub input_script_size: usize,
+    pub output_script_sizes: Vec<usize>,
+    pub change_script_size: usize,
+    // koinu per byte
+    pub fee_rate: u64,
+    pub relay_policy: DogeRelayPolicy,
+    pub max_inputs: usize,
+    // the soft dust fee owed for the destination outputs, paid on top of the size based fee
+    pub dust_fee: u64,
+}
+
+impl CoinSelectionParams {
+    pub fn new(
+        target_amount: u64,
+        input_script_size: usize,
+        output_script_sizes: Vec<usize>,
+        change_script_size: usize,
+        fee_rate: u64,
+    ) -> Self {
+        Self {
+            target_amount,
+            input_script_size,
+            output_script_sizes,
+            change_script_size,
+            fee_rate,
+            relay_policy: DOGE_DEFAULT_RELAY_POLICY,
+            max_inputs: 100,
+            dust_fee: 0,
+        }
+    }
+    pub fn with_relay_policy(mut self, relay_policy: DogeRelayPolicy) -> Self {
+        self.relay_policy = relay_policy;
+        self
+    }
+    pub fn with_max_inputs(mut self, max_inputs: usize) -> Self {
+        self.max_inputs = max_inputs.max(1);
+        self
+    }
+    pub fn with_dust_fee(mut self, dust_fee: u64) -> Self {
+        self.dust_fee = dust_fee;
+        self
+    }
+    // the fee rate actually paid, raised so that every size meets the relay fee floor
+    pub fn get_effective_fee_rate(&self) -> u64 {
+        self.fee_rate.max(self.relay_policy.min_relay_fee_per_kb.div_ceil(1000))
+    }
+    pub fn get_size(&self, input_count: usize, with_change: bool) -> usize {
+        let mut output_script_sizes = self.output_script_sizes.clone();
+        if with_change {
+            output_script_sizes.push(self.change_script_size);
+        }
+        BTCTransactionLayout::new(vec![self.input_script_size; input_count], output_script_sizes).get_total_size()
+    }
+    pub fn get_fee(&self, input_count: usize, with_change: bool) -> u64 {
+        (self.get_size(input_count, with_change) as u64)
+            .saturating_mul(self.get_effective_fee_rate())
+            .saturating_add(self.dust_fee)
+    }
+    // the value a utxo adds once the fee for spending it is paid
+    pub fn get_effective_value(&self, value: u64) -> u64 {
+        value.saturating_sub(self.get_fee(1, false).saturating_sub(self.get_fee(0, false)))
+    }
+    // what adding a change output costs, selections overshooting by less are better off without change
+    pub fn get_cost_of_change(&self) -> u64 {
+        self.get_fee(1, true)
+            .saturating_sub(self.get_fee(1, false))
+            .saturating_add(self.relay_policy.get_min_output_value())
+    }
+    fn is_standard_size(&self, input_count: usize, with_change: bool) -> bool {
+        self.get_size(input_count, with_change) <= self.relay_policy.max_standard_tx_size
+    }
+    // the most inputs a transaction with change can spend within max_inputs and the standard size limit
+    pub fn get_max_input_count(&self) -> usize {
+        let (mut low, mut high) = (0, self.max_inputs);
+        while low < high {
+            let middle = (low + high).div_ceil(2);
+            if self.is_standard_size(middle, true) {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+        low
+    }
+    // returns (fee, change_amount) for input_count inputs worth total_input_amount, None if they do not cover the target
+    // or the transaction would be too large to relay. change that would be dust or soft dust is left to the fee
+    pub fn get_fee_and_change(&self, input_count: usize, total_input_amount: u64) -> Option<(u64, u64)> {
+        if input_count == 0 || input_count > self.max_inputs {
+            return None;
+        }
+        let available = total_input_amount.checked_sub(self.target_amount)?;
+        let fee_with_change = self.get_fee(input_count, true);
+        if available >= fee_with_change.saturating_add(self.relay_policy.get_min_output_value()) {
+            // never burn spendable change to squeeze under the size limit
+            return self
+                .is_standard_size(input_count, true)
+                .then_some((fee_with_change, available - fee_with_change));
+        }
+        if available >= self.get_fee(input_count, false) && self.is_standard_size(input_count, false) {
+            return Some((available, 0));
+        }
+        None
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct CoinSelection<T> {
+    pub selected: Vec<T>,
+    pub total_input_amount: u64,
+    pub fee: u64,
+    // 0 if the transaction has no change output
+    pub change_amount: u64,
+}
+
+impl<T> CoinSelection<T> {
+    pub fn has_change(&self) -> bool {
+        self.change_amount != 0
+    }
+}
+
+pub trait CoinSelector {
+    // positions in candidates of the utxos to spend, None if no selection pays for params
+    fn select(&self, candidates: &[CoinSelectionCandidate], params: &CoinSelectionParams) -> Option<Vec<usize>>;
+}
+
+impl<S: CoinSelector + ?Sized> CoinSelector for &S {
+    fn select(&self, candidates: &[CoinSelectionCandidate], params: &CoinSelectionParams) -> Option<Vec<usize>> {
+        (**self).select(candidates, params)
+    }
+}
+
+fn get_total_value(candidates: &[CoinSelectionCandidate], positions: &[usize]) -> u64 {
+    positions
+        .iter()
+        .fold(0u64, |sum, position| sum.saturating_add(candidates[*position].value))
+}
+
+// spends candidates in the given order until they pay for params
+fn select_in_order(
+    candidates: &[CoinSelectionCandidate],
+    params: &CoinSelectionParams,
+    order: Vec<usize>,
+) -> Option<Vec<usize>> {
+    let mut total = 0u64;
+    for (count, position) in order.iter().enumerate().take(params.max_inputs) {
+        total = total.saturating_add(candidates[*position].value);
+        if params.get_fee_and_change(count + 1, total).is_some() {
+            return Some(order[0..=count].to_vec());
+        }
+    }
+    None
+}
+
+// (position, effective value) pairs
+type CoinSelectionPool = Vec<(usize, u64)>;
+
+// candidates worth spending at the fee rate, largest effective value first
+fn get_positions_by_effective_value(candidates: &[CoinSelectionCandidate], params: &CoinSelectionParams) -> CoinSelectionPool {
+    let mut positions = candidates
+        .iter()
+        .enumerate()
+        .map(|(position, candidate)| (position, params.get_effective_value(candidate.value)))
+        .filter(|(_, effective_value)| *effective_value > 0)
+        .collect::<CoinSelectionPool>();
+    positions.sort_by_key(|(position, effective_value)| (std::cmp::Reverse(*effective_value), *position));
+    positions
+}
+
+// fewest inputs, spends the largest utxos first
+#[derive(PartialEq, Clone, Copy, Debug, Eq, Default)]
+pub struct LargestFirstCoinSelector;
+
+impl CoinSelector for LargestFirstCoinSelector {
+    fn select(&self, candidates: &[CoinSelectionCandidate], params: &CoinSelectionParams) -> Option<Vec<usize>> {
+        let mut order = (0..candidates.len()).collect::<Vec<usize>>();
+        order.sort_by_key(|position| (std::cmp::Reverse(candidates[*position].value), *position));
+        select_in_order(candidates, params, order)
+    }
+}
+
+// spends the oldest confirmations first (unconfirmed utxos last), sweeping many small inputs over time
+#[derive(PartialEq, Clone, Copy, Debug, Eq, Default)]
+pub struct OldestFirstCoinSelector;
+
+impl CoinSelector for OldestFirstCoinSelector {
+    fn select(&self, candidates: &[CoinSelectionCandidate], params: &CoinSelectionParams) -> Option<Vec<usize>> {
+        let mut order = (0..candidates.len()).collect::<Vec<usize>>();
+        order.sort_by_key(|position| (candidates[*position].block_height.unwrap_or(u64::MAX), *position));
+        select_in_order(candidates, params, order)
+    }
+}
+
+/*
+Changeless selection: a depth first search over utxos sorted by effective value for a subset whose
+effective value lands in [target, target + cost of change], with target = target_amount + fee of the
+transaction without inputs. Among the matches the one with the least overshoot is returned.
+*/
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct BranchAndBoundCoinSelector {
+    max_tries: usize,
+}
+
+impl BranchAndBoundCoinSelector {
+    pub fn new() -> Self {
+        Self { max_tries: 100_000 }
+    }
+    pub fn with_max_tries(mut self, max_tries: usize) -> Self {
+        self.max_tries = max_tries;
+        self
+    }
+}
+
+impl Default for BranchAndBoundCoinSelector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct BranchAndBoundSearch<'a> {
+    pool: &'a [(usize, u64)],
+    // remaining[i] = sum of the effective values of pool[i..]
+    remaining: Vec<u64>,
+    target: u64,
+    upper_bound: u64,
+    max_inputs: usize,
+    tries: usize,
+    selected: Vec<usize>,
+    best: Option<(u64, Vec<usize>)>,
+}
+
+impl BranchAndBoundSearch<'_> {
+    fn search(&mut self, depth: usize, sum: u64) {
+        if self.tries == 0 || sum > self.upper_bound || sum.saturating_add(self.remaining[depth]) < self.target {
+            return;
+        }
+        self.tries -= 1;
+        if sum >= self.target {
+            if self.best.as_ref().is_none_or(|(best_sum, _)| sum < *best_sum) {
+                self.best = Some((sum, self.selected.clone()));
+            }
+            return;
+        }
+        if depth == self.pool.len() || self.selected.len() == self.max_inputs {
+            return;
+        }
+        let (position, effective_value) = self.pool[depth];
+        self.selected.push(position);
+        self.search(depth + 1, sum.saturating_add(effective_value));
+        self.selected.pop();
+        // skipping a utxo equal to an excluded predecessor explores the same sums again
+        let mut next = depth + 1;
+        while next < self.pool.len() && self.pool[next].1 == effective_value {
+            next += 1;
+        }
+        self.search(next, sum);
+    }
+}
+
+impl CoinSelector for BranchAndBoundCoinSelector {
+    fn select(&self, candidates: &[CoinSelectionCandidate], params: &CoinSelectionParams) -> Option<Vec<usize>> {
+        let pool = get_positions_by_effective_value(candidates, params);
+        let mut remaining = vec![0u64; pool.len() + 1];
+        for i in (0..pool.len()).rev() {
+            remaining[i] = remaining[i + 1].saturating_add(pool[i].1);
+        }
+        let target = params.target_amount.saturating_add(params.get_fee(0, false));
+        let mut search = BranchAndBoundSearch {
+            pool: &pool,
+            remaining,
+            target,
+            upper_bound: target.saturating_add(params.get_cost_of_change()),
+            max_inputs: params.max_inputs,
+            tries: self.max_tries,
+            selected: Vec::new(),
+            best: None,
+        };
+        search.search(0, 0);
+        let (_, selected) = search.best?;
+        // the linear fee model can be off by the input count varint, only keep exact changeless matches
+        match params.get_fee_and_change(selected.len(), get_total_value(candidates, &selected)) {
+            Some((_, 0)) => Some(selected),
+            _ => None,
+        }
+    }
+}
+
+/*
+Deterministic take on dogecoin core's knapsack solver: the smallest utxo covering the target with
+change on its own, unless a subset of the smaller utxos (collected greedily from each starting point)
+gets closer to it.
+*/
+#[derive(PartialEq, Clone, Copy, Debug, Eq, Default)]
+pub struct KnapsackCoinSelector;
+
+impl CoinSelector for KnapsackCoinSelector {
+    fn select(&self, candidates: &[CoinSelectionCandidate], params: &CoinSelectionParams) -> Option<Vec<usize>> {
+        let pool = get_positions_by_effective_value(candidates, params);
+        let target = params
+            .target_amount
+            .saturating_add(params.get_fee(0, true))
+            .saturating_add(params.relay_policy.get_min_output_value());
+        let (smaller, larger): (CoinSelectionPool, CoinSelectionPool) =
+            pool.iter().partition(|(_, effective_value)| *effective_value < target);
+        let lowest_larger = larger.last().map(|(position, effective_value)| (*effective_value, vec![*position]));
+
+        let mut best_subset: Option<(u64, Vec<usize>)> = None;
+        for start in 0..smaller.len() {
+            let mut sum = 0u64;
+            let mut subset = Vec::new();
+            for (position, effective_value) in smaller[start..].iter() {
+                if sum >= target || subset.len() == params.max_inputs {
+                    break;
+                }
+                sum = sum.saturating_add(*effective_value);
+                subset.push(*position);
+            }
+            if sum >= target && best_subset.as_ref().is_none_or(|(best_sum, _)| sum < *best_sum) {
+                best_subset = Some((sum, subset));
+            }
+        }
+        let best = match (lowest_larger, best_subset) {
+            (Some(larger), Some(subset)) => {
+                if larger.0 <= subset.0 { larger } else { subset }
+            }
+            (Some(best), None) | (None, Some(best)) => best,
+            // not enough for change, everything left may still pay for a changeless transaction
+            (None, None) => (0, smaller.iter().take(params.max_inputs).map(|(position, _)| *position).collect()),
+        };
+        let selected = best.1;
+        params
+            .get_fee_and_change(selected.len(), get_total_value(candidates, &selected))
+            .map(|_| selected)
+    }
+}
+
+// changeless branch and bound where possible, falling back to another selector
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct FallbackCoinSelector<Primary: CoinSelector, Fallback: CoinSelector> {
+    pub primary: Primary,
+    pub fallback: Fallback,
+}
+
+impl<Primary: CoinSelector, Fallback: CoinSelector> FallbackCoinSelector<Primary, Fallback> {
+    pub fn new(primary: Primary, fallback: Fallback) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+impl Default for FallbackCoinSelector<BranchAndBoundCoinSelector, KnapsackCoinSelector> {
+    fn default() -> Self {
+        Self::new(BranchAndBoundCoinSelector::new(), KnapsackCoinSelector)
+    }
+}
+
+impl<Primary: CoinSelector, Fallback: CoinSelector> CoinSelector for FallbackCoinSelector<Primary, Fallback> {
+    fn select(&self, candidates: &[CoinSelectionCandidate], params: &CoinSelectionParams) -> Option<Vec<usize>> {
+        self.primary
+            .select(candidates, params)
+            .or_else(|| self.fallback.select(candidates, params))
+    }
+}
+
+// runs selector over utxos and computes the fee and change of its selection
+pub fn select_coins<T: CoinSelectionUTXO, S: CoinSelector + ?Sized>(
+    selector: &S,
+    utxos: &[T],
+    params: &CoinSelectionParams,
+) -> anyhow::Result<CoinSelection<T>> {
+    let candidates = utxos
+        .iter()
+        .map(|utxo| CoinSelectionCandidate {
+            value: utxo.get_value(),
+            block_height: utxo.get_block_height(),
+        })
+        .collect::<Vec<CoinSelectionCandidate>>();
+    let insufficient_funds = || {
+        anyhow::anyhow!(
+            "insufficient funds: {} utxos worth {} koinu do not cover {} koinu and fees",
+            utxos.len(),
+            candidates.iter().fold(0u64, |sum, candidate| sum.saturating_add(candidate.value)),
+            params.target_amount
+        )
+    };
+    let positions = selector.select(&candidates, params).ok_or_else(insufficient_funds)?;
+    let mut unique_positions = positions.clone();
+    unique_positions.sort();
+    unique_positions.dedup();
+    if unique_positions.len() != positions.len() || positions.iter().any(|position| *position >= candidates.len()) {
+        anyhow::bail!("coin selector returned an invalid selection");
+    }
+    let total_input_amount = get_total_value(&candidates, &positions);
+    let (fee, change_amount) = params
+        .get_fee_and_change(positions.len(), total_input_amount)
+        .ok_or_else(insufficient_funds)?;
+    Ok(CoinSelection {
+        selected: positions.iter().map(|position| utxos[*position].clone()).collect(),
+        total_input_amount,
+        fee,
+        change_amount,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_utxos(values: &[u64]) -> Vec<PartialBTCUTXO> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| PartialBTCUTXO {
+                txid: [i as u8; 32],
+                value: *value,
+                vout: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_coin_selectors() {
+        let params = CoinSelectionParams::new(10_000_000, P2PKH_SCRIPT_SIG_MAX_SIZE, vec![P2PKH_SCRIPT_SIZE], P2SH_SCRIPT_SIZE, 1000);
+        let input_fee = params.get_fee(1, false) - params.get_fee(0, false);
+        let changeless_fee = params.get_fee(2, false);
+        // 3_000_000 + 7_000_000 + fee pays the target exactly without change
+        let utxos = get_utxos(&[50_000_000, 3_000_000, 7_000_000 + changeless_fee, 1_000_000, 2_000_000 + input_fee]);
+
+        let selection = select_coins(&LargestFirstCoinSelector, &utxos, &params).unwrap();
+        assert_eq!(selection.selected, vec![utxos[0]]);
+        assert!(selection.has_change());
+        assert_eq!(selection.total_input_amount, 10_000_000 + selection.fee + selection.change_amount);
+
+        let selection = select_coins(&BranchAndBoundCoinSelector::new(), &utxos, &params).unwrap();
+        assert_eq!(selection.selected, vec![utxos[2], utxos[1]]);
+        assert_eq!((selection.fee, selection.change_amount), (changeless_fee, 0));
+        let selection = select_coins(&FallbackCoinSelector::default(), &utxos, &params).unwrap();
+        assert!(!selection.has_change());
+
+        // without an exact match the fallback still funds the transaction, with change
+        let params = CoinSelectionParams { target_amount: 20_000_000, ..params };
+        assert!(BranchAndBoundCoinSelector::new().select(&[], &params).is_none());
+        let selection = select_coins(&FallbackCoinSelector::default(), &utxos, &params).unwrap();
+        assert_eq!(selection.selected, vec![utxos[0]]);
+        assert!(selection.has_change());
+
+        // the oldest confirmations go first, unconfirmed utxos last
+        let mut utxos = utxos
+            .iter()
+            .zip([30u64, 20, 10, 40, 0])
+            .map(|(utxo, height)| BTCUTXO {
+                status: crate::psy_doge_link::data::BTCUTXOStatus {
+                    block_hash: [0u8; 32],
+                    block_height: height,
+                    block_time: 0,
+                    confirmed: height != 0,
+                },
+                txid: utxo.txid,
+                value: utxo.value,
+                vout: utxo.vout,
+            })
+            .collect::<Vec<BTCUTXO>>();
+        let selection = select_coins(&OldestFirstCoinSelector, &utxos, &params).unwrap();
+        assert_eq!(selection.selected, vec![utxos[2], utxos[1], utxos[0]]);
+        utxos.remove(0);
+        assert!(select_coins(&OldestFirstCoinSelector, &utxos, &params).is_err());
+    }
+}
@@ -2,7 +2,7 @@
 use serde::{Deserialize, Deserializer, Serialize};
 use serde::de::Error as SerdeError;
 use std::collections::HashMap;
-use crate::dogecoin::{hash::QHash256, transaction::BTCTransaction};
+use crate::dogecoin::{hash::QHash256, transaction::{BTCTransaction, BTCTransactionInputWithoutScript}};
 use crate::psy_doge_link::bytes::U8BytesFixed;
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
@@ -61,6 +61,15 @@ impl From<BTCUTXO> for PartialBTCUTXO {
     }
 }
 
+impl From<&BTCUTXO> for BTCTransactionInputWithoutScript {
+    fn from(utxo: &BTCUTXO) -> Self {
+        // electrs reports txids, inputs reference the transaction hash
+        let mut hash = utxo.txid;
+        hash.reverse();
+        BTCTransactionInputWithoutScript::new_simple(hash, utxo.vout)
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
 pub struct BTCOutpoint {
     pub txid: QHash256,
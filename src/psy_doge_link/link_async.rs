@@ -156,6 +156,11 @@ impl<N: DogeNetworkConfig + Send + Sync> PsyBitcoinAPIAsync for DogeLinkElectrsR
     async fn send_transaction(&self, tx: &BTCTransaction) -> anyhow::Result<QHash256> {
         self.submit_raw_transaction(&tx.to_bytes()).await
     }
+
+    async fn get_block_tip_height(&self) -> anyhow::Result<u64> {
+        let height = self.get_text("/blocks/tip/height").await?;
+        Ok(height.trim().parse::<u64>()?)
+    }
 }
 
 #[async_trait]
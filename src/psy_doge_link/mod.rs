@@ -2,4 +2,5 @@ pub mod traits;
 pub mod data;
 pub mod link_sync;
 pub mod link_async;
-pub mod bytes;
\ No newline at end of file
+pub mod bytes;
+pub mod coin_selection;
\ No newline at end of file
@@ -41,4 +41,8 @@ pub trait PsyBitcoinAPIAsync {
     async fn estimate_fee_rate(&self, n_blocks: u32) -> anyhow::Result<u64>;
     async fn get_transaction(&self, txid: QHash256) -> anyhow::Result<BTCTransaction>;
     async fn send_transaction(&self, tx: &BTCTransaction) -> anyhow::Result<QHash256>;
+    // only needed by the deposit watcher, APIs without a tip height endpoint can keep the default
+    async fn get_block_tip_height(&self) -> anyhow::Result<u64> {
+        anyhow::bail!("this bitcoin api does not report the block tip height")
+    }
 }
\ No newline at end of file
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+
+use crate::{
+    dogecoin::hash::{Keccak256HashProvider, QHash256},
+    wormhole::vaa::{WORMHOLE_VAA_SIGNATURE_SIZE, WormholeVAA},
+};
+
+// how long the previous guardian set keeps attesting VAAs after an upgrade, as in wormhole core
+pub const WORMHOLE_GUARDIAN_SET_EXPIRATION_GRACE_PERIOD: u32 = 24 * 60 * 60;
+
+// guardians are identified by their ethereum-style address: keccak256(uncompressed_pubkey[1..])[12..]
+pub type WormholeGuardianAddress = [u8; 20];
+
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct WormholeGuardianSet {
+    pub index: u32,
+    pub keys: Vec<WormholeGuardianAddress>,
+    // unix timestamp (seconds) after which the set can no longer attest VAAs, 0 means it never expires
+    pub expiration_time: u32,
+}
+
+impl WormholeGuardianSet {
+    pub fn new(index: u32, keys: Vec<WormholeGuardianAddress>) -> Self {
+        Self {
+            index,
+            keys,
+            expiration_time: 0,
+        }
+    }
+    // wormhole requires 2/3 + 1 of the guardians to sign
+    pub fn quorum(&self) -> usize {
+        (self.keys.len() * 2) / 3 + 1
+    }
+    pub fn is_expired(&self, now: u32) -> bool {
+        self.expiration_time != 0 && now >= self.expiration_time
+    }
+}
+
+pub fn recover_guardian_address<Hasher: Keccak256HashProvider>(
+    digest: &QHash256,
+    signature: &[u8; WORMHOLE_VAA_SIGNATURE_SIZE],
+) -> anyhow::Result<WormholeGuardianAddress> {
+    let rs = Signature::from_slice(&signature[0..64])
+        .map_err(|e| anyhow::anyhow!("invalid guardian signature: {:?}", e))?;
+    let recovery_id = RecoveryId::try_from(signature[64])
+        .map_err(|_| anyhow::anyhow!("invalid guardian signature recovery id {}", signature[64]))?;
+    let public_key = VerifyingKey::recover_from_prehash(digest, &rs, recovery_id)
+        .map_err(|e| anyhow::anyhow!("could not recover guardian public key: {:?}", e))?;
+    let uncompressed = public_key.to_encoded_point(false);
+    let public_key_hash = Hasher::hash_bytes_keccak256(&uncompressed.as_bytes()[1..]);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&public_key_hash[12..]);
+    Ok(address)
+}
+
+#[derive(PartialEq, Clone, Debug, Eq, Default)]
+pub struct WormholeGuardianSetRegistry {
+    guardian_sets: BTreeMap<u32, WormholeGuardianSet>,
+}
+
+impl WormholeGuardianSetRegistry {
+    pub fn new() -> Self {
+        Self {
+            guardian_sets: BTreeMap::new(),
+        }
+    }
+    // a newer set than the current one starts the grace period of the current set at now, unless it already expires
+    pub fn add_guardian_set(&mut self, guardian_set: WormholeGuardianSet, now: u32) -> anyhow::Result<()> {
+        if guardian_set.keys.is_empty() {
+            anyhow::bail!("guardian set {} has no keys", guardian_set.index);
+        }
+        if self.guardian_sets.contains_key(&guardian_set.index) {
+            anyhow::bail!("guardian set {} is already registered", guardian_set.index);
+        }
+        if let Some(current) = self.guardian_sets.values_mut().next_back()
+            && current.index < guardian_set.index
+            && current.expiration_time == 0
+        {
+            current.expiration_time = now.saturating_add(WORMHOLE_GUARDIAN_SET_EXPIRATION_GRACE_PERIOD);
+        }
+        self.guardian_sets.insert(guardian_set.index, guardian_set);
+        Ok(())
+    }
+    // marks an older guardian set as expiring at the given time (wormhole keeps the previous set valid for a grace period after an upgrade)
+    pub fn set_guardian_set_expiration(&mut self, index: u32, expiration_time: u32) -> anyhow::Result<()> {
+        let guardian_set = self
+            .guardian_sets
+            .get_mut(&index)
+            .ok_or_else(|| anyhow::anyhow!("unknown guardian set index {}", index))?;
+        guardian_set.expiration_time = expiration_time;
+        Ok(())
+    }
+    pub fn get_guardian_set(&self, index: u32) -> Option<&WormholeGuardianSet> {
+        self.guardian_sets.get(&index)
+    }
+    pub fn get_current_guardian_set_index(&self) -> Option<u32> {
+        self.guardian_sets.keys().next_back().cloned()
+    }
+
+    // checks that a quorum of the VAA's guardian set signed keccak256(keccak256(body))
+    pub fn verify_vaa<Hasher: Keccak256HashProvider>(
+        &self,
+        vaa: &WormholeVAA,
+        now: u32,
+    ) -> anyhow::Result<()> {
+        let guardian_set = self
+            .get_guardian_set(vaa.guardian_set_index)
+            .ok_or_else(|| anyhow::anyhow!("unknown guardian set index {}", vaa.guardian_set_index))?;
+        // only the current set may attest forever, an older one added without an expiration time is never trusted
+        if guardian_set.expiration_time == 0 && Some(guardian_set.index) != self.get_current_guardian_set_index() {
+            anyhow::bail!("guardian set {} was replaced and has no expiration time", guardian_set.index);
+        }
+        if guardian_set.is_expired(now) {
+            anyhow::bail!(
+                "guardian set {} expired at {}",
+                guardian_set.index,
+                guardian_set.expiration_time
+            );
+        }
+        let quorum = guardian_set.quorum();
+        if vaa.signatures.len() < quorum {
+            anyhow::bail!(
+                "VAA has {} signatures but guardian set {} requires a quorum of {}",
+                vaa.signatures.len(),
+                guardian_set.index,
+                quorum
+            );
+        }
+
+        let digest = vaa.get_signing_digest::<Hasher>();
+        let mut last_guardian_index: Option<u8> = None;
+        for signature in vaa.signatures.iter() {
+            // strictly increasing indices rule out counting the same guardian twice
+            if let Some(last) = last_guardian_index
+                && signature.guardian_index <= last
+            {
+                anyhow::bail!("VAA guardian signatures are not sorted by guardian index");
+            }
+            last_guardian_index = Some(signature.guardian_index);
+
+            let expected_address = guardian_set
+                .keys
+                .get(signature.guardian_index as usize)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "guardian index {} is out of range for guardian set {}",
+                        signature.guardian_index,
+                        guardian_set.index
+                    )
+                })?;
+            let recovered_address =
+                recover_guardian_address::<Hasher>(&digest, &signature.signature)?;
+            if recovered_address != *expected_address {
+                anyhow::bail!(
+                    "signature for guardian index {} was not produced by guardian {}",
+                    signature.guardian_index,
+                    hex::encode(expected_address)
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::SigningKey;
+
+    use crate::{
+        dogecoin::hash::CommonDogeHashProvider,
+        wormhole::vaa::{WORMHOLE_VAA_VERSION, WormholeVAAGuardianSignature},
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+
+    fn guardian_keys(count: u8) -> Vec<SigningKey> {
+        (1..=count)
+            .map(|i| SigningKey::from_slice(&[i; 32]).unwrap())
+            .collect()
+    }
+
+    fn guardian_address(key: &SigningKey) -> WormholeGuardianAddress {
+        let uncompressed = key.verifying_key().to_encoded_point(false);
+        let hash = Hasher::hash_bytes_keccak256(&uncompressed.as_bytes()[1..]);
+        hash[12..].try_into().unwrap()
+    }
+
+    fn signed_vaa(keys: &[SigningKey], signer_indices: &[u8]) -> WormholeVAA {
+        let mut vaa = WormholeVAA {
+            version: WORMHOLE_VAA_VERSION,
+            guardian_set_index: 0,
+            signatures: vec![],
+            timestamp: 1_700_000_000,
+            nonce: 0,
+            emitter_chain: 1,
+            emitter_address: [1u8; 32],
+            sequence: 9,
+            consistency_level: 32,
+            payload: vec![1, 2, 3],
+        };
+        let digest = vaa.get_signing_digest::<Hasher>();
+        for index in signer_indices {
+            let (signature, recovery_id) = keys[*index as usize]
+                .sign_prehash_recoverable(&digest)
+                .unwrap();
+            let mut raw = [0u8; WORMHOLE_VAA_SIGNATURE_SIZE];
+            raw[0..64].copy_from_slice(&signature.to_bytes());
+            raw[64] = recovery_id.to_byte();
+            vaa.signatures.push(WormholeVAAGuardianSignature {
+                guardian_index: *index,
+                signature: raw,
+            });
+        }
+        vaa
+    }
+
+    #[test]
+    fn test_verify_vaa_quorum() {
+        let keys = guardian_keys(4);
+        let mut registry = WormholeGuardianSetRegistry::new();
+        registry
+            .add_guardian_set(
+                WormholeGuardianSet::new(0, keys.iter().map(guardian_address).collect()),
+                0,
+            )
+            .unwrap();
+
+        // 4 guardians => quorum of 3
+        registry
+            .verify_vaa::<Hasher>(&signed_vaa(&keys, &[0, 1, 3]), 0)
+            .unwrap();
+        assert!(registry.verify_vaa::<Hasher>(&signed_vaa(&keys, &[0, 1]), 0).is_err());
+        assert!(registry.verify_vaa::<Hasher>(&signed_vaa(&keys, &[1, 1, 3]), 0).is_err());
+
+        let mut tampered = signed_vaa(&keys, &[0, 1, 2]);
+        tampered.sequence += 1;
+        assert!(registry.verify_vaa::<Hasher>(&tampered, 0).is_err());
+
+        registry.set_guardian_set_expiration(0, 100).unwrap();
+        assert!(registry.verify_vaa::<Hasher>(&signed_vaa(&keys, &[0, 1, 2]), 100).is_err());
+    }
+
+    #[test]
+    fn test_guardian_set_upgrade_expires_previous_set() {
+        let old_keys = guardian_keys(4);
+        let new_keys = (5..=8u8).map(|i| SigningKey::from_slice(&[i; 32]).unwrap()).collect::<Vec<SigningKey>>();
+        let mut registry = WormholeGuardianSetRegistry::new();
+        registry
+            .add_guardian_set(WormholeGuardianSet::new(0, old_keys.iter().map(guardian_address).collect()), 0)
+            .unwrap();
+        let upgrade_time = 1_000;
+        registry
+            .add_guardian_set(
+                WormholeGuardianSet::new(1, new_keys.iter().map(guardian_address).collect()),
+                upgrade_time,
+            )
+            .unwrap();
+        let grace_period_end = upgrade_time + WORMHOLE_GUARDIAN_SET_EXPIRATION_GRACE_PERIOD;
+        assert_eq!(registry.get_guardian_set(0).unwrap().expiration_time, grace_period_end);
+
+        let old_vaa = signed_vaa(&old_keys, &[0, 1, 2]);
+        registry.verify_vaa::<Hasher>(&old_vaa, grace_period_end - 1).unwrap();
+        assert!(registry.verify_vaa::<Hasher>(&old_vaa, grace_period_end).is_err());
+        let new_vaa = WormholeVAA { guardian_set_index: 1, ..signed_vaa(&new_keys, &[0, 1, 2]) };
+        registry.verify_vaa::<Hasher>(&new_vaa, grace_period_end).unwrap();
+
+        // a retired set registered after its successor never attests without an expiration time
+        let mut registry = WormholeGuardianSetRegistry::new();
+        registry
+            .add_guardian_set(WormholeGuardianSet::new(1, new_keys.iter().map(guardian_address).collect()), 0)
+            .unwrap();
+        registry
+            .add_guardian_set(WormholeGuardianSet::new(0, old_keys.iter().map(guardian_address).collect()), 0)
+            .unwrap();
+        assert!(registry.verify_vaa::<Hasher>(&old_vaa, 0).is_err());
+    }
+}
@@ -1,2 +1,4 @@
 pub mod signature;
-pub mod signer;
\ No newline at end of file
+pub mod signer;
+pub mod guardian_set;
+pub mod verifier;
@@ -1,5 +1,18 @@
-use crate::dogecoin::hash::QHash256;
+use crate::dogecoin::{hash::QHash256, sighash::SIGHASH_ALL, transaction::encode_binary_stack_item};
 
+// secp256k1 group order n and n / 2 (big endian)
+const SECP256K1_ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b,
+    0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+const SECP256K1_HALF_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d,
+    0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
 
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub struct PsyCompressedSecp256K1Signature {
@@ -10,33 +23,167 @@ pub struct PsyCompressedSecp256K1Signature {
     pub message: QHash256,
 }
 
+// encodes a big endian u256 as a minimal DER INTEGER (leading zeros stripped, 0x00 prefix if the high bit is set)
 pub fn u256_to_der(u256: &[u8]) -> Vec<u8> {
     assert_eq!(u256.len(), 32);
+    let first_non_zero = u256.iter().position(|x| *x != 0).unwrap_or(31);
+    let value = &u256[first_non_zero..];
     let mut result = vec![];
     result.push(0x02u8);
-    if (u256[0] & 0x80) != 0 {
-        result.push((u256.len() + 1) as u8);
+    if (value[0] & 0x80) != 0 {
+        result.push((value.len() + 1) as u8);
         result.push(0);
-        result.extend_from_slice(u256);
+        result.extend_from_slice(value);
     } else {
-        result.push(u256.len() as u8);
-        result.extend_from_slice(u256);
+        result.push(value.len() as u8);
+        result.extend_from_slice(value);
     }
     result
 }
 
+// s <= n / 2
+pub fn is_low_s(s: &[u8; 32]) -> bool {
+    *s <= SECP256K1_HALF_ORDER
+}
+
+// replaces s with n - s if s > n / 2, (r, s) and (r, n - s) are both valid but only low-S is standard
+pub fn normalize_low_s(rs: &[u8; 64]) -> [u8; 64] {
+    let s: [u8; 32] = rs[32..64].try_into().unwrap();
+    if is_low_s(&s) {
+        return *rs;
+    }
+    let mut result = *rs;
+    let mut borrow = 0i16;
+    for i in (0..32).rev() {
+        let mut diff = SECP256K1_ORDER[i] as i16 - s[i] as i16 - borrow;
+        if diff < 0 {
+            diff += 256;
+            borrow = 1;
+        } else {
+            borrow = 0;
+        }
+        result[32 + i] = diff as u8;
+    }
+    result
+}
+
+// strict BIP66 check of a DER signature followed by its sighash type byte (IsValidSignatureEncoding in dogecoin core)
+pub fn is_valid_der_signature_encoding(signature: &[u8]) -> bool {
+    let len = signature.len();
+    if !(9..=73).contains(&len) || signature[0] != 0x30 || signature[1] as usize != len - 3 {
+        return false;
+    }
+    let r_len = signature[3] as usize;
+    if 5 + r_len >= len {
+        return false;
+    }
+    let s_len = signature[5 + r_len] as usize;
+    if r_len + s_len + 7 != len {
+        return false;
+    }
+    // integers must be positive and minimally encoded
+    if signature[2] != 0x02
+        || r_len == 0
+        || (signature[4] & 0x80) != 0
+        || (r_len > 1 && signature[4] == 0 && (signature[5] & 0x80) == 0)
+    {
+        return false;
+    }
+    if signature[r_len + 4] != 0x02
+        || s_len == 0
+        || (signature[r_len + 6] & 0x80) != 0
+        || (s_len > 1 && signature[r_len + 6] == 0 && (signature[r_len + 7] & 0x80) == 0)
+    {
+        return false;
+    }
+    true
+}
+
+fn der_integer_to_u256(integer: &[u8]) -> anyhow::Result<[u8; 32]> {
+    let value = match integer.split_first() {
+        Some((0, rest)) => rest,
+        _ => integer,
+    };
+    if value.len() > 32 {
+        anyhow::bail!("DER integer is longer than 32 bytes");
+    }
+    let mut result = [0u8; 32];
+    result[(32 - value.len())..].copy_from_slice(value);
+    Ok(result)
+}
+
+// encodes r || s as a strict DER signature (without a sighash type byte), normalizing to low-S
+pub fn encode_der_signature(rs: &[u8; 64]) -> Vec<u8> {
+    let rs = normalize_low_s(rs);
+    let r = u256_to_der(&rs[0..32]);
+    let s = u256_to_der(&rs[32..64]);
+    [vec![0x30u8, (r.len() + s.len()) as u8], r, s].concat()
+}
+
+// decodes a strict DER signature (without a sighash type byte) into r || s
+pub fn decode_der_signature(der: &[u8]) -> anyhow::Result<[u8; 64]> {
+    // the validator expects a trailing sighash type byte
+    let mut with_sighash_type = der.to_vec();
+    with_sighash_type.push(0);
+    if !is_valid_der_signature_encoding(&with_sighash_type) {
+        anyhow::bail!("signature is not strict DER: {}", hex::encode(der));
+    }
+    let r_len = der[3] as usize;
+    let s_len = der[5 + r_len] as usize;
+    let mut rs = [0u8; 64];
+    rs[0..32].copy_from_slice(&der_integer_to_u256(&der[4..(4 + r_len)])?);
+    rs[32..64].copy_from_slice(&der_integer_to_u256(&der[(6 + r_len)..(6 + r_len + s_len)])?);
+    Ok(rs)
+}
+
 impl PsyCompressedSecp256K1Signature {
+    pub fn with_low_s(&self) -> Self {
+        Self {
+            signature: normalize_low_s(&self.signature),
+            ..*self
+        }
+    }
+    pub fn to_der(&self) -> Vec<u8> {
+        encode_der_signature(&self.signature)
+    }
+    // <DER signature || SIGHASH_ALL>
+    pub fn to_btc_signature_push(&self) -> Vec<u8> {
+        let mut signature = self.to_der();
+        signature.push(SIGHASH_ALL as u8);
+        encode_binary_stack_item(&signature)
+    }
+    // <DER signature || SIGHASH_ALL> <compressed public key>
     pub fn to_btc_script(&self) -> Vec<u8> {
-        let r = u256_to_der(&self.signature[0..32]);
-        let s = u256_to_der(&self.signature[32..64]);
-        let combined_rs_length = (r.len() + s.len()) as u8;
-        let sig_stack_raw = [
-            vec![combined_rs_length + 3, 0x30u8, combined_rs_length],
-            r,
-            s,
-            vec![0x01],
-        ]
-        .concat();
-        [sig_stack_raw, vec![0x21], self.public_key.to_vec()].concat()
-    }
-}
\ No newline at end of file
+        [self.to_btc_signature_push(), encode_binary_stack_item(&self.public_key)].concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_der_round_trip_is_minimal_and_low_s() {
+        // r has leading zero bytes, s is high
+        let mut rs = [0u8; 64];
+        rs[2..32].copy_from_slice(&[0x11; 30]);
+        rs[32..64].copy_from_slice(&SECP256K1_ORDER);
+        rs[63] -= 1;
+        let der = encode_der_signature(&rs);
+        assert_eq!(&der[2..5], &[0x02, 30, 0x11]);
+        assert!(is_valid_der_signature_encoding(&[der.clone(), vec![1]].concat()));
+
+        let decoded = decode_der_signature(&der).unwrap();
+        assert_eq!(decoded[0..32], rs[0..32]);
+        // n - (n - 1) = 1
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        assert_eq!(decoded[32..64], one);
+        assert!(is_low_s(&one));
+        assert!(!is_low_s(&rs[32..64].try_into().unwrap()));
+
+        // a redundant leading zero is not strict DER
+        let non_minimal = [vec![0x30, 0x08, 0x02, 0x02, 0x00, 0x01], vec![0x02, 0x02, 0x00, 0x01]].concat();
+        assert!(decode_der_signature(&non_minimal).is_err());
+    }
+}
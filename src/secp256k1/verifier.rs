@@ -0,0 +1,134 @@
+use k256::ecdsa::{Signature, VerifyingKey, signature::hazmat::PrehashVerifier};
+
+use crate::{
+    dogecoin::hash::{DogeHashProvider, QHash160, QHash256},
+    secp256k1::signature::PsyCompressedSecp256K1Signature,
+};
+
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub enum SignatureVerificationError {
+    // the signature was produced for a different key than the one the funds are locked to
+    PublicKeyMismatch {
+        expected_public_key_hash: QHash160,
+        actual_public_key_hash: QHash160,
+    },
+    // the signature was produced for a different sighash than the one requested
+    MessageMismatch {
+        expected_message: QHash256,
+        actual_message: QHash256,
+    },
+    // the public key is not a valid compressed secp256k1 point
+    InvalidPublicKey,
+    // r/s are out of range or do not verify against the public key and message
+    InvalidSignature,
+}
+
+impl std::fmt::Display for SignatureVerificationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureVerificationError::PublicKeyMismatch {
+                expected_public_key_hash,
+                actual_public_key_hash,
+            } => write!(
+                f,
+                "signature public key hash {} does not match the expected public key hash {}",
+                hex::encode(actual_public_key_hash),
+                hex::encode(expected_public_key_hash)
+            ),
+            SignatureVerificationError::MessageMismatch {
+                expected_message,
+                actual_message,
+            } => write!(
+                f,
+                "signature is for message {} but message {} was requested",
+                hex::encode(actual_message),
+                hex::encode(expected_message)
+            ),
+            SignatureVerificationError::InvalidPublicKey => write!(f, "signature public key is not a valid secp256k1 point"),
+            SignatureVerificationError::InvalidSignature => write!(f, "signature does not verify against its public key and message"),
+        }
+    }
+}
+
+impl std::error::Error for SignatureVerificationError {}
+
+// checks that r/s is a valid ECDSA signature of signature.message by signature.public_key
+pub fn verify_signature(signature: &PsyCompressedSecp256K1Signature) -> Result<(), SignatureVerificationError> {
+    // k256 also accepts the sec1 "compact" tag (0x05), which dogecoin does not
+    if signature.public_key[0] != 0x02 && signature.public_key[0] != 0x03 {
+        return Err(SignatureVerificationError::InvalidPublicKey);
+    }
+    let public_key =
+        VerifyingKey::from_sec1_bytes(&signature.public_key).map_err(|_| SignatureVerificationError::InvalidPublicKey)?;
+    let rs = Signature::from_slice(&signature.signature).map_err(|_| SignatureVerificationError::InvalidSignature)?;
+    // k256 only accepts low-S, but (r, n - s) is just as valid so accept high-S here too
+    let rs = rs.normalize_s().unwrap_or(rs);
+    public_key
+        .verify_prehash(&signature.message, &rs)
+        .map_err(|_| SignatureVerificationError::InvalidSignature)
+}
+
+// checks a signature returned by a signer before it is embedded in a transaction
+pub fn verify_signature_for_key_and_message<Hasher: DogeHashProvider>(
+    signature: &PsyCompressedSecp256K1Signature,
+    expected_public_key_hash: &QHash160,
+    expected_message: &QHash256,
+) -> Result<(), SignatureVerificationError> {
+    let actual_public_key_hash = Hasher::bitcoin_hash160(&signature.public_key);
+    if actual_public_key_hash != *expected_public_key_hash {
+        return Err(SignatureVerificationError::PublicKeyMismatch {
+            expected_public_key_hash: *expected_public_key_hash,
+            actual_public_key_hash,
+        });
+    }
+    if signature.message != *expected_message {
+        return Err(SignatureVerificationError::MessageMismatch {
+            expected_message: *expected_message,
+            actual_message: signature.message,
+        });
+    }
+    verify_signature(signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dogecoin::hash::CommonDogeHashProvider,
+        secp256k1::signer::{SimpleSinglePublicKeySigner, SimpleSingleSigner},
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+
+    #[test]
+    fn test_verify_signature_for_key_and_message() {
+        let signer = SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([7u8; 32]).unwrap();
+        let public_key_hash = Hasher::bitcoin_hash160(&signer.get_compressed_public_key().0);
+        let message = [5u8; 32];
+        let signature = signer.sign_message(message).unwrap();
+        verify_signature_for_key_and_message::<Hasher>(&signature, &public_key_hash, &message).unwrap();
+
+        assert!(matches!(
+            verify_signature_for_key_and_message::<Hasher>(&signature, &[0u8; 20], &message),
+            Err(SignatureVerificationError::PublicKeyMismatch { .. })
+        ));
+        assert!(matches!(
+            verify_signature_for_key_and_message::<Hasher>(&signature, &public_key_hash, &[6u8; 32]),
+            Err(SignatureVerificationError::MessageMismatch { .. })
+        ));
+
+        let mut wrong_message = signature;
+        wrong_message.message = [6u8; 32];
+        assert_eq!(verify_signature(&wrong_message), Err(SignatureVerificationError::InvalidSignature));
+
+        let mut invalid_point = signature;
+        invalid_point.public_key[0] = 0x05;
+        assert_eq!(verify_signature(&invalid_point), Err(SignatureVerificationError::InvalidPublicKey));
+        // x = 5 is not on the curve
+        invalid_point.public_key = [0u8; 33];
+        invalid_point.public_key[0] = 0x02;
+        invalid_point.public_key[32] = 5;
+        assert_eq!(verify_signature(&invalid_point), Err(SignatureVerificationError::InvalidPublicKey));
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{
+    dogecoin::{
+        address::{AddressToBTCScript, BTCAddress160},
+        constants::DogeNetworkConfig,
+        hash::DogeHashProvider,
+        transaction::{BTCTransactionInputWithoutScript, BTCTransactionOutput},
+    },
+    psy_doge_link::{coin_selection::CoinSelectionParams, data::BTCUTXO, traits::PsyBitcoinAPIAsync},
+    wormhole::{
+        fee_estimator::{WormholeVAAFeeBounds, WormholeVAASpendSize},
+        p2sh_vaa::{WormholeBitcoinLikeVAAMessage, WormholeBitcoinLikeVAAMetadata},
+        template_registry::{WormholeGuardianKey, WormholeScriptTemplateRegistry},
+    },
+};
+
+/*
+The rules a consolidation message must pass on top of the spend validation (which already enforces
+the fee bounds): every input is locked to the same redeem script and the only output pays its P2SH
+address. script_positions[i] is the position in redeem_scripts of the script input i is locked to.
+*/
+pub fn check_consolidation<Hasher: DogeHashProvider>(
+    message: &WormholeBitcoinLikeVAAMessage,
+    redeem_scripts: &[Vec<u8>],
+    script_positions: &[usize],
+) -> anyhow::Result<()> {
+    let [output] = message.outputs.as_slice() else {
+        anyhow::bail!("a consolidation must have exactly one output, got {}", message.outputs.len());
+    };
+    let position = *script_positions
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("a consolidation must have at least one input"))?;
+    if script_positions.iter().any(|other| *other != position) {
+        anyhow::bail!("consolidation inputs must all be locked to the same vaa-p2sh address");
+    }
+    let redeem_script = redeem_scripts
+        .get(position)
+        .ok_or_else(|| anyhow::anyhow!("no redeem script at position {}", position))?;
+    let address = BTCAddress160::new_p2sh(Hasher::bitcoin_hash160(redeem_script));
+    if output.script != address.to_btc_script() {
+        anyhow::bail!("consolidation output must pay the vaa-p2sh address its inputs are locked to");
+    }
+    Ok(())
+}
+
+// Picks small utxos of a VAA-P2SH address worth sweeping into one output to the same address
+#[derive(Clone, Debug)]
+pub struct WormholeConsolidationPlanner {
+    guardian_key: WormholeGuardianKey,
+    // koinu per byte
+    fee_rate: u64,
+    // share of its value (in basis points) a utxo must keep after paying for its own input
+    min_efficiency_bps: u64,
+    // larger utxos are left alone
+    max_utxo_value: u64,
+    min_inputs: usize,
+    max_inputs: usize,
+    include_unconfirmed: bool,
+    script_template_registry: WormholeScriptTemplateRegistry,
+}
+
+impl WormholeConsolidationPlanner {
+    // P2PK templates need a key with its full public key, see WormholeGuardianKey::new_with_public_key
+    pub fn new(guardian_key: WormholeGuardianKey, fee_rate: u64, min_efficiency_bps: u64) -> Self {
+        Self {
+            guardian_key,
+            fee_rate,
+            min_efficiency_bps: min_efficiency_bps.min(10_000),
+            max_utxo_value: u64::MAX,
+            min_inputs: 2,
+            max_inputs: 500,
+            include_unconfirmed: false,
+            script_template_registry: WormholeScriptTemplateRegistry::new_with_builtin_templates(),
+        }
+    }
+    pub fn with_max_utxo_value(mut self, max_utxo_value: u64) -> Self {
+        self.max_utxo_value = max_utxo_value;
+        self
+    }
+    // fewer qualifying utxos than this are not worth a message
+    pub fn with_min_inputs(mut self, min_inputs: usize) -> Self {
+        self.min_inputs = min_inputs.max(1);
+        self
+    }
+    pub fn with_max_inputs(mut self, max_inputs: usize) -> Self {
+        self.max_inputs = max_inputs.max(1);
+        self
+    }
+    pub fn with_unconfirmed_utxos(mut self, include_unconfirmed: bool) -> Self {
+        self.include_unconfirmed = include_unconfirmed;
+        self
+    }
+    // the templates addresses are derived and sweeps are sized with, the builtin ones by default
+    pub fn with_script_template_registry(mut self, script_template_registry: WormholeScriptTemplateRegistry) -> Self {
+        self.script_template_registry = script_template_registry;
+        self
+    }
+    pub fn get_guardian_key(&self) -> &WormholeGuardianKey {
+        &self.guardian_key
+    }
+    pub fn get_p2sh_address<Hasher: DogeHashProvider>(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+    ) -> anyhow::Result<BTCAddress160> {
+        self.script_template_registry
+            .get_p2sh_address::<Hasher>(metadata, &self.guardian_key)
+    }
+
+    fn is_worth_consolidating(&self, params: &CoinSelectionParams, utxo: &BTCUTXO) -> bool {
+        let effective_value = params.get_effective_value(utxo.value);
+        (self.include_unconfirmed || utxo.status.confirmed)
+            && utxo.value <= self.max_utxo_value
+            && effective_value > 0
+            && effective_value as u128 * 10_000 >= utxo.value as u128 * self.min_efficiency_bps as u128
+    }
+
+    // I/O free planning, returns None if too few utxos qualify or they cannot pay for the sweep
+    pub fn plan_for_utxos<N: DogeNetworkConfig, Hasher: DogeHashProvider>(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        utxos: &[BTCUTXO],
+    ) -> anyhow::Result<Option<WormholeBitcoinLikeVAAMessage>> {
+        let output = self.get_p2sh_address::<Hasher>(metadata)?.to_btc_output(0);
+        // the consolidated output is the change of a transaction without destination outputs
+        let params = CoinSelectionParams::new(
+            0,
+            self.script_template_registry.estimate_script_sig_size(metadata)?,
+            vec![],
+            output.script.len(),
+            self.fee_rate,
+        )
+        .with_relay_policy(N::RELAY_POLICY)
+        .with_max_inputs(self.max_inputs);
+
+        // many small inputs: the smallest qualifying utxos first
+        let mut selected = utxos
+            .iter()
+            .filter(|utxo| self.is_worth_consolidating(&params, utxo))
+            .collect::<Vec<&BTCUTXO>>();
+        selected.sort_by_key(|utxo| (utxo.value, utxo.txid, utxo.vout));
+        selected.truncate(params.get_max_input_count());
+        if selected.len() < self.min_inputs {
+            return Ok(None);
+        }
+        let total_input_amount = selected.iter().fold(0u64, |sum, utxo| sum.saturating_add(utxo.value));
+        let Some((fee, amount)) = params
+            .get_fee_and_change(selected.len(), total_input_amount)
+            .filter(|(_, amount)| *amount > 0)
+        else {
+            return Ok(None);
+        };
+
+        let fee_bounds = WormholeVAASpendSize::estimate_with_registry(
+            &self.script_template_registry,
+            metadata,
+            selected.len(),
+            std::slice::from_ref(&output.script),
+        )?
+            .get_fee_bounds(&N::RELAY_POLICY, self.fee_rate);
+        let fee_bounds = WormholeVAAFeeBounds {
+            max_doge_transaction_fee: fee_bounds.max_doge_transaction_fee.max(fee),
+            ..fee_bounds
+        };
+        Ok(Some(WormholeBitcoinLikeVAAMessage {
+            metadata: WormholeBitcoinLikeVAAMetadata {
+                total_output_amount: amount,
+                ..fee_bounds.apply_to_metadata(metadata)
+            },
+            inputs: selected.iter().copied().map(BTCTransactionInputWithoutScript::from).collect(),
+            outputs: vec![BTCTransactionOutput { value: amount, ..output }],
+        }))
+    }
+
+    pub async fn plan<N: DogeNetworkConfig, Hasher: DogeHashProvider, API: PsyBitcoinAPIAsync>(
+        &self,
+        api: &API,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+    ) -> anyhow::Result<Option<WormholeBitcoinLikeVAAMessage>> {
+        let address = self.get_p2sh_address::<Hasher>(metadata)?;
+        let utxos = api.get_utxos(address).await?;
+        self.plan_for_utxos::<N, Hasher>(metadata, &utxos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dogecoin::{
+            constants::DogeTestNetConfig,
+            hash::CommonDogeHashProvider,
+            transaction::{BTCTransaction, BTCTransactionInput},
+        },
+        wormhole::{
+            script_template::WORMHOLE_VAA_TEMPLATE_V1_P2PK,
+            test_utils::{EXAMPLE_GUARDIAN_PUBLIC_KEY_HASH, example_metadata, get_confirmed_utxo, get_input_transactions},
+        },
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+    type Network = DogeTestNetConfig;
+
+    #[test]
+    fn test_consolidation_sweeps_small_utxos_back_to_self() {
+        let guardian_key = EXAMPLE_GUARDIAN_PUBLIC_KEY_HASH;
+        let metadata = example_metadata();
+        let address = metadata.get_p2sh_address::<Network, Hasher>(&guardian_key).unwrap();
+        // a utxo that mostly pays for its own input, three small ones and one too large to touch
+        let funding_transactions = [300_000u64, 2_000_000, 1_000_000, 50_000_000, 3_000_000]
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                BTCTransaction::from_io(
+                    vec![BTCTransactionInput { hash: [i as u8; 32], ..Default::default() }],
+                    vec![address.to_btc_output(*value)],
+                )
+            })
+            .collect::<Vec<BTCTransaction>>();
+        let utxos = funding_transactions
+            .iter()
+            .map(|tx| get_confirmed_utxo::<Hasher>(tx, 0))
+            .collect::<Vec<BTCUTXO>>();
+        let planner = WormholeConsolidationPlanner::new(WormholeGuardianKey::new(guardian_key), 1000, 5_000).with_max_utxo_value(10_000_000);
+
+        let message = planner.plan_for_utxos::<Network, Hasher>(&metadata, &utxos).unwrap().unwrap();
+        assert_eq!(message.inputs.len(), 3);
+        assert_eq!(message.outputs.len(), 1);
+        assert_eq!(message.outputs[0].get_output_address().unwrap(), address);
+        let input_transactions = get_input_transactions::<Hasher>(&message, &funding_transactions);
+        let redeem_scripts = vec![metadata.get_locking_script(&guardian_key).unwrap()];
+        let (_, script_positions, _) = message
+            .validate_and_get_sighashes_for_redeem_scripts::<Hasher>(&input_transactions, &redeem_scripts)
+            .unwrap();
+        check_consolidation::<Hasher>(&message, &redeem_scripts, &script_positions).unwrap();
+
+        // paying anywhere but back to the same address is not a consolidation
+        let mut redirected = message.clone();
+        redirected.outputs[0] = BTCAddress160::new_p2pkh([9u8; 20]).to_btc_output(redirected.outputs[0].value);
+        assert!(check_consolidation::<Hasher>(&redirected, &redeem_scripts, &script_positions).is_err());
+        let mut split = message.clone();
+        split.outputs.push(split.outputs[0].clone());
+        assert!(check_consolidation::<Hasher>(&split, &redeem_scripts, &script_positions).is_err());
+
+        assert!(planner
+            .clone()
+            .with_min_inputs(4)
+            .plan_for_utxos::<Network, Hasher>(&metadata, &utxos)
+            .unwrap()
+            .is_none());
+
+        // P2PK templates commit to the full public key, a planner that only knows the hash cannot derive them
+        let p2pk_metadata = WormholeBitcoinLikeVAAMetadata { template_version: WORMHOLE_VAA_TEMPLATE_V1_P2PK, ..metadata };
+        assert!(planner.plan_for_utxos::<Network, Hasher>(&p2pk_metadata, &utxos).is_err());
+        let public_key = [0x02u8; 33];
+        let message = WormholeConsolidationPlanner::new(WormholeGuardianKey::new_with_public_key::<Hasher>(public_key), 1000, 5_000)
+            .with_max_utxo_value(10_000_000)
+            .plan_for_utxos::<Network, Hasher>(&p2pk_metadata, &utxos)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            message.outputs[0].get_output_address().unwrap(),
+            p2pk_metadata.get_p2sh_address_for_public_key::<Network, Hasher>(&public_key).unwrap()
+        );
+    }
+}
@@ -0,0 +1,430 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    dogecoin::{
+        address::{BTCAddress160, BTCAddressType},
+        constants::DogeNetworkConfig,
+        hash::{DogeHashProvider, QHash256},
+    },
+    psy_doge_link::traits::PsyBitcoinAPIAsync,
+    wormhole::{
+        p2sh_vaa::WormholeBitcoinLikeVAAMetadata,
+        template_registry::{WormholeGuardianKey, WormholeScriptTemplateRegistry},
+    },
+};
+
+// (txid, vout) as reported by PsyBitcoinAPIAsync::get_utxos
+pub type DepositOutpoint = (QHash256, u32);
+
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct WormholeDeposit {
+    pub address: BTCAddress160,
+    pub metadata: WormholeBitcoinLikeVAAMetadata,
+    pub txid: QHash256,
+    pub vout: u32,
+    pub value: u64,
+}
+
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub enum WormholeDepositEvent {
+    // the output showed up in the address' utxo set (possibly still in the mempool)
+    DepositSeen(WormholeDeposit),
+    // the output reached the watcher's required number of confirmations
+    DepositConfirmed {
+        deposit: WormholeDeposit,
+        block_height: u64,
+        confirmations: u64,
+    },
+    // the output left the address' utxo set (spent, or dropped from the mempool before confirming)
+    DepositSpent(WormholeDeposit),
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub enum DepositWatcherStage {
+    Seen,
+    Confirmed,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct TrackedDeposit {
+    pub address: BTCAddress160,
+    pub value: u64,
+    pub stage: DepositWatcherStage,
+}
+
+// the last event emitted for every unspent deposit, so a restarted watcher resumes instead of re-emitting
+#[derive(PartialEq, Clone, Debug, Eq, Default)]
+pub struct DepositWatcherCheckpoint {
+    pub deposits: BTreeMap<DepositOutpoint, TrackedDeposit>,
+}
+
+/*
+Text encoding, one deposit per line:
+    <txid> <vout> <address type> <address hash160> <value> <seen|confirmed>
+*/
+impl DepositWatcherCheckpoint {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn to_text(&self) -> String {
+        self.deposits
+            .iter()
+            .map(|((txid, vout), deposit)| {
+                let stage = match deposit.stage {
+                    DepositWatcherStage::Seen => "seen",
+                    DepositWatcherStage::Confirmed => "confirmed",
+                };
+                format!(
+                    "{} {} {} {} {} {}\n",
+                    hex::encode(txid),
+                    vout,
+                    deposit.address.address_type.to_u8(),
+                    hex::encode(deposit.address.address),
+                    deposit.value,
+                    stage
+                )
+            })
+            .collect()
+    }
+    pub fn from_text(text: &str) -> anyhow::Result<Self> {
+        let mut deposits = BTreeMap::new();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let parts = line.split(' ').collect::<Vec<&str>>();
+            if parts.len() != 6 {
+                anyhow::bail!("malformed deposit watcher checkpoint line: {}", line);
+            }
+            let mut txid = [0u8; 32];
+            hex::decode_to_slice(parts[0], &mut txid)?;
+            let mut address = [0u8; 20];
+            hex::decode_to_slice(parts[3], &mut address)?;
+            let stage = match parts[5] {
+                "seen" => DepositWatcherStage::Seen,
+                "confirmed" => DepositWatcherStage::Confirmed,
+                _ => anyhow::bail!("unknown deposit stage in checkpoint: {}", parts[5]),
+            };
+            deposits.insert(
+                (txid, parts[1].parse()?),
+                TrackedDeposit {
+                    address: BTCAddress160 {
+                        address_type: BTCAddressType::try_from(parts[2].parse::<u8>()?)?,
+                        address,
+                    },
+                    value: parts[4].parse()?,
+                    stage,
+                },
+            );
+        }
+        Ok(Self { deposits })
+    }
+}
+
+pub trait DepositWatcherCheckpointStore {
+    fn load(&self) -> anyhow::Result<DepositWatcherCheckpoint>;
+    fn save(&self, checkpoint: &DepositWatcherCheckpoint) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryDepositWatcherCheckpointStore {
+    checkpoint: Mutex<DepositWatcherCheckpoint>,
+}
+
+impl MemoryDepositWatcherCheckpointStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl DepositWatcherCheckpointStore for MemoryDepositWatcherCheckpointStore {
+    fn load(&self) -> anyhow::Result<DepositWatcherCheckpoint> {
+        Ok(self
+            .checkpoint
+            .lock()
+            .map_err(|_| anyhow::anyhow!("deposit watcher checkpoint lock poisoned"))?
+            .clone())
+    }
+    fn save(&self, checkpoint: &DepositWatcherCheckpoint) -> anyhow::Result<()> {
+        *self
+            .checkpoint
+            .lock()
+            .map_err(|_| anyhow::anyhow!("deposit watcher checkpoint lock poisoned"))? = checkpoint.clone();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileDepositWatcherCheckpointStore {
+    path: PathBuf,
+}
+
+impl FileDepositWatcherCheckpointStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl DepositWatcherCheckpointStore for FileDepositWatcherCheckpointStore {
+    fn load(&self) -> anyhow::Result<DepositWatcherCheckpoint> {
+        if !self.path.exists() {
+            return Ok(DepositWatcherCheckpoint::new());
+        }
+        DepositWatcherCheckpoint::from_text(&std::fs::read_to_string(&self.path)?)
+    }
+    fn save(&self, checkpoint: &DepositWatcherCheckpoint) -> anyhow::Result<()> {
+        // write then rename so a crash leaves either the old or the new checkpoint, never half of one
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, checkpoint.to_text())?;
+        std::fs::File::open(&temp_path)?.sync_all()?;
+        std::fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+pub struct WormholeDepositWatcher<API: PsyBitcoinAPIAsync, Store: DepositWatcherCheckpointStore> {
+    api: API,
+    checkpoint_store: Store,
+    guardian_public_key_hash: [u8; 20],
+    required_confirmations: u64,
+    registrations: BTreeMap<BTCAddress160, WormholeBitcoinLikeVAAMetadata>,
+    checkpoint: DepositWatcherCheckpoint,
+    // the checkpoint after the events returned by the last poll, saved once they are committed
+    pending_checkpoint: Option<DepositWatcherCheckpoint>,
+    script_template_registry: WormholeScriptTemplateRegistry,
+}
+
+// Sync is required by the default get_block_tip_height of the api
+impl<API: PsyBitcoinAPIAsync + Sync, Store: DepositWatcherCheckpointStore> WormholeDepositWatcher<API, Store> {
+    pub fn new(
+        api: API,
+        checkpoint_store: Store,
+        guardian_public_key_hash: [u8; 20],
+        required_confirmations: u64,
+    ) -> anyhow::Result<Self> {
+        let checkpoint = checkpoint_store.load()?;
+        Ok(Self {
+            api,
+            checkpoint_store,
+            guardian_public_key_hash,
+            required_confirmations,
+            registrations: BTreeMap::new(),
+            checkpoint,
+            pending_checkpoint: None,
+            script_template_registry: WormholeScriptTemplateRegistry::new_with_builtin_templates(),
+        })
+    }
+    // the templates registered addresses are derived with, the builtin ones by default
+    pub fn with_script_template_registry(mut self, script_template_registry: WormholeScriptTemplateRegistry) -> Self {
+        self.script_template_registry = script_template_registry;
+        self
+    }
+    // starts watching the p2sh address of the metadata's (emitter, sub_address_seed) pair
+    pub fn register<N: DogeNetworkConfig, Hasher: DogeHashProvider>(
+        &mut self,
+        metadata: WormholeBitcoinLikeVAAMetadata,
+    ) -> anyhow::Result<BTCAddress160> {
+        let address = self
+            .script_template_registry
+            .get_p2sh_address::<Hasher>(&metadata, &WormholeGuardianKey::new(self.guardian_public_key_hash))?;
+        self.registrations.insert(address, metadata);
+        Ok(address)
+    }
+    pub fn unregister(&mut self, address: &BTCAddress160) -> Option<WormholeBitcoinLikeVAAMetadata> {
+        self.registrations.remove(address)
+    }
+    pub fn get_checkpoint(&self) -> &DepositWatcherCheckpoint {
+        &self.checkpoint
+    }
+
+    /*
+    Fetches the utxos of every registered address and returns the events since the last committed
+    checkpoint. Nothing is saved until commit is called once the events have been handled, so a crash
+    in between delivers them again on the next poll (at least once delivery).
+    */
+    pub async fn poll(&mut self) -> anyhow::Result<Vec<WormholeDepositEvent>> {
+        let tip_height = self.api.get_block_tip_height().await?;
+        let mut checkpoint = self.checkpoint.clone();
+        let mut events = Vec::new();
+
+        for (address, metadata) in self.registrations.iter() {
+            let utxos = self.api.get_utxos(*address).await?;
+            for utxo in utxos.iter() {
+                let outpoint = (utxo.txid, utxo.vout);
+                let deposit = WormholeDeposit {
+                    address: *address,
+                    metadata: *metadata,
+                    txid: utxo.txid,
+                    vout: utxo.vout,
+                    value: utxo.value,
+                };
+                let tracked = checkpoint.deposits.entry(outpoint).or_insert_with(|| {
+                    events.push(WormholeDepositEvent::DepositSeen(deposit.clone()));
+                    TrackedDeposit {
+                        address: *address,
+                        value: utxo.value,
+                        stage: DepositWatcherStage::Seen,
+                    }
+                });
+                if tracked.stage == DepositWatcherStage::Seen
+                    && utxo.status.confirmed
+                    && tip_height >= utxo.status.block_height
+                {
+                    let confirmations = tip_height - utxo.status.block_height + 1;
+                    if confirmations >= self.required_confirmations {
+                        tracked.stage = DepositWatcherStage::Confirmed;
+                        events.push(WormholeDepositEvent::DepositConfirmed {
+                            deposit,
+                            block_height: utxo.status.block_height,
+                            confirmations,
+                        });
+                    }
+                }
+            }
+
+            let spent = checkpoint
+                .deposits
+                .iter()
+                .filter(|((txid, vout), tracked)| {
+                    tracked.address == *address
+                        && !utxos.iter().any(|utxo| utxo.txid == *txid && utxo.vout == *vout)
+                })
+                .map(|(outpoint, tracked)| (*outpoint, *tracked))
+                .collect::<Vec<(DepositOutpoint, TrackedDeposit)>>();
+            for ((txid, vout), tracked) in spent {
+                checkpoint.deposits.remove(&(txid, vout));
+                events.push(WormholeDepositEvent::DepositSpent(WormholeDeposit {
+                    address: *address,
+                    metadata: *metadata,
+                    txid,
+                    vout,
+                    value: tracked.value,
+                }));
+            }
+        }
+
+        self.pending_checkpoint = Some(checkpoint);
+        Ok(events)
+    }
+    // acknowledges the events of the last poll and saves the checkpoint past them
+    pub fn commit(&mut self) -> anyhow::Result<()> {
+        let Some(checkpoint) = self.pending_checkpoint.take() else {
+            anyhow::bail!("no polled deposit events to commit");
+        };
+        self.checkpoint_store.save(&checkpoint)?;
+        self.checkpoint = checkpoint;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        dogecoin::{constants::DogeTestNetConfig, hash::CommonDogeHashProvider, transaction::BTCTransaction},
+        psy_doge_link::data::{BTCUTXO, BTCUTXOStatus},
+        wormhole::test_utils::{EXAMPLE_GUARDIAN_PUBLIC_KEY_HASH, example_metadata},
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+    type Network = DogeTestNetConfig;
+
+    #[derive(Default)]
+    struct LocalBitcoinAPI {
+        tip_height: Mutex<u64>,
+        utxos: Mutex<HashMap<BTCAddress160, Vec<BTCUTXO>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl PsyBitcoinAPIAsync for &LocalBitcoinAPI {
+        async fn get_utxos(&self, address: BTCAddress160) -> anyhow::Result<Vec<BTCUTXO>> {
+            Ok(self.utxos.lock().unwrap().get(&address).cloned().unwrap_or_default())
+        }
+        async fn estimate_fee_rate(&self, _n_blocks: u32) -> anyhow::Result<u64> {
+            Ok(1)
+        }
+        async fn get_transaction(&self, _txid: QHash256) -> anyhow::Result<BTCTransaction> {
+            anyhow::bail!("not implemented")
+        }
+        async fn send_transaction(&self, _tx: &BTCTransaction) -> anyhow::Result<QHash256> {
+            anyhow::bail!("not implemented")
+        }
+        async fn get_block_tip_height(&self) -> anyhow::Result<u64> {
+            Ok(*self.tip_height.lock().unwrap())
+        }
+    }
+
+    fn utxo(block_height: Option<u64>) -> BTCUTXO {
+        BTCUTXO {
+            status: BTCUTXOStatus {
+                block_hash: [0u8; 32],
+                block_height: block_height.unwrap_or(0),
+                block_time: 0,
+                confirmed: block_height.is_some(),
+            },
+            txid: [9u8; 32],
+            value: 5_000_000,
+            vout: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deposit_lifecycle_survives_restart() {
+        let api = LocalBitcoinAPI::default();
+        *api.tip_height.lock().unwrap() = 100;
+        let checkpoint_path = std::env::temp_dir().join(format!("deposit_watcher_{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&checkpoint_path);
+        let metadata = example_metadata();
+        let new_watcher = || {
+            let mut watcher = WormholeDepositWatcher::new(
+                &api,
+                FileDepositWatcherCheckpointStore::new(&checkpoint_path),
+                EXAMPLE_GUARDIAN_PUBLIC_KEY_HASH,
+                6,
+            )
+            .unwrap();
+            let address = watcher.register::<Network, Hasher>(metadata).unwrap();
+            (watcher, address)
+        };
+
+        let (mut watcher, address) = new_watcher();
+        api.utxos.lock().unwrap().insert(address, vec![utxo(None)]);
+        let events = watcher.poll().await.unwrap();
+        assert!(matches!(events.as_slice(), [WormholeDepositEvent::DepositSeen(deposit)] if deposit.value == 5_000_000));
+
+        // events that were never committed are delivered again after a restart
+        let (mut watcher, _) = new_watcher();
+        assert_eq!(watcher.poll().await.unwrap(), events);
+        watcher.commit().unwrap();
+        assert!(watcher.commit().is_err());
+
+        api.utxos.lock().unwrap().insert(address, vec![utxo(Some(96))]);
+        assert!(watcher.poll().await.unwrap().is_empty());
+        watcher.commit().unwrap();
+
+        // restarting must not re-emit DepositSeen
+        let (mut watcher, _) = new_watcher();
+        *api.tip_height.lock().unwrap() = 101;
+        let events = watcher.poll().await.unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [WormholeDepositEvent::DepositConfirmed { confirmations: 6, .. }]
+        ));
+        watcher.commit().unwrap();
+        assert!(watcher.poll().await.unwrap().is_empty());
+
+        api.utxos.lock().unwrap().insert(address, vec![]);
+        let events = watcher.poll().await.unwrap();
+        assert!(matches!(events.as_slice(), [WormholeDepositEvent::DepositSpent(_)]));
+        watcher.commit().unwrap();
+        assert!(watcher.get_checkpoint().deposits.is_empty());
+        std::fs::remove_file(&checkpoint_path).unwrap();
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{
+    dogecoin::{
+        constants::DogeNetworkConfig, relay_policy::DogeRelayPolicy, transaction::BTCTransactionOutput,
+        transaction_layout::BTCTransactionLayout,
+    },
+    wormhole::{
+        p2sh_vaa::{WormholeBitcoinLikeVAAMetadata, WormholeGuardianMultisig},
+        template_registry::{
+            WormholeScriptTemplateRegistry, WormholeVAAScriptTemplate, get_builtin_script_template_registry,
+        },
+    },
+};
+
+// The signed size range of a VAA spend, the exact size depends on the DER length of each guardian signature
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct WormholeVAASpendSize {
+    pub min_size: usize,
+    pub max_size: usize,
+}
+
+impl WormholeVAASpendSize {
+    // size of a spend of input_count utxos locked by the builtin template selected by metadata.template_version
+    pub fn estimate(
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        input_count: usize,
+        output_scripts: &[Vec<u8>],
+    ) -> anyhow::Result<Self> {
+        Self::estimate_with_registry(get_builtin_script_template_registry(), metadata, input_count, output_scripts)
+    }
+    pub fn estimate_with_registry(
+        registry: &WormholeScriptTemplateRegistry,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        input_count: usize,
+        output_scripts: &[Vec<u8>],
+    ) -> anyhow::Result<Self> {
+        Self::estimate_with_template(registry.get(metadata.template_version)?, metadata, input_count, output_scripts)
+    }
+    pub fn estimate_with_template(
+        template: &dyn WormholeVAAScriptTemplate,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        input_count: usize,
+        output_scripts: &[Vec<u8>],
+    ) -> anyhow::Result<Self> {
+        Self::from_script_sig_sizes(
+            template.estimate_min_script_sig_size(metadata)?,
+            template.estimate_script_sig_size(metadata)?,
+            input_count,
+            output_scripts,
+        )
+    }
+    // size of a spend of input_count utxos locked to multisig by the builtin multisig template of metadata.template_version
+    pub fn estimate_multisig(
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        multisig: &WormholeGuardianMultisig,
+        input_count: usize,
+        output_scripts: &[Vec<u8>],
+    ) -> anyhow::Result<Self> {
+        Self::estimate_multisig_with_registry(
+            get_builtin_script_template_registry(),
+            metadata,
+            multisig,
+            input_count,
+            output_scripts,
+        )
+    }
+    // every input carries m signatures, each 71 to 73 bytes pushed
+    pub fn estimate_multisig_with_registry(
+        registry: &WormholeScriptTemplateRegistry,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        multisig: &WormholeGuardianMultisig,
+        input_count: usize,
+        output_scripts: &[Vec<u8>],
+    ) -> anyhow::Result<Self> {
+        let template = registry.get_multisig(metadata.template_version)?;
+        Self::from_script_sig_sizes(
+            template.estimate_min_script_sig_size(metadata, multisig)?,
+            template.estimate_script_sig_size(metadata, multisig)?,
+            input_count,
+            output_scripts,
+        )
+    }
+    fn from_script_sig_sizes(
+        min_script_sig_size: usize,
+        max_script_sig_size: usize,
+        input_count: usize,
+        output_scripts: &[Vec<u8>],
+    ) -> anyhow::Result<Self> {
+        if input_count == 0 {
+            anyhow::bail!("a vaa spend needs at least one input");
+        }
+        let output_script_sizes = output_scripts.iter().map(|script| script.len()).collect::<Vec<usize>>();
+        Ok(Self {
+            min_size: BTCTransactionLayout::new(vec![min_script_sig_size; input_count], output_script_sizes.clone())
+                .get_total_size(),
+            max_size: BTCTransactionLayout::new(vec![max_script_sig_size; input_count], output_script_sizes)
+                .get_total_size(),
+        })
+    }
+    // fee_rate in koinu per byte, both bounds are raised to what the relay policy requires for the largest signing
+    pub fn get_fee_bounds(&self, policy: &DogeRelayPolicy, fee_rate: u64) -> WormholeVAAFeeBounds {
+        let relay_fee = policy.get_min_relay_fee(self.max_size);
+        let min_fee = (self.min_size as u64).saturating_mul(fee_rate).max(relay_fee);
+        let max_fee = (self.max_size as u64).saturating_mul(fee_rate).max(min_fee);
+        WormholeVAAFeeBounds {
+            min_doge_transaction_fee: min_fee,
+            max_doge_transaction_fee: max_fee,
+        }
+    }
+}
+
+// The min/max fee fields an emitter contract should set for a spend
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct WormholeVAAFeeBounds {
+    pub min_doge_transaction_fee: u64,
+    pub max_doge_transaction_fee: u64,
+}
+
+impl WormholeVAAFeeBounds {
+    pub fn contains(&self, fee: u64) -> bool {
+        fee >= self.min_doge_transaction_fee && fee <= self.max_doge_transaction_fee
+    }
+    // raises both bounds by the soft dust fee policy charges for outputs
+    pub fn with_dust_fee(self, policy: &DogeRelayPolicy, outputs: &[BTCTransactionOutput]) -> Self {
+        let dust_fee = policy.get_dust_fee(outputs);
+        Self {
+            min_doge_transaction_fee: self.min_doge_transaction_fee.saturating_add(dust_fee),
+            max_doge_transaction_fee: self.max_doge_transaction_fee.saturating_add(dust_fee),
+        }
+    }
+    pub fn apply_to_metadata(&self, metadata: &WormholeBitcoinLikeVAAMetadata) -> WormholeBitcoinLikeVAAMetadata {
+        WormholeBitcoinLikeVAAMetadata {
+            min_doge_transaction_fee: self.min_doge_transaction_fee,
+            max_doge_transaction_fee: self.max_doge_transaction_fee,
+            ..*metadata
+        }
+    }
+}
+
+// fee fields for a spend that guardians on network N accept, fee_rate in koinu per byte
+pub fn recommend_vaa_fee_bounds<N: DogeNetworkConfig>(
+    metadata: &WormholeBitcoinLikeVAAMetadata,
+    input_count: usize,
+    output_scripts: &[Vec<u8>],
+    fee_rate: u64,
+) -> anyhow::Result<WormholeVAAFeeBounds> {
+    Ok(WormholeVAASpendSize::estimate(metadata, input_count, output_scripts)?.get_fee_bounds(&N::RELAY_POLICY, fee_rate))
+}
+
+// recommend_vaa_fee_bounds for spends of the m-of-n guardian multisig
+pub fn recommend_vaa_multisig_fee_bounds<N: DogeNetworkConfig>(
+    metadata: &WormholeBitcoinLikeVAAMetadata,
+    multisig: &WormholeGuardianMultisig,
+    input_count: usize,
+    output_scripts: &[Vec<u8>],
+    fee_rate: u64,
+) -> anyhow::Result<WormholeVAAFeeBounds> {
+    Ok(WormholeVAASpendSize::estimate_multisig(metadata, multisig, input_count, output_scripts)?
+        .get_fee_bounds(&N::RELAY_POLICY, fee_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dogecoin::{
+            constants::DogeTestNetConfig,
+            hash::CommonDogeHashProvider,
+            transaction::{BTCTransaction, BTCTransactionInput},
+        },
+        secp256k1::signature::PsyCompressedSecp256K1Signature,
+        wormhole::{
+            p2sh_vaa::WormholeRecoveryPath,
+            template_registry::WormholeGuardianKey,
+            test_utils::example_metadata,
+        },
+    };
+
+    #[test]
+    fn test_spend_size_matches_signed_transactions() {
+        let public_key = [0x02u8; 33];
+        let guardian_key = WormholeGuardianKey::new_with_public_key::<CommonDogeHashProvider>(public_key);
+        // r with the high bit set and a full s (73 byte push), a full r and s (72) and a 31 byte s (71)
+        let mut short_s = [0x01u8; 32];
+        short_s[0] = 0;
+        let signatures = [([0x80u8; 32], [0x01u8; 32]), ([0x01; 32], [0x01; 32]), ([0x01; 32], short_s)]
+            .map(|(r, s)| PsyCompressedSecp256K1Signature {
+                public_key,
+                signature: [r, s].concat().try_into().unwrap(),
+                message: [0u8; 32],
+            });
+        let output_scripts = vec![vec![0x51u8; 25], vec![0x6a, 0x01, 0xff]];
+        let outputs = output_scripts
+            .iter()
+            .map(|script| BTCTransactionOutput { value: 1, script: script.clone() })
+            .collect::<Vec<BTCTransactionOutput>>();
+
+        let registry = get_builtin_script_template_registry();
+        for template_version in registry.get_template_versions() {
+            for recovery in [None, Some(WormholeRecoveryPath { lock_time: 5_000_000, recovery_public_key_hash: [3u8; 20] })] {
+                let metadata = WormholeBitcoinLikeVAAMetadata {
+                    emitter_chain: 300,
+                    total_output_amount: 2,
+                    template_version,
+                    recovery,
+                    ..example_metadata()
+                };
+                let template = registry.get(template_version).unwrap();
+                let redeem_script = template.build_locking_script(&metadata, &guardian_key).unwrap();
+                let size = WormholeVAASpendSize::estimate(&metadata, 3, &output_scripts).unwrap();
+                let signed_sizes = signatures
+                    .iter()
+                    .map(|signature| {
+                        let input = BTCTransactionInput {
+                            script: template.build_script_sig(&metadata, signature, &redeem_script),
+                            ..Default::default()
+                        };
+                        BTCTransaction::from_io(vec![input; 3], outputs.clone()).to_bytes().len()
+                    })
+                    .collect::<Vec<usize>>();
+                assert_eq!(signed_sizes, vec![size.max_size, size.max_size - 3, size.min_size]);
+
+                let bounds = recommend_vaa_fee_bounds::<DogeTestNetConfig>(&metadata, 3, &output_scripts, 1000).unwrap();
+                assert_eq!(bounds.min_doge_transaction_fee, size.min_size as u64 * 1000);
+                assert_eq!(bounds.max_doge_transaction_fee, size.max_size as u64 * 1000);
+                assert_eq!(bounds.apply_to_metadata(&metadata).max_doge_transaction_fee, bounds.max_doge_transaction_fee);
+                // below the relay floor both bounds are raised to the relay fee of the largest signing
+                let relay_fee = DogeTestNetConfig::RELAY_POLICY.get_min_relay_fee(size.max_size);
+                let bounds = size.get_fee_bounds(&DogeTestNetConfig::RELAY_POLICY, 1);
+                assert!(bounds.contains(relay_fee) && !bounds.contains(relay_fee - 1) && !bounds.contains(relay_fee + 1));
+                let soft_dust_fee = DogeTestNetConfig::RELAY_POLICY.soft_dust_limit;
+                let bounds = bounds.with_dust_fee(&DogeTestNetConfig::RELAY_POLICY, &outputs);
+                assert_eq!(bounds.min_doge_transaction_fee, relay_fee + soft_dust_fee);
+            }
+        }
+        // multisig inputs carry m signatures of 71 to 73 bytes each
+        let multisig = WormholeGuardianMultisig::new(2, vec![[0x02; 33], [0x03; 33], [0x04; 33]]).unwrap();
+        for template_version in registry.get_multisig_template_versions() {
+            let metadata =
+                WormholeBitcoinLikeVAAMetadata { emitter_chain: 300, template_version, ..example_metadata() };
+            let template = registry.get_multisig(template_version).unwrap();
+            let redeem_script = template.build_locking_script(&metadata, &multisig).unwrap();
+            let size = WormholeVAASpendSize::estimate_multisig(&metadata, &multisig, 3, &output_scripts).unwrap();
+            let signed_sizes = signatures
+                .iter()
+                .map(|signature| {
+                    let input = BTCTransactionInput {
+                        script: template.build_script_sig(&[signature, signature], &redeem_script),
+                        ..Default::default()
+                    };
+                    BTCTransaction::from_io(vec![input; 3], outputs.clone()).to_bytes().len()
+                })
+                .collect::<Vec<usize>>();
+            assert_eq!(signed_sizes, vec![size.max_size, size.max_size - 6, size.min_size]);
+            let bounds =
+                recommend_vaa_multisig_fee_bounds::<DogeTestNetConfig>(&metadata, &multisig, 3, &output_scripts, 1000)
+                    .unwrap();
+            assert_eq!(bounds.max_doge_transaction_fee, size.max_size as u64 * 1000);
+        }
+
+        let unknown_template = WormholeBitcoinLikeVAAMetadata { template_version: 3, ..example_metadata() };
+        assert!(WormholeVAASpendSize::estimate(&unknown_template, 1, &output_scripts).is_err());
+    }
+}
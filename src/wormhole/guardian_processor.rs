@@ -1,48 +1,687 @@
 use crate::{
     dogecoin::{
         constants::DogeNetworkConfig,
-        hash::{DogeHashProvider, QHash256},
-        transaction::{BTCTransaction, encode_binary_stack_item},
+        hash::{DogeHashProvider, Keccak256HashProvider, QHash256},
+        script::interpreter::verify_transaction_input,
+        transaction::{BTCTransaction, BTCTransactionOutput},
+    },
+    secp256k1::{
+        guardian_set::WormholeGuardianSetRegistry, signature::PsyCompressedSecp256K1Signature,
+        verifier::verify_signature_for_key_and_message,
     },
     tx_store::traits::{DogecoinRPCProviderAsync, DogecoinRPCProviderSync},
     wormhole::{
-        p2sh_vaa::WormholeBitcoinLikeVAAMessage,
-        tss_signer::{WormholeTSSSignerAsync, WormholeTSSSignerSync},
+        consolidation::check_consolidation,
+        p2sh_vaa::{WormholeBitcoinLikeVAAMessage, WormholeBitcoinLikeVAAMessageKind, WormholeGuardianMultisig},
+        processed_message_store::{ProcessedMessageRecord, ProcessedMessageStore},
+        template_registry::{WormholeGuardianKey, WormholeScriptTemplateRegistry},
+        tss_key_set::{WormholeTSSKey, WormholeTSSKeySet},
+        tss_signer::{
+            WithTSSPublicKey, WormholeMultisigSignerAsync, WormholeMultisigSignerSync, WormholeTSSSignerAsync,
+            WormholeTSSSignerSync,
+        },
+        vaa::{WormholeVAA, WormholeVAAId},
     },
 };
 
-pub struct WormholeGuardianProcessorAsync<
-    RPC: DogecoinRPCProviderAsync,
-    Signer: WormholeTSSSignerAsync,
-> {
-    tss_public_key_hash: [u8; 20],
-    rpc_provider: RPC,
+fn current_unix_timestamp() -> anyhow::Result<u32> {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+    Ok(now.as_secs() as u32)
+}
+
+// A validated message waiting for its signatures
+#[derive(PartialEq, Clone, Debug)]
+pub struct WormholeSpendSigningRequest {
+    pub message: WormholeBitcoinLikeVAAMessage,
+    // sighashes[i] must be signed for spend_transaction.inputs[i]
+    pub sighashes: Vec<QHash256>,
+    // the guardian tss key hash that spend_transaction.inputs[i] is locked to (empty for multisig requests)
+    pub public_key_hashes: Vec<[u8; 20]>,
+    pub spend_transaction: BTCTransaction,
+    // the outputs being spent, spent_outputs[i] is spent by spend_transaction.inputs[i]
+    pub spent_outputs: Vec<BTCTransactionOutput>,
+    // digest under which the message was recorded in the processed message store, if one is configured
+    pub reservation: Option<QHash256>,
+}
+
+// A signing failure before any signer was asked for a signature, the only failure that releases the reservation
+#[derive(Debug)]
+pub struct WormholeNothingSignedError(pub anyhow::Error);
+
+impl std::fmt::Display for WormholeNothingSignedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "nothing was signed: {}", self.0)
+    }
+}
+
+impl std::error::Error for WormholeNothingSignedError {}
+
+// The I/O free part of the guardian processor shared by the sync and async front-ends:
+// validate -> sighashes -> (front-end signs) -> assemble scriptSigs
+pub struct WormholeGuardianProcessorCore {
+    tss_key_set: WormholeTSSKeySet,
+    multisig: Option<WormholeGuardianMultisig>,
+    guardian_set_registry: Option<WormholeGuardianSetRegistry>,
+    processed_message_store: Option<Box<dyn ProcessedMessageStore + Send + Sync>>,
+    script_verify_flags: Option<u32>,
+    script_template_registry: WormholeScriptTemplateRegistry,
+}
+
+impl WormholeGuardianProcessorCore {
+    pub fn new(tss_public_key_hash: [u8; 20]) -> Self {
+        Self::new_with_tss_key(WormholeTSSKey::new(tss_public_key_hash))
+    }
+    // a key with its full public key can also spend the P2PK VAA templates
+    pub fn new_with_tss_key(tss_key: WormholeTSSKey) -> Self {
+        let mut tss_key_set = WormholeTSSKeySet::new();
+        tss_key_set.add_key(tss_key).expect("an empty key set accepts any key");
+        Self {
+            tss_key_set,
+            multisig: None,
+            guardian_set_registry: None,
+            processed_message_store: None,
+            script_verify_flags: None,
+            script_template_registry: WormholeScriptTemplateRegistry::new_with_builtin_templates(),
+        }
+    }
+    // a core for deployments that lock deposits to an m-of-n multisig instead of a tss key
+    pub fn new_multisig(multisig: WormholeGuardianMultisig) -> Self {
+        Self {
+            tss_key_set: WormholeTSSKeySet::new(),
+            multisig: Some(multisig),
+            guardian_set_registry: None,
+            processed_message_store: None,
+            script_verify_flags: None,
+            script_template_registry: WormholeScriptTemplateRegistry::new_with_builtin_templates(),
+        }
+    }
+    pub fn get_multisig(&self) -> Option<&WormholeGuardianMultisig> {
+        self.multisig.as_ref()
+    }
+    pub fn get_tss_key_set(&self) -> &WormholeTSSKeySet {
+        &self.tss_key_set
+    }
+    // inputs locked to any key active in the set are accepted, e.g. both the old and new key during a rotation
+    pub fn set_tss_key_set(&mut self, tss_key_set: WormholeTSSKeySet) {
+        self.tss_key_set = tss_key_set;
+    }
+    pub fn get_script_template_registry(&self) -> &WormholeScriptTemplateRegistry {
+        &self.script_template_registry
+    }
+    // messages whose template version is not in the registry are rejected before anything is signed
+    pub fn set_script_template_registry(&mut self, script_template_registry: WormholeScriptTemplateRegistry) {
+        self.script_template_registry = script_template_registry;
+    }
+    pub fn set_guardian_set_registry(&mut self, guardian_set_registry: WormholeGuardianSetRegistry) {
+        self.guardian_set_registry = Some(guardian_set_registry);
+    }
+    pub fn set_processed_message_store<Store: ProcessedMessageStore + Send + Sync + 'static>(
+        &mut self,
+        processed_message_store: Store,
+    ) {
+        self.processed_message_store = Some(Box::new(processed_message_store));
+    }
+    // run every assembled input through the script interpreter with these flags before returning the transaction
+    pub fn set_script_verify_flags(&mut self, script_verify_flags: u32) {
+        self.script_verify_flags = Some(script_verify_flags);
+    }
+
+    // verifies the guardian quorum on a VAA and decodes its payload, sighashes are only computed for messages that pass
+    pub fn verify_vaa_and_get_message<Hasher: Keccak256HashProvider>(
+        &self,
+        vaa: &WormholeVAA,
+    ) -> anyhow::Result<WormholeBitcoinLikeVAAMessage> {
+        match self.verify_vaa_and_get_message_with_kind::<Hasher>(vaa)? {
+            (WormholeBitcoinLikeVAAMessageKind::Spend, message) => Ok(message),
+            (kind, _) => anyhow::bail!("expected a spend VAA but got a {:?} VAA", kind),
+        }
+    }
+    // verify_vaa_and_get_message for VAAs of any message kind
+    pub fn verify_vaa_and_get_message_with_kind<Hasher: Keccak256HashProvider>(
+        &self,
+        vaa: &WormholeVAA,
+    ) -> anyhow::Result<(WormholeBitcoinLikeVAAMessageKind, WormholeBitcoinLikeVAAMessage)> {
+        let guardian_set_registry = self.guardian_set_registry.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("no guardian set registry configured, cannot verify VAA signatures")
+        })?;
+        guardian_set_registry.verify_vaa::<Hasher>(vaa, current_unix_timestamp()?)?;
+        vaa.to_bitcoin_like_vaa_message_with_kind()
+    }
+
+    // validates the message against the transactions it spends and records it in the processed message store
+    pub fn prepare_signing_request<Hasher: DogeHashProvider, N: DogeNetworkConfig>(
+        &self,
+        message: WormholeBitcoinLikeVAAMessage,
+        input_transactions: &[BTCTransaction],
+        vaa_id: Option<WormholeVAAId>,
+    ) -> anyhow::Result<WormholeSpendSigningRequest> {
+        self.prepare_signing_request_with_kind::<Hasher, N>(
+            message,
+            WormholeBitcoinLikeVAAMessageKind::Spend,
+            input_transactions,
+            vaa_id,
+        )
+    }
+
+    // prepare_signing_request that also enforces the rules of the message kind, see consolidation::check_consolidation
+    pub fn prepare_signing_request_with_kind<Hasher: DogeHashProvider, N: DogeNetworkConfig>(
+        &self,
+        message: WormholeBitcoinLikeVAAMessage,
+        kind: WormholeBitcoinLikeVAAMessageKind,
+        input_transactions: &[BTCTransaction],
+        vaa_id: Option<WormholeVAAId>,
+    ) -> anyhow::Result<WormholeSpendSigningRequest> {
+        let template = self.script_template_registry.get(message.metadata.template_version)?;
+        // keys without a known public key cannot be used with templates that embed it and are skipped for them
+        let (redeem_scripts, active_key_hashes): (Vec<Vec<u8>>, Vec<[u8; 20]>) = self
+            .tss_key_set
+            .get_active_keys(current_unix_timestamp()?)
+            .iter()
+            .filter_map(|key| {
+                let guardian_key = WormholeGuardianKey {
+                    public_key_hash: key.public_key_hash,
+                    public_key: key.public_key,
+                };
+                let redeem_script = template.build_locking_script(&message.metadata, &guardian_key).ok()?;
+                Some((redeem_script, key.public_key_hash))
+            })
+            .unzip();
+        if redeem_scripts.is_empty() {
+            anyhow::bail!("no active tss key can spend template version {}", message.metadata.template_version);
+        }
+        let (sighashes, script_positions, spend_transaction) =
+            message.validate_and_get_sighashes_for_redeem_scripts::<Hasher>(input_transactions, &redeem_scripts)?;
+        if kind == WormholeBitcoinLikeVAAMessageKind::Consolidation {
+            check_consolidation::<Hasher>(&message, &redeem_scripts, &script_positions)?;
+        }
+        let script_sig_size = template.estimate_script_sig_size(&message.metadata)?;
+        message.check_relay_policy(&N::RELAY_POLICY, input_transactions, &vec![script_sig_size; message.inputs.len()])?;
+        let public_key_hashes = script_positions
+            .into_iter()
+            .map(|position| active_key_hashes[position])
+            .collect();
+        self.reserve_signing_request::<Hasher>(
+            message,
+            input_transactions,
+            vaa_id,
+            sighashes,
+            public_key_hashes,
+            spend_transaction,
+        )
+    }
+
+    // prepare_signing_request for messages spending the configured m-of-n multisig VAA-P2SH outputs
+    pub fn prepare_multisig_signing_request<Hasher: DogeHashProvider, N: DogeNetworkConfig>(
+        &self,
+        message: WormholeBitcoinLikeVAAMessage,
+        input_transactions: &[BTCTransaction],
+        vaa_id: Option<WormholeVAAId>,
+    ) -> anyhow::Result<WormholeSpendSigningRequest> {
+        self.prepare_multisig_signing_request_with_kind::<Hasher, N>(
+            message,
+            WormholeBitcoinLikeVAAMessageKind::Spend,
+            input_transactions,
+            vaa_id,
+        )
+    }
+
+    pub fn prepare_multisig_signing_request_with_kind<Hasher: DogeHashProvider, N: DogeNetworkConfig>(
+        &self,
+        message: WormholeBitcoinLikeVAAMessage,
+        kind: WormholeBitcoinLikeVAAMessageKind,
+        input_transactions: &[BTCTransaction],
+        vaa_id: Option<WormholeVAAId>,
+    ) -> anyhow::Result<WormholeSpendSigningRequest> {
+        let multisig = self
+            .multisig
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("no guardian multisig configured"))?;
+        let template = self.script_template_registry.get_multisig(message.metadata.template_version)?;
+        let redeem_scripts = vec![template.build_locking_script(&message.metadata, multisig)?];
+        let (sighashes, script_positions, spend_transaction) =
+            message.validate_and_get_sighashes_for_redeem_scripts::<Hasher>(input_transactions, &redeem_scripts)?;
+        if kind == WormholeBitcoinLikeVAAMessageKind::Consolidation {
+            check_consolidation::<Hasher>(&message, &redeem_scripts, &script_positions)?;
+        }
+        let script_sig_size = template.estimate_script_sig_size(&message.metadata, multisig)?;
+        message.check_relay_policy(&N::RELAY_POLICY, input_transactions, &vec![script_sig_size; message.inputs.len()])?;
+        self.reserve_signing_request::<Hasher>(
+            message,
+            input_transactions,
+            vaa_id,
+            sighashes,
+            vec![],
+            spend_transaction,
+        )
+    }
+
+    fn reserve_signing_request<Hasher: DogeHashProvider>(
+        &self,
+        message: WormholeBitcoinLikeVAAMessage,
+        input_transactions: &[BTCTransaction],
+        vaa_id: Option<WormholeVAAId>,
+        sighashes: Vec<QHash256>,
+        public_key_hashes: Vec<[u8; 20]>,
+        spend_transaction: BTCTransaction,
+    ) -> anyhow::Result<WormholeSpendSigningRequest> {
+        if sighashes.len() != message.inputs.len() {
+            anyhow::bail!("Invalid number of sighashes returned");
+        }
+        let spent_outputs = input_transactions
+            .iter()
+            .zip(message.inputs.iter())
+            .map(|(input_transaction, input)| {
+                input_transaction
+                    .outputs
+                    .get(input.index as usize)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("input transaction has no output {}", input.index))
+            })
+            .collect::<anyhow::Result<Vec<BTCTransactionOutput>>>()?;
+        let reservation = match self.processed_message_store.as_deref() {
+            Some(store) => {
+                // rejects replays and outpoints spent by an earlier message before anything is signed
+                let record = ProcessedMessageRecord::from_message::<Hasher>(&message, vaa_id)?;
+                store.check_and_record(&record)?;
+                Some(record.message_digest)
+            }
+            None => None,
+        };
+        Ok(WormholeSpendSigningRequest {
+            message,
+            sighashes,
+            public_key_hashes,
+            spend_transaction,
+            spent_outputs,
+            reservation,
+        })
+    }
+
+    pub fn assemble_signed_transaction<Hasher: DogeHashProvider>(
+        &self,
+        request: &WormholeSpendSigningRequest,
+        signatures: &[PsyCompressedSecp256K1Signature],
+    ) -> anyhow::Result<BTCTransaction> {
+        if signatures.len() != request.sighashes.len() {
+            anyhow::bail!(
+                "expected {} signatures but got {}",
+                request.sighashes.len(),
+                signatures.len()
+            );
+        }
+        // never embed a signature from a faulty or malicious signing round
+        for (input_index, (signature, sighash)) in signatures.iter().zip(request.sighashes.iter()).enumerate() {
+            verify_signature_for_key_and_message::<Hasher>(signature, &request.public_key_hashes[input_index], sighash)
+                .map_err(|e| anyhow::Error::new(e).context(format!("rejected signature for input {}", input_index)))?;
+        }
+
+        let metadata = &request.message.metadata;
+        let template = self.script_template_registry.get(metadata.template_version)?;
+        let mut spend_transaction = request.spend_transaction.clone();
+        for (input, signature) in spend_transaction.inputs.iter_mut().zip(signatures.iter()) {
+            // the signature key was checked against the input's key hash above
+            let guardian_key = WormholeGuardianKey::new_with_public_key::<Hasher>(signature.public_key);
+            let redeem_script = template.build_locking_script(metadata, &guardian_key)?;
+            input.script = template.build_script_sig(metadata, signature, &redeem_script);
+        }
+        self.verify_signed_transaction::<Hasher>(request, spend_transaction)
+    }
+
+    // scriptSig = OP_0 <sig 1> .. <sig m> <redeem script>, signatures are the ones collected for each input in any order
+    pub fn assemble_multisig_signed_transaction<Hasher: DogeHashProvider>(
+        &self,
+        request: &WormholeSpendSigningRequest,
+        signatures: &[Vec<PsyCompressedSecp256K1Signature>],
+    ) -> anyhow::Result<BTCTransaction> {
+        let multisig = self
+            .multisig
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("no guardian multisig configured"))?;
+        if signatures.len() != request.sighashes.len() {
+            anyhow::bail!(
+                "expected signatures for {} inputs but got {}",
+                request.sighashes.len(),
+                signatures.len()
+            );
+        }
+        let template = self.script_template_registry.get_multisig(request.message.metadata.template_version)?;
+        let redeem_script = template.build_locking_script(&request.message.metadata, multisig)?;
+
+        let mut spend_transaction = request.spend_transaction.clone();
+        for (input_index, (input, input_signatures)) in
+            spend_transaction.inputs.iter_mut().zip(signatures.iter()).enumerate()
+        {
+            // CHECKMULTISIG walks the keys in order, so the signatures must follow the key order
+            let mut valid_signatures = Vec::with_capacity(multisig.required_signatures);
+            for signature in input_signatures.iter() {
+                let Some(key_position) = multisig.get_key_position(&signature.public_key) else {
+                    continue;
+                };
+                let public_key_hash = Hasher::bitcoin_hash160(&signature.public_key);
+                if valid_signatures.iter().any(|(position, _)| *position == key_position)
+                    || verify_signature_for_key_and_message::<Hasher>(
+                        signature,
+                        &public_key_hash,
+                        &request.sighashes[input_index],
+                    )
+                    .is_err()
+                {
+                    continue;
+                }
+                valid_signatures.push((key_position, signature));
+            }
+            if valid_signatures.len() < multisig.required_signatures {
+                anyhow::bail!(
+                    "input {} only has {} of the {} required valid signatures",
+                    input_index,
+                    valid_signatures.len(),
+                    multisig.required_signatures
+                );
+            }
+            valid_signatures.sort_by_key(|(position, _)| *position);
+            let ordered_signatures = valid_signatures
+                .iter()
+                .take(multisig.required_signatures)
+                .map(|(_, signature)| *signature)
+                .collect::<Vec<&PsyCompressedSecp256K1Signature>>();
+            input.script = template.build_script_sig(&ordered_signatures, &redeem_script);
+        }
+        self.verify_signed_transaction::<Hasher>(request, spend_transaction)
+    }
+
+    fn verify_signed_transaction<Hasher: DogeHashProvider>(
+        &self,
+        request: &WormholeSpendSigningRequest,
+        spend_transaction: BTCTransaction,
+    ) -> anyhow::Result<BTCTransaction> {
+        if let Some(flags) = self.script_verify_flags {
+            for (input_index, spent_output) in request.spent_outputs.iter().enumerate() {
+                verify_transaction_input::<Hasher>(&spend_transaction, input_index, &spent_output.script, flags)
+                    .map_err(|e| anyhow::anyhow!("signed input {} failed script verification: {}", input_index, e))?;
+            }
+        }
+        Ok(spend_transaction)
+    }
+
+    /*
+    Releases the processed message reservation if signing failed before any signer was called, so the message
+    can be retried. Once a signature may exist, another message over the same outpoints could double spend them,
+    the reservation is kept and must be released by hand (ProcessedMessageStore::release) after checking the chain.
+    */
+    pub fn finish_signing_request<T>(
+        &self,
+        request: &WormholeSpendSigningRequest,
+        result: anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let (Err(error), Some(store), Some(message_digest)) =
+            (&result, self.processed_message_store.as_deref(), request.reservation)
+        else {
+            return result;
+        };
+        if error.downcast_ref::<WormholeNothingSignedError>().is_some() {
+            store.release(&message_digest)?;
+            return result;
+        }
+        result.map_err(|error| {
+            error.context(format!(
+                "signing failed after signatures were requested, message {} stays reserved",
+                hex::encode(message_digest)
+            ))
+        })
+    }
+}
+
+// How a front-end signs the requests its core prepares, see WormholeTSSSigning and WormholeMultisigSigning
+pub trait WormholeSigningStrategy {
+    fn prepare_signing_request<Hasher: DogeHashProvider, N: DogeNetworkConfig>(
+        &self,
+        core: &WormholeGuardianProcessorCore,
+        message: WormholeBitcoinLikeVAAMessage,
+        kind: WormholeBitcoinLikeVAAMessageKind,
+        input_transactions: &[BTCTransaction],
+        vaa_id: Option<WormholeVAAId>,
+    ) -> anyhow::Result<WormholeSpendSigningRequest>;
+}
+
+#[async_trait::async_trait]
+pub trait WormholeSigningStrategyAsync: WormholeSigningStrategy + Sync {
+    async fn sign_request_async<Hasher: DogeHashProvider>(
+        &self,
+        core: &WormholeGuardianProcessorCore,
+        request: &WormholeSpendSigningRequest,
+    ) -> anyhow::Result<BTCTransaction>;
+}
+
+pub trait WormholeSigningStrategySync: WormholeSigningStrategy {
+    fn sign_request_sync<Hasher: DogeHashProvider>(
+        &self,
+        core: &WormholeGuardianProcessorCore,
+        request: &WormholeSpendSigningRequest,
+    ) -> anyhow::Result<BTCTransaction>;
+}
+
+// every input is signed by the tss signer of the key it is locked to
+pub struct WormholeTSSSigning<Signer> {
+    // (hash160 of the tss public key, signer)
+    signers: Vec<([u8; 20], Signer)>,
+}
+
+impl<Signer> WormholeTSSSigning<Signer> {
+    fn get_signer(&self, tss_public_key_hash: &[u8; 20]) -> anyhow::Result<&Signer> {
+        self.signers
+            .iter()
+            .find(|(key_hash, _)| key_hash == tss_public_key_hash)
+            .map(|(_, signer)| signer)
+            .ok_or_else(|| anyhow::anyhow!("no signer for tss key {}", hex::encode(tss_public_key_hash)))
+    }
+    // the signer of every input, looked up before the first one is asked to sign
+    fn get_request_signers(&self, request: &WormholeSpendSigningRequest) -> anyhow::Result<Vec<&Signer>> {
+        request
+            .public_key_hashes
+            .iter()
+            .map(|public_key_hash| self.get_signer(public_key_hash))
+            .collect::<anyhow::Result<Vec<&Signer>>>()
+            .map_err(|error| anyhow::Error::new(WormholeNothingSignedError(error)))
+    }
+}
+
+impl<Signer> WormholeSigningStrategy for WormholeTSSSigning<Signer> {
+    fn prepare_signing_request<Hasher: DogeHashProvider, N: DogeNetworkConfig>(
+        &self,
+        core: &WormholeGuardianProcessorCore,
+        message: WormholeBitcoinLikeVAAMessage,
+        kind: WormholeBitcoinLikeVAAMessageKind,
+        input_transactions: &[BTCTransaction],
+        vaa_id: Option<WormholeVAAId>,
+    ) -> anyhow::Result<WormholeSpendSigningRequest> {
+        core.prepare_signing_request_with_kind::<Hasher, N>(message, kind, input_transactions, vaa_id)
+    }
+}
+
+#[async_trait::async_trait]
+impl<Signer: WormholeTSSSignerAsync + Sync> WormholeSigningStrategyAsync for WormholeTSSSigning<Signer> {
+    async fn sign_request_async<Hasher: DogeHashProvider>(
+        &self,
+        core: &WormholeGuardianProcessorCore,
+        request: &WormholeSpendSigningRequest,
+    ) -> anyhow::Result<BTCTransaction> {
+        let signers = self.get_request_signers(request)?;
+        let mut signatures = Vec::with_capacity(request.sighashes.len());
+        for (sighash, signer) in request.sighashes.iter().zip(signers) {
+            let signature = signer
+                .sign_message_tss_and_broadcast_to_other_guardians_and_wait_for_signature(*sighash)
+                .await?;
+            signatures.push(signature);
+        }
+        core.assemble_signed_transaction::<Hasher>(request, &signatures)
+    }
+}
+
+impl<Signer: WormholeTSSSignerSync> WormholeSigningStrategySync for WormholeTSSSigning<Signer> {
+    fn sign_request_sync<Hasher: DogeHashProvider>(
+        &self,
+        core: &WormholeGuardianProcessorCore,
+        request: &WormholeSpendSigningRequest,
+    ) -> anyhow::Result<BTCTransaction> {
+        let signers = self.get_request_signers(request)?;
+        let signatures = request
+            .sighashes
+            .iter()
+            .zip(signers)
+            .map(|(sighash, signer)| {
+                signer.sign_message_tss_and_broadcast_to_other_guardians_and_wait_for_signature_sync(*sighash)
+            })
+            .collect::<anyhow::Result<Vec<PsyCompressedSecp256K1Signature>>>()?;
+        core.assemble_signed_transaction::<Hasher>(request, &signatures)
+    }
+}
+
+// every input spends the configured m-of-n guardian multisig, signatures are collected by the multisig signer
+pub struct WormholeMultisigSigning<Signer> {
     signer: Signer,
 }
 
-impl<RPC: DogecoinRPCProviderAsync + Sync, Signer: WormholeTSSSignerAsync>
-    WormholeGuardianProcessorAsync<RPC, Signer>
-{
+impl<Signer> WormholeSigningStrategy for WormholeMultisigSigning<Signer> {
+    fn prepare_signing_request<Hasher: DogeHashProvider, N: DogeNetworkConfig>(
+        &self,
+        core: &WormholeGuardianProcessorCore,
+        message: WormholeBitcoinLikeVAAMessage,
+        kind: WormholeBitcoinLikeVAAMessageKind,
+        input_transactions: &[BTCTransaction],
+        vaa_id: Option<WormholeVAAId>,
+    ) -> anyhow::Result<WormholeSpendSigningRequest> {
+        core.prepare_multisig_signing_request_with_kind::<Hasher, N>(message, kind, input_transactions, vaa_id)
+    }
+}
+
+#[async_trait::async_trait]
+impl<Signer: WormholeMultisigSignerAsync + Sync> WormholeSigningStrategyAsync for WormholeMultisigSigning<Signer> {
+    async fn sign_request_async<Hasher: DogeHashProvider>(
+        &self,
+        core: &WormholeGuardianProcessorCore,
+        request: &WormholeSpendSigningRequest,
+    ) -> anyhow::Result<BTCTransaction> {
+        let mut signatures = Vec::with_capacity(request.sighashes.len());
+        for sighash in request.sighashes.iter() {
+            signatures.push(self.signer.collect_multisig_signatures(*sighash).await?);
+        }
+        core.assemble_multisig_signed_transaction::<Hasher>(request, &signatures)
+    }
+}
+
+impl<Signer: WormholeMultisigSignerSync> WormholeSigningStrategySync for WormholeMultisigSigning<Signer> {
+    fn sign_request_sync<Hasher: DogeHashProvider>(
+        &self,
+        core: &WormholeGuardianProcessorCore,
+        request: &WormholeSpendSigningRequest,
+    ) -> anyhow::Result<BTCTransaction> {
+        let signatures = request
+            .sighashes
+            .iter()
+            .map(|sighash| self.signer.collect_multisig_signatures_sync(*sighash))
+            .collect::<anyhow::Result<Vec<Vec<PsyCompressedSecp256K1Signature>>>>()?;
+        core.assemble_multisig_signed_transaction::<Hasher>(request, &signatures)
+    }
+}
+
+// The sync and async front-ends: fetch the spent transactions, prepare the request with the core, sign it with the strategy
+pub struct WormholeGuardianProcessor<RPC, Signing> {
+    core: WormholeGuardianProcessorCore,
+    rpc_provider: RPC,
+    signing: Signing,
+}
+
+pub type WormholeGuardianProcessorAsync<RPC, Signer> = WormholeGuardianProcessor<RPC, WormholeTSSSigning<Signer>>;
+pub type WormholeGuardianProcessorSync<RPC, Signer> = WormholeGuardianProcessor<RPC, WormholeTSSSigning<Signer>>;
+pub type WormholeMultisigGuardianProcessorAsync<RPC, Signer> =
+    WormholeGuardianProcessor<RPC, WormholeMultisigSigning<Signer>>;
+pub type WormholeMultisigGuardianProcessorSync<RPC, Signer> =
+    WormholeGuardianProcessor<RPC, WormholeMultisigSigning<Signer>>;
+
+impl<RPC, Signing> WormholeGuardianProcessor<RPC, Signing> {
+    pub fn with_guardian_set_registry(mut self, guardian_set_registry: WormholeGuardianSetRegistry) -> Self {
+        self.core.set_guardian_set_registry(guardian_set_registry);
+        self
+    }
+    pub fn with_processed_message_store<Store: ProcessedMessageStore + Send + Sync + 'static>(
+        mut self,
+        processed_message_store: Store,
+    ) -> Self {
+        self.core.set_processed_message_store(processed_message_store);
+        self
+    }
+    pub fn with_script_verification(mut self, script_verify_flags: u32) -> Self {
+        self.core.set_script_verify_flags(script_verify_flags);
+        self
+    }
+    pub fn with_script_template_registry(mut self, script_template_registry: WormholeScriptTemplateRegistry) -> Self {
+        self.core.set_script_template_registry(script_template_registry);
+        self
+    }
+    pub fn core(&self) -> &WormholeGuardianProcessorCore {
+        &self.core
+    }
+}
+
+impl<RPC, Signer: WithTSSPublicKey> WormholeGuardianProcessor<RPC, WormholeTSSSigning<Signer>> {
     pub fn new_with_tss_public_key_hash(
         rpc_provider: RPC,
         signer: Signer,
         tss_public_key_hash: [u8; 20],
     ) -> Self {
         Self {
-            tss_public_key_hash,
+            core: WormholeGuardianProcessorCore::new(tss_public_key_hash),
             rpc_provider,
-            signer,
+            signing: WormholeTSSSigning {
+                signers: vec![(tss_public_key_hash, signer)],
+            },
         }
     }
     pub fn new<Hasher: DogeHashProvider>(rpc_provider: RPC, signer: Signer) -> Self {
+        let tss_key = WormholeTSSKey::new_with_public_key::<Hasher>(signer.get_tss_public_key().0);
+
+        Self {
+            core: WormholeGuardianProcessorCore::new_with_tss_key(tss_key),
+            rpc_provider,
+            signing: WormholeTSSSigning {
+                signers: vec![(tss_key.public_key_hash, signer)],
+            },
+        }
+    }
+    pub fn with_tss_key_set(mut self, tss_key_set: WormholeTSSKeySet) -> Self {
+        self.core.set_tss_key_set(tss_key_set);
+        self
+    }
+    // an extra signer, e.g. for the previous key during a rotation (its key must also be in the tss key set)
+    pub fn with_signer<Hasher: DogeHashProvider>(mut self, signer: Signer) -> Self {
         let tss_public_key_hash = Hasher::bitcoin_hash160(&signer.get_tss_public_key().0);
+        self.signing.signers.push((tss_public_key_hash, signer));
+        self
+    }
+}
 
+impl<RPC, Signer> WormholeGuardianProcessor<RPC, WormholeMultisigSigning<Signer>> {
+    pub fn new(rpc_provider: RPC, signer: Signer, multisig: WormholeGuardianMultisig) -> Self {
         Self {
-            tss_public_key_hash,
+            core: WormholeGuardianProcessorCore::new_multisig(multisig),
             rpc_provider,
-            signer,
+            signing: WormholeMultisigSigning { signer },
         }
     }
+}
+
+impl<RPC: DogecoinRPCProviderAsync + Sync, Signing: WormholeSigningStrategyAsync> WormholeGuardianProcessor<RPC, Signing> {
+    pub async fn validate_signed_vaa_and_sign_async<
+        Hasher: DogeHashProvider + Keccak256HashProvider,
+        N: DogeNetworkConfig,
+    >(
+        &self,
+        vaa: &WormholeVAA,
+    ) -> anyhow::Result<BTCTransaction> {
+        let (kind, message) = self.core.verify_vaa_and_get_message_with_kind::<Hasher>(vaa)?;
+        self.validate_and_sign_message_async::<Hasher, N>(message, kind, Some(vaa.get_vaa_id()))
+            .await
+    }
     pub async fn validate_p2sh_vaa_message_and_sign_async<
         Hasher: DogeHashProvider,
         N: DogeNetworkConfig,
@@ -50,29 +689,34 @@ impl<RPC: DogecoinRPCProviderAsync + Sync, Signer: WormholeTSSSignerAsync>
         &self,
         message: WormholeBitcoinLikeVAAMessage,
     ) -> anyhow::Result<BTCTransaction> {
-        let (sighashes, mut spend_transaction) = message
-            .validate_and_get_sighashes_async::<Hasher, N, RPC>(
-                &self.rpc_provider,
-                &self.tss_public_key_hash,
-            )
+        self.validate_and_sign_message_async::<Hasher, N>(message, WormholeBitcoinLikeVAAMessageKind::Spend, None)
+            .await
+    }
+    pub async fn validate_p2sh_vaa_consolidation_and_sign_async<
+        Hasher: DogeHashProvider,
+        N: DogeNetworkConfig,
+    >(
+        &self,
+        message: WormholeBitcoinLikeVAAMessage,
+    ) -> anyhow::Result<BTCTransaction> {
+        self.validate_and_sign_message_async::<Hasher, N>(message, WormholeBitcoinLikeVAAMessageKind::Consolidation, None)
+            .await
+    }
+    async fn validate_and_sign_message_async<Hasher: DogeHashProvider, N: DogeNetworkConfig>(
+        &self,
+        message: WormholeBitcoinLikeVAAMessage,
+        kind: WormholeBitcoinLikeVAAMessageKind,
+        vaa_id: Option<WormholeVAAId>,
+    ) -> anyhow::Result<BTCTransaction> {
+        let input_transactions = self
+            .rpc_provider
+            .get_transactions_by_txid(&message.get_input_txids())
             .await?;
-        if sighashes.len() != message.inputs.len() {
-            return Err(anyhow::anyhow!("Invalid number of sighashes returned"));
-        }
-        for (i, sighash) in sighashes.into_iter().enumerate() {
-            let signature = self
-                .signer
-                .sign_message_tss_and_broadcast_to_other_guardians_and_wait_for_signature(sighash)
-                .await?;
-            let mut input_script = signature.to_btc_script();
-            let redeem_script = message
-                .metadata
-                .get_locking_script(&self.tss_public_key_hash);
-            input_script.extend_from_slice(&encode_binary_stack_item(&redeem_script));
-            spend_transaction.inputs[i].script = input_script;
-        }
-
-        Ok(spend_transaction)
+        let request = self
+            .signing
+            .prepare_signing_request::<Hasher, N>(&self.core, message, kind, &input_transactions, vaa_id)?;
+        let result = self.signing.sign_request_async::<Hasher>(&self.core, &request).await;
+        self.core.finish_signing_request(&request, result)
     }
     pub async fn validate_p2sh_vaa_message_sign_and_broadcast_asyncc<
         Hasher: DogeHashProvider,
@@ -90,37 +734,16 @@ impl<RPC: DogecoinRPCProviderAsync + Sync, Signer: WormholeTSSSignerAsync>
     }
 }
 
-pub struct WormholeGuardianProcessorSync<
-    RPC: DogecoinRPCProviderSync,
-    Signer: WormholeTSSSignerSync,
-> {
-    tss_public_key_hash: [u8; 20],
-    rpc_provider: RPC,
-    signer: Signer,
-}
-
-impl<RPC: DogecoinRPCProviderSync, Signer: WormholeTSSSignerSync>
-    WormholeGuardianProcessorSync<RPC, Signer>
-{
-    pub fn new_with_tss_public_key_hash(
-        rpc_provider: RPC,
-        signer: Signer,
-        tss_public_key_hash: [u8; 20],
-    ) -> Self {
-        Self {
-            tss_public_key_hash,
-            rpc_provider,
-            signer,
-        }
-    }
-    pub fn new<Hasher: DogeHashProvider>(rpc_provider: RPC, signer: Signer) -> Self {
-        let tss_public_key_hash = Hasher::bitcoin_hash160(&signer.get_tss_public_key().0);
-
-        Self {
-            tss_public_key_hash,
-            rpc_provider,
-            signer,
-        }
+impl<RPC: DogecoinRPCProviderSync, Signing: WormholeSigningStrategySync> WormholeGuardianProcessor<RPC, Signing> {
+    pub fn validate_signed_vaa_and_sign_sync<
+        Hasher: DogeHashProvider + Keccak256HashProvider,
+        N: DogeNetworkConfig,
+    >(
+        &self,
+        vaa: &WormholeVAA,
+    ) -> anyhow::Result<BTCTransaction> {
+        let (kind, message) = self.core.verify_vaa_and_get_message_with_kind::<Hasher>(vaa)?;
+        self.validate_and_sign_message_sync::<Hasher, N>(message, kind, Some(vaa.get_vaa_id()))
     }
     pub fn validate_p2sh_vaa_message_and_sign_sync<
         Hasher: DogeHashProvider,
@@ -129,23 +752,443 @@ impl<RPC: DogecoinRPCProviderSync, Signer: WormholeTSSSignerSync>
         &self,
         message: WormholeBitcoinLikeVAAMessage,
     ) -> anyhow::Result<BTCTransaction> {
-        let (sighashes, mut spend_transaction) = message
-            .validate_and_get_sighashes_sync::<Hasher, N, RPC>(
-                &self.rpc_provider,
-                &self.tss_public_key_hash,
-            )?;
-        if sighashes.len() != message.inputs.len() {
-            return Err(anyhow::anyhow!("Invalid number of sighashes returned"));
+        self.validate_and_sign_message_sync::<Hasher, N>(message, WormholeBitcoinLikeVAAMessageKind::Spend, None)
+    }
+    pub fn validate_p2sh_vaa_consolidation_and_sign_sync<
+        Hasher: DogeHashProvider,
+        N: DogeNetworkConfig,
+    >(
+        &self,
+        message: WormholeBitcoinLikeVAAMessage,
+    ) -> anyhow::Result<BTCTransaction> {
+        self.validate_and_sign_message_sync::<Hasher, N>(message, WormholeBitcoinLikeVAAMessageKind::Consolidation, None)
+    }
+    fn validate_and_sign_message_sync<Hasher: DogeHashProvider, N: DogeNetworkConfig>(
+        &self,
+        message: WormholeBitcoinLikeVAAMessage,
+        kind: WormholeBitcoinLikeVAAMessageKind,
+        vaa_id: Option<WormholeVAAId>,
+    ) -> anyhow::Result<BTCTransaction> {
+        let input_transactions = self
+            .rpc_provider
+            .get_transactions_by_txid_sync(&message.get_input_txids())?;
+        let request = self
+            .signing
+            .prepare_signing_request::<Hasher, N>(&self.core, message, kind, &input_transactions, vaa_id)?;
+        let result = self.signing.sign_request_sync::<Hasher>(&self.core, &request);
+        self.core.finish_signing_request(&request, result)
+    }
+    pub fn validate_p2sh_vaa_message_sign_and_broadcast_sync<
+        Hasher: DogeHashProvider,
+        N: DogeNetworkConfig,
+    >(
+        &self,
+        message: WormholeBitcoinLikeVAAMessage,
+    ) -> anyhow::Result<QHash256> {
+        let spend_transaction = self.validate_p2sh_vaa_message_and_sign_sync::<Hasher, N>(message)?;
+        let raw_tx = spend_transaction.to_bytes();
+        let txid = self.rpc_provider.submit_raw_transaction_sync(&raw_tx)?;
+        Ok(txid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections::HashMap};
+
+    use crate::{
+        dogecoin::{
+            address::{AddressToBTCScript, BTCAddress160},
+            constants::DogeTestNetConfig,
+            script::interpreter::STANDARD_SCRIPT_VERIFY_FLAGS,
+            hash::CommonDogeHashProvider,
+            transaction::{BTCTransactionInput, BTCTransactionInputWithoutScript, encode_binary_stack_item},
+        },
+        secp256k1::signer::{
+            CompressedPublicKey, CompressedPublicKeyToP2PKH, SimpleSinglePublicKeySigner, SimpleSingleSigner,
+        },
+        wormhole::{
+            p2sh_vaa::WormholeBitcoinLikeVAAMetadata, processed_message_store::MemoryProcessedMessageStore,
+            script_template::WORMHOLE_VAA_TEMPLATE_V1_P2PK,
+            test_utils::example_metadata,
+            tss_key_set::WormholeTSSKey,
+        },
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+    type Network = DogeTestNetConfig;
+
+    fn example_signer() -> SimpleSinglePublicKeySigner<crate::secp256k1::signer::MemorySecp256K1Wallet> {
+        SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([7u8; 32]).unwrap()
+    }
+
+    // a funding transaction paying the message's p2sh address, and a message spending it
+    fn example_scenario(
+        guardian_public_key_hash: &[u8; 20],
+    ) -> (HashMap<QHash256, BTCTransaction>, WormholeBitcoinLikeVAAMessage) {
+        let metadata = WormholeBitcoinLikeVAAMetadata {
+            total_output_amount: 9_000_000,
+            max_doge_transaction_fee: 1_000_000,
+            min_doge_transaction_fee: 100_000,
+            ..example_metadata()
+        };
+        let funding_transaction = BTCTransaction::from_io(
+            vec![BTCTransactionInput::default()],
+            vec![
+                metadata
+                    .get_p2sh_address::<Network, Hasher>(guardian_public_key_hash)
+                    .unwrap()
+                    .to_btc_output(9_300_000),
+            ],
+        );
+        let message = WormholeBitcoinLikeVAAMessage {
+            metadata,
+            inputs: vec![BTCTransactionInputWithoutScript::new_simple(
+                funding_transaction.get_hash::<Hasher>(),
+                0,
+            )],
+            outputs: vec![BTCAddress160::new_p2pkh([0xbb; 20]).to_btc_output(9_000_000)],
+        };
+        let mut rpc_provider = HashMap::new();
+        rpc_provider.insert(funding_transaction.get_txid::<Hasher>(), funding_transaction);
+        (rpc_provider, message)
+    }
+
+    #[tokio::test]
+    async fn test_sync_and_async_processors_produce_identical_transactions() {
+        let guardian_public_key_hash = example_signer()
+            .get_compressed_public_key()
+            .to_p2pkh_address::<Hasher>();
+        let (rpc_provider, message) = example_scenario(&guardian_public_key_hash);
+
+        let async_processor =
+            WormholeGuardianProcessorAsync::new::<Hasher>(rpc_provider.clone(), example_signer());
+        let sync_processor = WormholeGuardianProcessorSync::new::<Hasher>(rpc_provider, example_signer())
+            .with_script_verification(STANDARD_SCRIPT_VERIFY_FLAGS);
+
+        let async_tx = async_processor
+            .validate_p2sh_vaa_message_and_sign_async::<Hasher, Network>(message.clone())
+            .await
+            .unwrap();
+        let sync_tx = sync_processor
+            .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(message.clone())
+            .unwrap();
+        assert_eq!(async_tx.to_bytes(), sync_tx.to_bytes());
+
+        // scriptSig = <sig> <pubkey> <redeem script>
+        let redeem_script = message.metadata.get_locking_script(&guardian_public_key_hash).unwrap();
+        assert!(sync_tx.inputs[0]
+            .script
+            .ends_with(&encode_binary_stack_item(&redeem_script)));
+    }
+
+    #[test]
+    fn test_processor_spends_old_and_new_key_inputs_together() {
+        let old_signer = example_signer();
+        let new_signer =
+            SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([8u8; 32]).unwrap();
+        let old_key_hash = old_signer.get_compressed_public_key().to_p2pkh_address::<Hasher>();
+        let new_key_hash = new_signer.get_compressed_public_key().to_p2pkh_address::<Hasher>();
+
+        // one deposit made before the rotation and one after
+        let (mut rpc_provider, mut message) = example_scenario(&old_key_hash);
+        let (new_key_rpc_provider, new_key_message) = example_scenario(&new_key_hash);
+        rpc_provider.extend(new_key_rpc_provider);
+        message.inputs.extend(new_key_message.inputs);
+        message.metadata.total_output_amount = 18_000_000;
+        message.outputs[0].value = 18_000_000;
+
+        let mut key_set = WormholeTSSKeySet::new_single(old_key_hash);
+        key_set.add_key(WormholeTSSKey::new(new_key_hash)).unwrap();
+        let processor = WormholeGuardianProcessorSync::new::<Hasher>(rpc_provider.clone(), new_signer)
+            .with_signer::<Hasher>(old_signer)
+            .with_tss_key_set(key_set)
+            .with_script_verification(STANDARD_SCRIPT_VERIFY_FLAGS);
+        let tx = processor
+            .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(message.clone())
+            .unwrap();
+        for (input, key_hash) in tx.inputs.iter().zip([old_key_hash, new_key_hash]) {
+            let redeem_script = message.metadata.get_locking_script(&key_hash).unwrap();
+            assert!(input.script.ends_with(&encode_binary_stack_item(&redeem_script)));
         }
-        for (i, sighash) in sighashes.into_iter().enumerate() {
-            let signature = self
-                .signer
-                .sign_message_tss_and_broadcast_to_other_guardians_and_wait_for_signature_sync(
-                    sighash,
-                )?;
-            spend_transaction.inputs[i].script = signature.to_btc_script();
+
+        // once the old key is retired its deposits are no longer accepted
+        let mut key_set = WormholeTSSKeySet::new_single(new_key_hash);
+        key_set
+            .add_key(WormholeTSSKey { expiration_time: 1, ..WormholeTSSKey::new(old_key_hash) })
+            .unwrap();
+        let processor = WormholeGuardianProcessorSync::new::<Hasher>(rpc_provider, example_signer())
+            .with_tss_key_set(key_set);
+        assert!(processor
+            .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(message)
+            .is_err());
+    }
+
+    #[test]
+    fn test_processor_signs_compact_p2pk_template() {
+        let signer = example_signer();
+        let public_key = signer.get_compressed_public_key().0;
+        let (_, mut message) = example_scenario(&[0u8; 20]);
+        message.metadata.template_version = WORMHOLE_VAA_TEMPLATE_V1_P2PK;
+        assert!(message.metadata.get_p2sh_address::<Network, Hasher>(&[0u8; 20]).is_err());
+        let funding_transaction = BTCTransaction::from_io(
+            vec![BTCTransactionInput::default()],
+            vec![
+                message
+                    .metadata
+                    .get_p2sh_address_for_public_key::<Network, Hasher>(&public_key)
+                    .unwrap()
+                    .to_btc_output(9_300_000),
+            ],
+        );
+        message.inputs[0].hash = funding_transaction.get_hash::<Hasher>();
+        let mut rpc_provider = HashMap::new();
+        rpc_provider.insert(funding_transaction.get_txid::<Hasher>(), funding_transaction);
+
+        let processor = WormholeGuardianProcessorSync::new::<Hasher>(rpc_provider, signer)
+            .with_script_verification(STANDARD_SCRIPT_VERIFY_FLAGS);
+        let tx = processor
+            .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(message.clone())
+            .unwrap();
+
+        // scriptSig = <sig> <redeem script>, the public key lives in the redeem script
+        let comparison = message.metadata.compare_p2pkh_and_p2pk_input_sizes().unwrap();
+        assert_eq!(comparison.get_bytes_saved_per_input(), 34 - 10);
+        assert_eq!(comparison.get_fee_saved(100, 1000), 2_400_000);
+        assert!(tx.inputs[0].to_bytes().len() <= comparison.p2pk_input_size);
+        let encoded_redeem_script = encode_binary_stack_item(
+            &message.metadata.get_locking_script_for_public_key::<Hasher>(&public_key).unwrap(),
+        );
+        let signature_push_size = tx.inputs[0].script[0] as usize + 1;
+        assert_eq!(tx.inputs[0].script.len(), signature_push_size + encoded_redeem_script.len());
+        assert!(tx.inputs[0].script.ends_with(&encoded_redeem_script));
+    }
+
+    #[test]
+    fn test_multisig_processor_orders_signatures_by_key() {
+        let signers = [7u8, 8, 9]
+            .map(|key| SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([key; 32]).unwrap());
+        let multisig = WormholeGuardianMultisig::new(
+            2,
+            signers.iter().map(|signer| signer.get_compressed_public_key().0).collect(),
+        )
+        .unwrap();
+        assert!(WormholeGuardianMultisig::new(1, vec![multisig.public_keys[0]; 14]).is_err());
+
+        // funds locked to the multisig instead of a tss key hash
+        let (_, mut message) = example_scenario(&[0u8; 20]);
+        let funding_transaction = BTCTransaction::from_io(
+            vec![BTCTransactionInput::default()],
+            vec![
+                message
+                    .metadata
+                    .get_multisig_p2sh_address::<Network, Hasher>(&multisig)
+                    .unwrap()
+                    .to_btc_output(9_300_000),
+            ],
+        );
+        message.inputs[0].hash = funding_transaction.get_hash::<Hasher>();
+        let mut rpc_provider = HashMap::new();
+        rpc_provider.insert(funding_transaction.get_txid::<Hasher>(), funding_transaction);
+
+        // the guardians holding keys 3 and 1 respond, in that order
+        let [first, _, third] = signers;
+        let processor = WormholeMultisigGuardianProcessorSync::new(rpc_provider, vec![third, first], multisig.clone())
+            .with_script_verification(STANDARD_SCRIPT_VERIFY_FLAGS);
+        let tx = processor
+            .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(message.clone())
+            .unwrap();
+        assert_eq!(tx.inputs[0].script[0], 0x00);
+        let redeem_script = message.metadata.get_multisig_locking_script(&multisig).unwrap();
+        assert!(tx.inputs[0].script.ends_with(&encode_binary_stack_item(&redeem_script)));
+    }
+
+    #[test]
+    fn test_multisig_rejects_duplicate_public_keys() {
+        let public_key = example_signer().get_compressed_public_key().0;
+        let other_public_key = SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([8u8; 32])
+            .unwrap()
+            .get_compressed_public_key()
+            .0;
+        assert!(WormholeGuardianMultisig::new(2, vec![public_key, other_public_key]).is_ok());
+        // one guardian listed twice could satisfy a 2-of-3 on their own
+        assert!(WormholeGuardianMultisig::new(2, vec![public_key, other_public_key, public_key]).is_err());
+        assert!(WormholeGuardianMultisig::new(1, vec![public_key, public_key]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_processors_sign_consolidations_back_to_self() {
+        let signer = example_signer();
+        let guardian_public_key_hash = signer.get_compressed_public_key().to_p2pkh_address::<Hasher>();
+        let (rpc_provider, spend_message) = example_scenario(&guardian_public_key_hash);
+        let mut consolidation_message = spend_message.clone();
+        consolidation_message.outputs = vec![
+            consolidation_message
+                .metadata
+                .get_p2sh_address::<Network, Hasher>(&guardian_public_key_hash)
+                .unwrap()
+                .to_btc_output(9_000_000),
+        ];
+
+        let processor = WormholeGuardianProcessorSync::new::<Hasher>(rpc_provider.clone(), signer)
+            .with_script_verification(STANDARD_SCRIPT_VERIFY_FLAGS);
+        let tx = processor
+            .validate_p2sh_vaa_consolidation_and_sign_sync::<Hasher, Network>(consolidation_message.clone())
+            .unwrap();
+        assert_eq!(tx.outputs, consolidation_message.outputs);
+        // a spend paying someone else is not a consolidation, but is still a valid spend
+        assert!(processor
+            .validate_p2sh_vaa_consolidation_and_sign_sync::<Hasher, Network>(spend_message.clone())
+            .is_err());
+        processor
+            .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(spend_message.clone())
+            .unwrap();
+
+        // the same rules through the multisig strategy
+        let signers = vec![7u8, 8]
+            .into_iter()
+            .map(|key| SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([key; 32]).unwrap())
+            .collect::<Vec<_>>();
+        let multisig = WormholeGuardianMultisig::new(
+            2,
+            signers.iter().map(|signer| signer.get_compressed_public_key().0).collect(),
+        )
+        .unwrap();
+        let multisig_address = spend_message
+            .metadata
+            .get_multisig_p2sh_address::<Network, Hasher>(&multisig)
+            .unwrap();
+        let funding_transaction = BTCTransaction::from_io(
+            vec![BTCTransactionInput::default()],
+            vec![multisig_address.to_btc_output(9_300_000)],
+        );
+        let mut multisig_message = spend_message.clone();
+        multisig_message.inputs[0].hash = funding_transaction.get_hash::<Hasher>();
+        multisig_message.outputs = vec![multisig_address.to_btc_output(9_000_000)];
+        let mut rpc_provider = HashMap::new();
+        rpc_provider.insert(funding_transaction.get_txid::<Hasher>(), funding_transaction);
+
+        let processor = WormholeMultisigGuardianProcessorAsync::new(rpc_provider, signers, multisig)
+            .with_script_verification(STANDARD_SCRIPT_VERIFY_FLAGS);
+        let tx = processor
+            .validate_p2sh_vaa_consolidation_and_sign_async::<Hasher, Network>(multisig_message.clone())
+            .await
+            .unwrap();
+        assert_eq!(tx.outputs, multisig_message.outputs);
+        multisig_message.outputs = spend_message.outputs.clone();
+        assert!(processor
+            .validate_p2sh_vaa_consolidation_and_sign_async::<Hasher, Network>(multisig_message)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_processor_rejects_unknown_template_version() {
+        let guardian_public_key_hash = example_signer()
+            .get_compressed_public_key()
+            .to_p2pkh_address::<Hasher>();
+        let (rpc_provider, message) = example_scenario(&guardian_public_key_hash);
+        let processor = WormholeGuardianProcessorSync::new::<Hasher>(rpc_provider, example_signer())
+            .with_script_template_registry(WormholeScriptTemplateRegistry::new());
+        let err = processor
+            .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(message)
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown wormhole vaa script template version 1"));
+    }
+
+    // hands out the first signature, then fails like a tss round that timed out
+    struct SecondSignatureFailsSigner {
+        signer: SimpleSinglePublicKeySigner<crate::secp256k1::signer::MemorySecp256K1Wallet>,
+        calls: Cell<usize>,
+    }
+
+    impl WithTSSPublicKey for SecondSignatureFailsSigner {
+        fn get_tss_public_key(&self) -> CompressedPublicKey {
+            self.signer.get_compressed_public_key()
         }
+    }
 
-        Ok(spend_transaction)
+    impl WormholeTSSSignerSync for SecondSignatureFailsSigner {
+        fn sign_message_tss_and_broadcast_to_other_guardians_and_wait_for_signature_sync(
+            &self,
+            message: QHash256,
+        ) -> anyhow::Result<PsyCompressedSecp256K1Signature> {
+            self.calls.set(self.calls.get() + 1);
+            if self.calls.get() == 2 {
+                anyhow::bail!("tss signing round timed out");
+            }
+            self.signer.sign_message(message)
+        }
+    }
+
+    #[test]
+    fn test_processor_keeps_reservation_once_an_input_was_signed() {
+        let guardian_public_key_hash = example_signer()
+            .get_compressed_public_key()
+            .to_p2pkh_address::<Hasher>();
+        // two inputs locked to the same key
+        let (_, mut message) = example_scenario(&guardian_public_key_hash);
+        let address = message
+            .metadata
+            .get_p2sh_address::<Network, Hasher>(&guardian_public_key_hash)
+            .unwrap();
+        let funding_transaction = BTCTransaction::from_io(
+            vec![BTCTransactionInput::default()],
+            vec![address.to_btc_output(4_650_000), address.to_btc_output(4_650_000)],
+        );
+        let funding_hash = funding_transaction.get_hash::<Hasher>();
+        message.inputs = vec![
+            BTCTransactionInputWithoutScript::new_simple(funding_hash, 0),
+            BTCTransactionInputWithoutScript::new_simple(funding_hash, 1),
+        ];
+        let mut rpc_provider = HashMap::new();
+        rpc_provider.insert(funding_transaction.get_txid::<Hasher>(), funding_transaction);
+
+        let signer = SecondSignatureFailsSigner { signer: example_signer(), calls: Cell::new(0) };
+        let processor = WormholeGuardianProcessorSync::new::<Hasher>(rpc_provider.clone(), signer)
+            .with_processed_message_store(MemoryProcessedMessageStore::new());
+        assert!(processor
+            .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(message.clone())
+            .is_err());
+        // the first input's signature is out, neither the message nor a conflicting one may be signed again
+        let mut conflicting_message = message.clone();
+        conflicting_message.outputs = vec![BTCAddress160::new_p2pkh([0xcc; 20]).to_btc_output(9_000_000)];
+        for message in [message.clone(), conflicting_message] {
+            let error = processor
+                .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(message)
+                .unwrap_err();
+            assert!(!format!("{:#}", error).contains("timed out"));
+        }
+
+        // without a signer for the inputs' key nothing is signed and the reservation is released
+        let processor =
+            WormholeGuardianProcessorSync::new_with_tss_public_key_hash(rpc_provider, example_signer(), [0x55; 20])
+                .with_tss_key_set(WormholeTSSKeySet::new_single(guardian_public_key_hash))
+                .with_processed_message_store(MemoryProcessedMessageStore::new());
+        for _ in 0..2 {
+            let error = processor
+                .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(message.clone())
+                .unwrap_err();
+            assert!(error.downcast_ref::<WormholeNothingSignedError>().is_some());
+        }
+    }
+
+    #[test]
+    fn test_processor_rejects_replayed_message() {
+        let guardian_public_key_hash = example_signer()
+            .get_compressed_public_key()
+            .to_p2pkh_address::<Hasher>();
+        let (rpc_provider, message) = example_scenario(&guardian_public_key_hash);
+        let processor = WormholeGuardianProcessorSync::new::<Hasher>(rpc_provider, example_signer())
+            .with_processed_message_store(MemoryProcessedMessageStore::new());
+
+        processor
+            .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(message.clone())
+            .unwrap();
+        assert!(processor
+            .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(message)
+            .is_err());
     }
 }
@@ -0,0 +1,251 @@
+use crate::{
+    dogecoin::{
+        address::AddressToBTCScript,
+        constants::DogeNetworkConfig,
+        hash::DogeHashProvider,
+        transaction::BTCTransactionInputWithoutScript,
+        transaction_layout::BTCTransactionLayout,
+    },
+    psy_doge_link::{data::BTCUTXO, traits::PsyBitcoinAPIAsync},
+    wormhole::{
+        p2sh_vaa::{WormholeBitcoinLikeVAAMessage, WormholeBitcoinLikeVAAMetadata},
+        template_registry::{WormholeGuardianKey, WormholeScriptTemplateRegistry},
+    },
+};
+
+// a sub-address whose funds could not be migrated in this round
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct WormholeKeyRotationSkip {
+    pub metadata: WormholeBitcoinLikeVAAMetadata,
+    pub input_count: usize,
+    pub total_input_amount: u64,
+    pub reason: String,
+}
+
+#[derive(PartialEq, Clone, Debug, Eq, Default)]
+pub struct WormholeKeyRotationPlan {
+    // sweep messages spending old-key utxos, to be signed by a processor holding the old key
+    pub messages: Vec<WormholeBitcoinLikeVAAMessage>,
+    pub skipped: Vec<WormholeKeyRotationSkip>,
+}
+
+// Builds messages that move every utxo of an old guardian key's VAA-P2SH address into the
+// address with the same emitter and seed under the new guardian key.
+#[derive(Clone, Debug)]
+pub struct WormholeKeyRotationPlanner {
+    old_guardian_public_key_hash: [u8; 20],
+    new_guardian_public_key_hash: [u8; 20],
+    // koinu per byte
+    fee_rate: u64,
+    max_inputs_per_message: usize,
+    min_output_amount: u64,
+    include_unconfirmed: bool,
+    script_template_registry: WormholeScriptTemplateRegistry,
+}
+
+impl WormholeKeyRotationPlanner {
+    pub fn new(
+        old_guardian_public_key_hash: [u8; 20],
+        new_guardian_public_key_hash: [u8; 20],
+        fee_rate: u64,
+    ) -> Self {
+        Self {
+            old_guardian_public_key_hash,
+            new_guardian_public_key_hash,
+            fee_rate,
+            max_inputs_per_message: 100,
+            min_output_amount: 1,
+            include_unconfirmed: false,
+            script_template_registry: WormholeScriptTemplateRegistry::new_with_builtin_templates(),
+        }
+    }
+    pub fn with_max_inputs_per_message(mut self, max_inputs_per_message: usize) -> Self {
+        self.max_inputs_per_message = max_inputs_per_message.max(1);
+        self
+    }
+    // sweeps producing less than this (or the network's dust limit) are skipped
+    pub fn with_min_output_amount(mut self, min_output_amount: u64) -> Self {
+        self.min_output_amount = min_output_amount;
+        self
+    }
+    pub fn with_unconfirmed_utxos(mut self, include_unconfirmed: bool) -> Self {
+        self.include_unconfirmed = include_unconfirmed;
+        self
+    }
+    // the templates old and new addresses are derived with, the builtin ones by default
+    pub fn with_script_template_registry(mut self, script_template_registry: WormholeScriptTemplateRegistry) -> Self {
+        self.script_template_registry = script_template_registry;
+        self
+    }
+    pub fn get_old_guardian_public_key_hash(&self) -> &[u8; 20] {
+        &self.old_guardian_public_key_hash
+    }
+    pub fn get_new_guardian_public_key_hash(&self) -> &[u8; 20] {
+        &self.new_guardian_public_key_hash
+    }
+
+    // upper bound of the signed transaction size, every input carries the full old-key spend scriptSig
+    fn estimate_signed_size<Hasher: DogeHashProvider>(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        input_count: usize,
+    ) -> anyhow::Result<usize> {
+        let output = self
+            .script_template_registry
+            .get_p2sh_address::<Hasher>(metadata, &WormholeGuardianKey::new(self.new_guardian_public_key_hash))?
+            .to_btc_output(0);
+        let script_sig_size = self.script_template_registry.estimate_script_sig_size(metadata)?;
+        Ok(BTCTransactionLayout::new(vec![script_sig_size; input_count], vec![output.script.len()]).get_total_size())
+    }
+
+    // I/O free planning for a single sub-address, utxos must belong to the old-key address of metadata
+    pub fn plan_for_utxos<N: DogeNetworkConfig, Hasher: DogeHashProvider>(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        utxos: &[BTCUTXO],
+        plan: &mut WormholeKeyRotationPlan,
+    ) {
+        let mut utxos = utxos
+            .iter()
+            .filter(|utxo| self.include_unconfirmed || utxo.status.confirmed)
+            .collect::<Vec<&BTCUTXO>>();
+        utxos.sort_by_key(|utxo| (utxo.txid, utxo.vout));
+
+        for chunk in utxos.chunks(self.max_inputs_per_message) {
+            let total_input_amount = chunk.iter().fold(0u64, |sum, utxo| sum.saturating_add(utxo.value));
+            let skip = |reason: String| WormholeKeyRotationSkip {
+                metadata: *metadata,
+                input_count: chunk.len(),
+                total_input_amount,
+                reason,
+            };
+
+            // the planner only knows key hashes, P2PK templates have to be rotated from the full public keys
+            let (new_address, signed_size) = match self
+                .script_template_registry
+                .get_p2sh_address::<Hasher>(metadata, &WormholeGuardianKey::new(self.new_guardian_public_key_hash))
+                .and_then(|address| Ok((address, self.estimate_signed_size::<Hasher>(metadata, chunk.len())?)))
+            {
+                Ok(result) => result,
+                Err(err) => {
+                    plan.skipped.push(skip(err.to_string()));
+                    continue;
+                }
+            };
+            let fee = (signed_size as u64)
+                .saturating_mul(self.fee_rate)
+                .max(N::RELAY_POLICY.get_min_relay_fee(signed_size))
+                .max(metadata.min_doge_transaction_fee);
+            if fee > metadata.max_doge_transaction_fee {
+                plan.skipped.push(skip(format!(
+                    "required fee {} is more than maximum allowed {}",
+                    fee, metadata.max_doge_transaction_fee
+                )));
+                continue;
+            }
+            let output_amount = total_input_amount.saturating_sub(fee);
+            let min_output_amount = self.min_output_amount.max(N::RELAY_POLICY.get_min_output_value());
+            if output_amount < min_output_amount {
+                plan.skipped.push(skip(format!(
+                    "output amount {} after a fee of {} is below the minimum of {}",
+                    output_amount, fee, min_output_amount
+                )));
+                continue;
+            }
+
+            plan.messages.push(WormholeBitcoinLikeVAAMessage {
+                metadata: WormholeBitcoinLikeVAAMetadata {
+                    total_output_amount: output_amount,
+                    ..*metadata
+                },
+                inputs: chunk.iter().copied().map(BTCTransactionInputWithoutScript::from).collect(),
+                outputs: vec![new_address.to_btc_output(output_amount)],
+            });
+        }
+    }
+
+    pub async fn plan<N: DogeNetworkConfig, Hasher: DogeHashProvider, API: PsyBitcoinAPIAsync>(
+        &self,
+        api: &API,
+        metadata_list: &[WormholeBitcoinLikeVAAMetadata],
+    ) -> anyhow::Result<WormholeKeyRotationPlan> {
+        if self.old_guardian_public_key_hash == self.new_guardian_public_key_hash {
+            anyhow::bail!("old and new guardian public key hashes are the same");
+        }
+        let mut plan = WormholeKeyRotationPlan::default();
+        for metadata in metadata_list {
+            let old_address = self
+                .script_template_registry
+                .get_p2sh_address::<Hasher>(metadata, &WormholeGuardianKey::new(self.old_guardian_public_key_hash))?;
+            let utxos = api.get_utxos(old_address).await?;
+            self.plan_for_utxos::<N, Hasher>(metadata, &utxos, &mut plan);
+        }
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dogecoin::{
+            constants::DogeTestNetConfig,
+            hash::CommonDogeHashProvider,
+            transaction::{BTCTransaction, BTCTransactionInput, BTCTransactionOutput},
+        },
+        wormhole::test_utils::{EXAMPLE_GUARDIAN_PUBLIC_KEY_HASH, example_metadata, get_confirmed_utxo, get_input_transactions},
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+    type Network = DogeTestNetConfig;
+
+    #[test]
+    fn test_rotation_messages_validate_against_old_key() {
+        let old_key = EXAMPLE_GUARDIAN_PUBLIC_KEY_HASH;
+        let new_key = [4u8; 20];
+        let metadata = WormholeBitcoinLikeVAAMetadata {
+            max_doge_transaction_fee: 2_000_000,
+            min_doge_transaction_fee: 100_000,
+            ..example_metadata()
+        };
+        let old_address = metadata.get_p2sh_address::<Network, Hasher>(&old_key).unwrap();
+        let funding_transactions = (0..3u64)
+            .map(|i| {
+                BTCTransaction::from_io(
+                    vec![BTCTransactionInput { hash: [i as u8; 32], ..Default::default() }],
+                    vec![
+                        BTCTransactionOutput { value: 1, script: vec![0x51] },
+                        old_address.to_btc_output(10_000_000 * (i + 1)),
+                    ],
+                )
+            })
+            .collect::<Vec<BTCTransaction>>();
+        let utxos = funding_transactions
+            .iter()
+            .map(|tx| get_confirmed_utxo::<Hasher>(tx, 1))
+            .collect::<Vec<BTCUTXO>>();
+
+        let planner = WormholeKeyRotationPlanner::new(old_key, new_key, 1000).with_max_inputs_per_message(2);
+        let mut plan = WormholeKeyRotationPlan::default();
+        planner.plan_for_utxos::<Network, Hasher>(&metadata, &utxos, &mut plan);
+        assert!(plan.skipped.is_empty());
+        assert_eq!(plan.messages.len(), 2);
+
+        let new_address = metadata.get_p2sh_address::<Network, Hasher>(&new_key).unwrap();
+        for message in plan.messages.iter() {
+            let input_transactions = get_input_transactions::<Hasher>(message, &funding_transactions);
+            message
+                .validate_and_get_sighashes_with_inputs::<Hasher, Network>(&input_transactions, &[old_key])
+                .unwrap();
+            assert_eq!(message.outputs[0].get_output_address().unwrap(), new_address);
+        }
+
+        // a fee ceiling below the required fee leaves the funds where they are
+        let strict_metadata = WormholeBitcoinLikeVAAMetadata { max_doge_transaction_fee: 100_000, ..metadata };
+        let mut plan = WormholeKeyRotationPlan::default();
+        planner.plan_for_utxos::<Network, Hasher>(&strict_metadata, &utxos, &mut plan);
+        assert!(plan.messages.is_empty());
+        assert_eq!(plan.skipped.len(), 2);
+    }
+}
@@ -1,4 +1,19 @@
 pub mod script_template;
 pub mod p2sh_vaa;
 pub mod guardian_processor;
-pub mod tss_signer;
\ No newline at end of file
+pub mod tss_signer;
+pub mod tss_key_set;
+pub mod vaa;
+pub mod payload;
+pub mod processed_message_store;
+pub mod spend_script;
+pub mod deposit_watcher;
+pub mod sub_address;
+pub mod key_rotation;
+pub mod recovery;
+pub mod template_registry;
+pub mod fee_estimator;
+pub mod withdrawal_planner;
+pub mod consolidation;
+#[cfg(test)]
+pub(crate) mod test_utils;
@@ -3,14 +3,17 @@ use crate::{
         address::BTCAddress160,
         constants::DogeNetworkConfig,
         hash::{DogeHashProvider, QHash256},
+        relay_policy::DogeRelayPolicy,
         sighash::{SIGHASH_ALL, SigHashPreimage},
-        transaction::{
-            BTCTransaction, BTCTransactionInput, BTCTransactionInputWithoutScript,
-            BTCTransactionOutput,
-        },
+        transaction::{BTCTransaction, BTCTransactionInput, BTCTransactionInputWithoutScript, BTCTransactionOutput},
+        transaction_layout::BTCTransactionLayout,
     },
     tx_store::traits::{DogecoinRPCProviderAsync, DogecoinRPCProviderSync},
-    wormhole::script_template::construct_wormhole_vaa_script,
+    wormhole::{
+        payload::encode_bitcoin_like_vaa_payload,
+        script_template::{WORMHOLE_VAA_TEMPLATE_P2PK_FLAG, construct_wormhole_vaa_multisig_script},
+        template_registry::{WormholeGuardianKey, WormholeVAAScriptTemplate, get_builtin_script_template_registry},
+    },
 };
 
 // The metadata in the message sent by the contract
@@ -22,25 +25,127 @@ pub struct WormholeBitcoinLikeVAAMetadata {
     pub total_output_amount: u64,
     pub max_doge_transaction_fee: u64,
     pub min_doge_transaction_fee: u64,
+    // selects the redeem script template, see WORMHOLE_VAA_TEMPLATE_* (addresses created before versioning use v1)
+    pub template_version: u8,
+    // optional emergency branch that lets a recovery key spend once the guardians have been unavailable until lock_time
+    pub recovery: Option<WormholeRecoveryPath>,
 }
 
+#[derive(PartialEq, Clone, Debug, Eq, Ord, PartialOrd, Copy)]
+pub struct WormholeRecoveryPath {
+    // block height if below 500_000_000 (LOCKTIME_THRESHOLD), unix timestamp otherwise
+    pub lock_time: u32,
+    pub recovery_public_key_hash: [u8; 20],
+}
+
+// The helpers below use the builtin templates, see WormholeScriptTemplateRegistry for the same with a custom registry
 impl WormholeBitcoinLikeVAAMetadata {
-    pub fn get_locking_script(&self, guardian_public_key_hash: &[u8; 20]) -> Vec<u8> {
-        construct_wormhole_vaa_script(
-            self.emitter_chain,
-            &self.emitter_contract_address,
-            &self.sub_address_seed,
-            guardian_public_key_hash,
-        )
+    pub fn get_script_template(&self) -> anyhow::Result<&'static dyn WormholeVAAScriptTemplate> {
+        get_builtin_script_template_registry().get(self.template_version)
+    }
+    // only for templates that commit to the guardian key hash, P2PK templates need get_locking_script_for_public_key
+    pub fn get_locking_script(&self, guardian_public_key_hash: &[u8; 20]) -> anyhow::Result<Vec<u8>> {
+        get_builtin_script_template_registry().build_locking_script(self, &WormholeGuardianKey::new(*guardian_public_key_hash))
+    }
+    pub fn get_locking_script_for_public_key<Hasher: DogeHashProvider>(
+        &self,
+        guardian_public_key: &[u8; 33],
+    ) -> anyhow::Result<Vec<u8>> {
+        get_builtin_script_template_registry()
+            .build_locking_script(self, &WormholeGuardianKey::new_with_public_key::<Hasher>(*guardian_public_key))
     }
     pub fn get_p2sh_address<N: DogeNetworkConfig, Hasher: DogeHashProvider>(
         &self,
         guardian_public_key_hash: &[u8; 20],
-    ) -> BTCAddress160 {
-        BTCAddress160::new_p2sh(Hasher::bitcoin_hash160(
-            &self.get_locking_script(guardian_public_key_hash),
-        ))
+    ) -> anyhow::Result<BTCAddress160> {
+        get_builtin_script_template_registry()
+            .get_p2sh_address::<Hasher>(self, &WormholeGuardianKey::new(*guardian_public_key_hash))
     }
+    pub fn get_p2sh_address_for_public_key<N: DogeNetworkConfig, Hasher: DogeHashProvider>(
+        &self,
+        guardian_public_key: &[u8; 33],
+    ) -> anyhow::Result<BTCAddress160> {
+        get_builtin_script_template_registry()
+            .get_p2sh_address::<Hasher>(self, &WormholeGuardianKey::new_with_public_key::<Hasher>(*guardian_public_key))
+    }
+    // upper bound of the serialized size of one signed guardian input (outpoint, scriptSig and sequence)
+    pub fn estimate_signed_input_size(&self) -> anyhow::Result<usize> {
+        get_builtin_script_template_registry().estimate_signed_input_size(self)
+    }
+    // how much the compact P2PK tail saves compared to the P2PKH tail for this emitter/recovery configuration
+    pub fn compare_p2pkh_and_p2pk_input_sizes(&self) -> anyhow::Result<WormholeTemplateSizeComparison> {
+        let header_version = self.template_version & !WORMHOLE_VAA_TEMPLATE_P2PK_FLAG;
+        let p2pkh = Self { template_version: header_version, ..*self };
+        let p2pk = Self { template_version: header_version | WORMHOLE_VAA_TEMPLATE_P2PK_FLAG, ..*self };
+        Ok(WormholeTemplateSizeComparison {
+            p2pkh_input_size: p2pkh.estimate_signed_input_size()?,
+            p2pk_input_size: p2pk.estimate_signed_input_size()?,
+        })
+    }
+    pub fn get_multisig_locking_script(&self, multisig: &WormholeGuardianMultisig) -> anyhow::Result<Vec<u8>> {
+        get_builtin_script_template_registry().build_multisig_locking_script(self, multisig)
+    }
+    pub fn get_multisig_p2sh_address<N: DogeNetworkConfig, Hasher: DogeHashProvider>(
+        &self,
+        multisig: &WormholeGuardianMultisig,
+    ) -> anyhow::Result<BTCAddress160> {
+        get_builtin_script_template_registry().get_multisig_p2sh_address::<Hasher>(self, multisig)
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct WormholeTemplateSizeComparison {
+    pub p2pkh_input_size: usize,
+    pub p2pk_input_size: usize,
+}
+
+impl WormholeTemplateSizeComparison {
+    pub fn get_bytes_saved_per_input(&self) -> usize {
+        self.p2pkh_input_size.saturating_sub(self.p2pk_input_size)
+    }
+    // fee_rate in koinu per byte
+    pub fn get_fee_saved(&self, input_count: usize, fee_rate: u64) -> u64 {
+        (self.get_bytes_saved_per_input() as u64)
+            .saturating_mul(input_count as u64)
+            .saturating_mul(fee_rate)
+    }
+}
+
+// m-of-n guardian keys, for deployments that lock deposits to a CHECKMULTISIG script instead of a tss key
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct WormholeGuardianMultisig {
+    pub required_signatures: usize,
+    // CHECKMULTISIG requires the signatures in the same order as these keys
+    pub public_keys: Vec<[u8; 33]>,
+}
+
+impl WormholeGuardianMultisig {
+    pub fn new(required_signatures: usize, public_keys: Vec<[u8; 33]>) -> anyhow::Result<Self> {
+        // the largest emitter chain header, so any metadata can be used with the multisig
+        construct_wormhole_vaa_multisig_script(u16::MAX, &[0u8; 32], &[0u8; 32], required_signatures, &public_keys)?;
+        // CHECKMULTISIG would accept m signatures from a key listed m times
+        for (i, public_key) in public_keys.iter().enumerate() {
+            if public_keys[..i].contains(public_key) {
+                anyhow::bail!("multisig public key {} is listed more than once", hex::encode(public_key));
+            }
+        }
+        Ok(Self {
+            required_signatures,
+            public_keys,
+        })
+    }
+    pub fn get_key_position(&self, public_key: &[u8; 33]) -> Option<usize> {
+        self.public_keys.iter().position(|key| key == public_key)
+    }
+}
+
+// What a message authorizes, carried by the payload version
+#[derive(PartialEq, Clone, Copy, Debug, Eq, Ord, PartialOrd, Hash)]
+pub enum WormholeBitcoinLikeVAAMessageKind {
+    // pays arbitrary outputs within the fee bounds
+    Spend,
+    // sweeps utxos of one VAA-P2SH address back into a single output to the same address
+    Consolidation,
 }
 
 #[derive(PartialEq, Clone, Debug, Eq, Ord, PartialOrd)]
@@ -59,18 +164,102 @@ fn checked_add_sum(list: &[u64]) -> anyhow::Result<u64> {
     Ok(total)
 }
 impl WormholeBitcoinLikeVAAMessage {
-    fn validate_and_get_sighashes_with_inputs<Hasher: DogeHashProvider, N: DogeNetworkConfig>(
+    // canonical identifier of a message: sha256d of its payload encoding
+    pub fn get_message_digest<Hasher: DogeHashProvider>(&self) -> anyhow::Result<QHash256> {
+        Ok(Hasher::bitcoin_hash256(&encode_bitcoin_like_vaa_payload(self)?))
+    }
+    pub fn get_input_txids(&self) -> Vec<QHash256> {
+        self.inputs.iter().map(|input| input.get_txid()).collect()
+    }
+    // I/O free validation against the transactions being spent (input_transactions[i] must fund inputs[i])
+    // each input may be locked to any of the guardian key hashes, the key used by input i is returned in position i
+    pub fn validate_and_get_sighashes_with_inputs<Hasher: DogeHashProvider, N: DogeNetworkConfig>(
         &self,
         input_transactions: &[BTCTransaction],
-        guardian_public_key_hash: &[u8; 20],
+        guardian_public_key_hashes: &[[u8; 20]],
+    ) -> anyhow::Result<(Vec<QHash256>, Vec<[u8; 20]>, BTCTransaction)> {
+        let redeem_scripts = guardian_public_key_hashes
+            .iter()
+            .map(|key_hash| self.metadata.get_locking_script(key_hash))
+            .collect::<anyhow::Result<Vec<Vec<u8>>>>()?;
+        let (sighashes, script_positions, base_tx) =
+            self.validate_and_get_sighashes_for_redeem_scripts::<Hasher>(input_transactions, &redeem_scripts)?;
+        let script_sig_size = get_builtin_script_template_registry().estimate_script_sig_size(&self.metadata)?;
+        self.check_relay_policy(&N::RELAY_POLICY, input_transactions, &vec![script_sig_size; self.inputs.len()])?;
+        let input_key_hashes = script_positions
+            .into_iter()
+            .map(|position| guardian_public_key_hashes[position])
+            .collect();
+        Ok((sighashes, input_key_hashes, base_tx))
+    }
+    // same as validate_and_get_sighashes_with_inputs for messages spending m-of-n multisig VAA-P2SH outputs
+    pub fn validate_and_get_sighashes_with_multisig_inputs<Hasher: DogeHashProvider, N: DogeNetworkConfig>(
+        &self,
+        input_transactions: &[BTCTransaction],
+        multisig: &WormholeGuardianMultisig,
     ) -> anyhow::Result<(Vec<QHash256>, BTCTransaction)> {
-        let expected_address = self
-            .metadata
-            .get_p2sh_address::<N, Hasher>(guardian_public_key_hash);
+        let template = get_builtin_script_template_registry().get_multisig(self.metadata.template_version)?;
+        let redeem_script = template.build_locking_script(&self.metadata, multisig)?;
+        let (sighashes, _, base_tx) = self
+            .validate_and_get_sighashes_for_redeem_scripts::<Hasher>(input_transactions, std::slice::from_ref(&redeem_script))?;
+        let script_sig_size = template.estimate_script_sig_size(&self.metadata, multisig)?;
+        self.check_relay_policy(&N::RELAY_POLICY, input_transactions, &vec![script_sig_size; self.inputs.len()])?;
+        Ok((sighashes, base_tx))
+    }
+    // rejects messages whose signed transaction would not be relayed, script_sig_sizes[i] bounds the scriptSig of input i.
+    // input_transactions must already have been validated against the message inputs
+    pub fn check_relay_policy(
+        &self,
+        policy: &DogeRelayPolicy,
+        input_transactions: &[BTCTransaction],
+        script_sig_sizes: &[usize],
+    ) -> anyhow::Result<()> {
+        let total_input_amount = checked_add_sum(
+            &input_transactions
+                .iter()
+                .zip(self.inputs.iter())
+                .map(|(input_transaction, input)| {
+                    input_transaction
+                        .outputs
+                        .get(input.index as usize)
+                        .map(|output| output.value)
+                        .ok_or_else(|| anyhow::anyhow!("input transaction has no output {}", input.index))
+                })
+                .collect::<anyhow::Result<Vec<u64>>>()?,
+        )?;
+        let fee = total_input_amount
+            .checked_sub(self.metadata.total_output_amount)
+            .ok_or_else(|| anyhow::anyhow!("u64 underflow in subtraction"))?;
+        let layout = BTCTransactionLayout::new(
+            script_sig_sizes.to_vec(),
+            self.outputs.iter().map(|output| output.script.len()).collect(),
+        );
+        policy.check_transaction(&layout, fee, &self.outputs)
+    }
+    // returns the sighashes, the position of the redeem script each input is locked to and the unsigned transaction
+    pub fn validate_and_get_sighashes_for_redeem_scripts<Hasher: DogeHashProvider>(
+        &self,
+        input_transactions: &[BTCTransaction],
+        redeem_scripts: &[Vec<u8>],
+    ) -> anyhow::Result<(Vec<QHash256>, Vec<usize>, BTCTransaction)> {
+        if redeem_scripts.is_empty() {
+            anyhow::bail!("no guardian redeem scripts to validate the inputs against");
+        }
+        let candidate_addresses = redeem_scripts
+            .iter()
+            .map(|redeem_script| BTCAddress160::new_p2sh(Hasher::bitcoin_hash160(redeem_script)))
+            .collect::<Vec<BTCAddress160>>();
 
-        let redeem_script = self.metadata.get_locking_script(guardian_public_key_hash);
+        if input_transactions.len() != self.inputs.len() {
+            anyhow::bail!(
+                "expected {} input transactions but got {}",
+                self.inputs.len(),
+                input_transactions.len()
+            );
+        }
 
         let mut total_input_amount: u64 = 0;
+        let mut script_positions = Vec::with_capacity(self.inputs.len());
         for (input_transaction, input) in input_transactions.iter().zip(self.inputs.iter()) {
             let actual_hash = input_transaction.get_hash::<Hasher>();
             let expected_hash = input.hash;
@@ -81,9 +270,11 @@ impl WormholeBitcoinLikeVAAMessage {
                     actual_hash
                 );
             }
-            if !input_transaction.has_vout_for_address(&expected_address, input.index as usize) {
-                anyhow::bail!("input does not spend from the expected wormhole vaa p2sh address");
-            }
+            let script_position = candidate_addresses
+                .iter()
+                .position(|address| input_transaction.has_vout_for_address(address, input.index as usize))
+                .ok_or_else(|| anyhow::anyhow!("input does not spend from the expected wormhole vaa p2sh address"))?;
+            script_positions.push(script_position);
             total_input_amount = total_input_amount
                 .checked_add(input_transaction.outputs[input.index as usize].value)
                 .ok_or_else(|| anyhow::anyhow!("u64 overflow in addition"))?;
@@ -136,18 +327,20 @@ impl WormholeBitcoinLikeVAAMessage {
             outputs: self.outputs.clone(),
             locktime: 0,
         };
-        let sighashes = (0..self.inputs.len())
-            .map(|i: usize| {
+        let sighashes = script_positions
+            .iter()
+            .enumerate()
+            .map(|(i, script_position)| {
                 SigHashPreimage::for_transaction_pre_segwit(
                     &base_tx,
                     i,
-                    &redeem_script, // FIXED: Use redeem_script here instead of vaa_address_output_script
+                    &redeem_scripts[*script_position], // FIXED: Use redeem_script here instead of vaa_address_output_script
                     SIGHASH_ALL,
                 )
                 .get_hash::<Hasher>()
             })
             .collect();
-        Ok((sighashes, base_tx))
+        Ok((sighashes, script_positions, base_tx))
     }
 
     // in a production scenario, you would likely want to use an async RPC provider and cache the transactions
@@ -158,18 +351,12 @@ impl WormholeBitcoinLikeVAAMessage {
     >(
         &self,
         rpc_provider: &RPC,
-        guardian_public_key_hash: &[u8; 20],
-    ) -> anyhow::Result<(Vec<QHash256>, BTCTransaction)> {
-        let input_txids = self
-            .inputs
-            .iter()
-            .map(|input| input.get_txid())
-            .collect::<Vec<QHash256>>();
-
-        let input_transactions = rpc_provider.get_transactions_by_txid_sync(&input_txids)?;
+        guardian_public_key_hashes: &[[u8; 20]],
+    ) -> anyhow::Result<(Vec<QHash256>, Vec<[u8; 20]>, BTCTransaction)> {
+        let input_transactions = rpc_provider.get_transactions_by_txid_sync(&self.get_input_txids())?;
         self.validate_and_get_sighashes_with_inputs::<Hasher, N>(
             &input_transactions,
-            guardian_public_key_hash,
+            guardian_public_key_hashes,
         )
     }
     pub async fn validate_and_get_sighashes_async<
@@ -179,17 +366,14 @@ impl WormholeBitcoinLikeVAAMessage {
     >(
         &self,
         rpc_provider: &RPC,
-        guardian_public_key_hash: &[u8; 20],
-    ) -> anyhow::Result<(Vec<QHash256>, BTCTransaction)> {
-        let input_txids = self
-            .inputs
-            .iter()
-            .map(|input| input.get_txid())
-            .collect::<Vec<QHash256>>();
-        let input_transactions = rpc_provider.get_transactions_by_txid(&input_txids).await?;
+        guardian_public_key_hashes: &[[u8; 20]],
+    ) -> anyhow::Result<(Vec<QHash256>, Vec<[u8; 20]>, BTCTransaction)> {
+        let input_transactions = rpc_provider
+            .get_transactions_by_txid(&self.get_input_txids())
+            .await?;
         self.validate_and_get_sighashes_with_inputs::<Hasher, N>(
             &input_transactions,
-            guardian_public_key_hash,
+            guardian_public_key_hashes,
         )
     }
 }
@@ -0,0 +1,277 @@
+use crate::{
+    dogecoin::transaction::{BTCTransactionInputWithoutScript, BTCTransactionOutput},
+    wormhole::{
+        p2sh_vaa::{
+            WormholeBitcoinLikeVAAMessage, WormholeBitcoinLikeVAAMessageKind, WormholeBitcoinLikeVAAMetadata,
+            WormholeRecoveryPath,
+        },
+        script_template::WORMHOLE_VAA_TEMPLATE_V1,
+        vaa::WormholeBytesReader,
+    },
+};
+
+pub const WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V1: u8 = 1;
+// v1 plus the emergency recovery branch of the redeem script
+pub const WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V2: u8 = 2;
+// v1 plus the redeem script template version and an optional recovery branch
+pub const WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V3: u8 = 3;
+// a consolidation message, v3 layout
+pub const WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_CONSOLIDATION_V1: u8 = 4;
+
+/*
+Payload v1 layout (big endian, wormhole conventions):
+    version                   u8 (= 1)
+    emitter_chain             u16
+    emitter_contract_address  [u8; 32]
+    sub_address_seed          [u8; 32]
+    total_output_amount       u64
+    max_doge_transaction_fee  u64
+    min_doge_transaction_fee  u64
+    input count               u16
+    inputs                    (hash [u8; 32], index u32, sequence u32)*
+    output count              u16
+    outputs                   (value u64, script length u16, script)*
+
+Payload v2 is v1 with the recovery branch inserted after min_doge_transaction_fee:
+    recovery_lock_time        u32
+    recovery_public_key_hash  [u8; 20]
+
+Payload v3 is v1 with the template version inserted after min_doge_transaction_fee:
+    template_version          u8
+    has_recovery              u8 (0 or 1)
+    recovery_lock_time        u32       (only if has_recovery = 1)
+    recovery_public_key_hash  [u8; 20]  (only if has_recovery = 1)
+
+Messages are encoded with the lowest version that can represent them: v1 templates
+without a recovery branch as v1, v1 templates with one as v2, everything else as v3.
+
+Consolidation messages always use version 4, laid out like v3. Its single output must pay
+the VAA-P2SH address the inputs are locked to, see wormhole::consolidation.
+
+Decoding is strict: unknown versions and trailing bytes are rejected so the
+Solana program and every guardian agree on exactly one encoding per message.
+*/
+
+fn checked_u16_len(len: usize, what: &str) -> anyhow::Result<[u8; 2]> {
+    let len: u16 = len
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("too many {} to encode in a payload: {}", what, len))?;
+    Ok(len.to_be_bytes())
+}
+
+pub fn encode_bitcoin_like_vaa_payload(
+    message: &WormholeBitcoinLikeVAAMessage,
+) -> anyhow::Result<Vec<u8>> {
+    encode_bitcoin_like_vaa_payload_with_kind(message, WormholeBitcoinLikeVAAMessageKind::Spend)
+}
+
+pub fn encode_bitcoin_like_vaa_payload_with_kind(
+    message: &WormholeBitcoinLikeVAAMessage,
+    kind: WormholeBitcoinLikeVAAMessageKind,
+) -> anyhow::Result<Vec<u8>> {
+    let metadata = &message.metadata;
+    let mut bytes = Vec::new();
+    let version = match (kind, metadata.template_version, metadata.recovery) {
+        (WormholeBitcoinLikeVAAMessageKind::Consolidation, _, _) => WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_CONSOLIDATION_V1,
+        (_, WORMHOLE_VAA_TEMPLATE_V1, None) => WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V1,
+        (_, WORMHOLE_VAA_TEMPLATE_V1, Some(_)) => WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V2,
+        _ => WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V3,
+    };
+    bytes.push(version);
+    bytes.extend_from_slice(&metadata.emitter_chain.to_be_bytes());
+    bytes.extend_from_slice(&metadata.emitter_contract_address);
+    bytes.extend_from_slice(&metadata.sub_address_seed);
+    bytes.extend_from_slice(&metadata.total_output_amount.to_be_bytes());
+    bytes.extend_from_slice(&metadata.max_doge_transaction_fee.to_be_bytes());
+    bytes.extend_from_slice(&metadata.min_doge_transaction_fee.to_be_bytes());
+    if version == WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V3 || version == WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_CONSOLIDATION_V1 {
+        bytes.push(metadata.template_version);
+        bytes.push(metadata.recovery.is_some() as u8);
+    }
+    if let Some(recovery) = metadata.recovery {
+        bytes.extend_from_slice(&recovery.lock_time.to_be_bytes());
+        bytes.extend_from_slice(&recovery.recovery_public_key_hash);
+    }
+
+    bytes.extend_from_slice(&checked_u16_len(message.inputs.len(), "inputs")?);
+    for input in message.inputs.iter() {
+        bytes.extend_from_slice(&input.hash);
+        bytes.extend_from_slice(&input.index.to_be_bytes());
+        bytes.extend_from_slice(&input.sequence.to_be_bytes());
+    }
+
+    bytes.extend_from_slice(&checked_u16_len(message.outputs.len(), "outputs")?);
+    for output in message.outputs.iter() {
+        bytes.extend_from_slice(&output.value.to_be_bytes());
+        bytes.extend_from_slice(&checked_u16_len(output.script.len(), "output script bytes")?);
+        bytes.extend_from_slice(&output.script);
+    }
+    Ok(bytes)
+}
+
+fn decode_bitcoin_like_vaa_payload_body(
+    reader: &mut WormholeBytesReader,
+    version: u8,
+) -> anyhow::Result<WormholeBitcoinLikeVAAMessage> {
+    let emitter_chain = reader.read_u16()?;
+    let emitter_contract_address = reader.read_array::<32>()?;
+    let sub_address_seed = reader.read_array::<32>()?;
+    let total_output_amount = reader.read_u64()?;
+    let max_doge_transaction_fee = reader.read_u64()?;
+    let min_doge_transaction_fee = reader.read_u64()?;
+    let (template_version, has_recovery) = match version {
+        WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V1 => (WORMHOLE_VAA_TEMPLATE_V1, false),
+        WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V2 => (WORMHOLE_VAA_TEMPLATE_V1, true),
+        _ => {
+            let template_version = reader.read_u8()?;
+            // v1 template spends are always encoded as v1 or v2, a second encoding would have another digest
+            if version == WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V3 && template_version == WORMHOLE_VAA_TEMPLATE_V1 {
+                anyhow::bail!("v1 template messages must be encoded as payload v1 or v2, not v3");
+            }
+            let has_recovery = match reader.read_u8()? {
+                0 => false,
+                1 => true,
+                flag => anyhow::bail!("invalid has_recovery flag {}", flag),
+            };
+            (template_version, has_recovery)
+        }
+    };
+    let recovery = if has_recovery {
+        Some(WormholeRecoveryPath {
+            lock_time: reader.read_u32()?,
+            recovery_public_key_hash: reader.read_array::<20>()?,
+        })
+    } else {
+        None
+    };
+
+    let input_count = reader.read_u16()? as usize;
+    let mut inputs = Vec::with_capacity(input_count);
+    for _ in 0..input_count {
+        let hash = reader.read_array::<32>()?;
+        let index = reader.read_u32()?;
+        let sequence = reader.read_u32()?;
+        inputs.push(BTCTransactionInputWithoutScript::new(hash, index, sequence));
+    }
+
+    let output_count = reader.read_u16()? as usize;
+    let mut outputs = Vec::with_capacity(output_count);
+    for _ in 0..output_count {
+        let value = reader.read_u64()?;
+        let script_len = reader.read_u16()? as usize;
+        let script = reader.read_slice(script_len)?.to_vec();
+        outputs.push(BTCTransactionOutput { value, script });
+    }
+
+    Ok(WormholeBitcoinLikeVAAMessage {
+        metadata: WormholeBitcoinLikeVAAMetadata {
+            emitter_chain,
+            emitter_contract_address,
+            sub_address_seed,
+            total_output_amount,
+            max_doge_transaction_fee,
+            min_doge_transaction_fee,
+            template_version,
+            recovery,
+        },
+        inputs,
+        outputs,
+    })
+}
+
+// decodes spend payloads only, consolidations must go through decode_bitcoin_like_vaa_payload_with_kind
+pub fn decode_bitcoin_like_vaa_payload(payload: &[u8]) -> anyhow::Result<WormholeBitcoinLikeVAAMessage> {
+    match decode_bitcoin_like_vaa_payload_with_kind(payload)? {
+        (WormholeBitcoinLikeVAAMessageKind::Spend, message) => Ok(message),
+        (kind, _) => anyhow::bail!("expected a spend payload but got a {:?} payload", kind),
+    }
+}
+
+pub fn decode_bitcoin_like_vaa_payload_with_kind(
+    payload: &[u8],
+) -> anyhow::Result<(WormholeBitcoinLikeVAAMessageKind, WormholeBitcoinLikeVAAMessage)> {
+    let mut reader = WormholeBytesReader::new(payload);
+    let version = reader.read_u8()?;
+    let (kind, message) = match version {
+        WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V1
+        | WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V2
+        | WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V3 => (
+            WormholeBitcoinLikeVAAMessageKind::Spend,
+            decode_bitcoin_like_vaa_payload_body(&mut reader, version)?,
+        ),
+        WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_CONSOLIDATION_V1 => (
+            WormholeBitcoinLikeVAAMessageKind::Consolidation,
+            decode_bitcoin_like_vaa_payload_body(&mut reader, version)?,
+        ),
+        _ => anyhow::bail!("unsupported bitcoin like VAA payload version {}", version),
+    };
+    if reader.remaining() != 0 {
+        anyhow::bail!(
+            "bitcoin like VAA payload has {} trailing bytes",
+            reader.remaining()
+        );
+    }
+    Ok((kind, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wormhole::{script_template::WORMHOLE_VAA_TEMPLATE_V1_P2PK, test_utils::example_message};
+
+    use super::*;
+
+    #[test]
+    fn test_payload_round_trip() {
+        let message = example_message();
+        let payload = encode_bitcoin_like_vaa_payload(&message).unwrap();
+        assert_eq!(payload[0], WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V1);
+        assert_eq!(decode_bitcoin_like_vaa_payload(&payload).unwrap(), message);
+
+        let mut message = example_message();
+        message.metadata.recovery = Some(WormholeRecoveryPath {
+            lock_time: 5_000_000,
+            recovery_public_key_hash: [9u8; 20],
+        });
+        let payload = encode_bitcoin_like_vaa_payload(&message).unwrap();
+        assert_eq!(payload[0], WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V2);
+        assert_eq!(decode_bitcoin_like_vaa_payload(&payload).unwrap(), message);
+
+        message.metadata.template_version = WORMHOLE_VAA_TEMPLATE_V1_P2PK;
+        let payload = encode_bitcoin_like_vaa_payload(&message).unwrap();
+        assert_eq!(payload[0], WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V3);
+        assert_eq!(decode_bitcoin_like_vaa_payload(&payload).unwrap(), message);
+        message.metadata.recovery = None;
+        let payload = encode_bitcoin_like_vaa_payload(&message).unwrap();
+        assert_eq!(decode_bitcoin_like_vaa_payload(&payload).unwrap(), message);
+
+        // consolidations keep their kind and are not accepted where a spend is expected
+        let message = example_message();
+        let payload =
+            encode_bitcoin_like_vaa_payload_with_kind(&message, WormholeBitcoinLikeVAAMessageKind::Consolidation).unwrap();
+        assert_eq!(payload[0], WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_CONSOLIDATION_V1);
+        assert_eq!(
+            decode_bitcoin_like_vaa_payload_with_kind(&payload).unwrap(),
+            (WormholeBitcoinLikeVAAMessageKind::Consolidation, message)
+        );
+        assert!(decode_bitcoin_like_vaa_payload(&payload).is_err());
+    }
+
+    #[test]
+    fn test_payload_rejects_trailing_bytes_and_unknown_versions() {
+        let mut payload = encode_bitcoin_like_vaa_payload(&example_message()).unwrap();
+        payload.push(0);
+        assert!(decode_bitcoin_like_vaa_payload(&payload).is_err());
+        payload.pop();
+        payload[0] = 0xff;
+        assert!(decode_bitcoin_like_vaa_payload(&payload).is_err());
+        assert!(decode_bitcoin_like_vaa_payload(&payload[..payload.len() - 1]).is_err());
+
+        // a v1 template message re-encoded in the v3 layout is not the canonical encoding
+        let message = example_message();
+        let mut payload =
+            encode_bitcoin_like_vaa_payload_with_kind(&message, WormholeBitcoinLikeVAAMessageKind::Consolidation).unwrap();
+        assert!(decode_bitcoin_like_vaa_payload_with_kind(&payload).is_ok());
+        payload[0] = WORMHOLE_BITCOIN_LIKE_VAA_PAYLOAD_V3;
+        assert!(decode_bitcoin_like_vaa_payload(&payload).is_err());
+    }
+}
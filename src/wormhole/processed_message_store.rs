@@ -0,0 +1,363 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    dogecoin::hash::{DogeHashProvider, QHash256},
+    wormhole::{p2sh_vaa::WormholeBitcoinLikeVAAMessage, vaa::WormholeVAAId},
+};
+
+// (hash, index) of a spent output, in the same byte order as BTCTransactionInputWithoutScript
+pub type WormholeOutpoint = (QHash256, u32);
+
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct ProcessedMessageRecord {
+    pub message_digest: QHash256,
+    pub vaa_id: Option<WormholeVAAId>,
+    pub outpoints: Vec<WormholeOutpoint>,
+}
+
+impl ProcessedMessageRecord {
+    pub fn from_message<Hasher: DogeHashProvider>(
+        message: &WormholeBitcoinLikeVAAMessage,
+        vaa_id: Option<WormholeVAAId>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            message_digest: message.get_message_digest::<Hasher>()?,
+            vaa_id,
+            outpoints: message
+                .inputs
+                .iter()
+                .map(|input| (input.hash, input.index))
+                .collect(),
+        })
+    }
+}
+
+pub trait ProcessedMessageStore {
+    fn is_message_processed(&self, message_digest: &QHash256) -> anyhow::Result<bool>;
+    fn get_message_digest_for_vaa_id(&self, vaa_id: &WormholeVAAId) -> anyhow::Result<Option<QHash256>>;
+    // returns the digest of the message that reserved the outpoint, if any
+    fn get_outpoint_reservation(&self, outpoint: &WormholeOutpoint) -> anyhow::Result<Option<QHash256>>;
+
+    // atomically rejects replays and double spends, then records the message and reserves its outpoints
+    fn check_and_record(&self, record: &ProcessedMessageRecord) -> anyhow::Result<()>;
+    // undoes check_and_record, used when signing fails before any signature was requested (or by an operator)
+    fn release(&self, message_digest: &QHash256) -> anyhow::Result<()>;
+}
+
+// the bookkeeping shared by the store implementations
+#[derive(Clone, Debug, Default)]
+pub struct ProcessedMessageLedger {
+    records: HashMap<QHash256, ProcessedMessageRecord>,
+    vaa_ids: HashMap<WormholeVAAId, QHash256>,
+    outpoints: HashMap<WormholeOutpoint, QHash256>,
+}
+
+impl ProcessedMessageLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn check(&self, record: &ProcessedMessageRecord) -> anyhow::Result<()> {
+        if self.records.contains_key(&record.message_digest) {
+            anyhow::bail!(
+                "message {} has already been signed",
+                hex::encode(record.message_digest)
+            );
+        }
+        if let Some(vaa_id) = record.vaa_id.as_ref()
+            && let Some(existing) = self.vaa_ids.get(vaa_id)
+        {
+            anyhow::bail!(
+                "VAA (emitter_chain={}, emitter_address={}, sequence={}) has already been processed as message {}",
+                vaa_id.emitter_chain,
+                hex::encode(vaa_id.emitter_address),
+                vaa_id.sequence,
+                hex::encode(existing)
+            );
+        }
+        let mut seen = HashSet::new();
+        for outpoint in record.outpoints.iter() {
+            if !seen.insert(*outpoint) {
+                anyhow::bail!(
+                    "message spends outpoint {}:{} more than once",
+                    hex::encode(outpoint.0),
+                    outpoint.1
+                );
+            }
+            if let Some(existing) = self.outpoints.get(outpoint) {
+                anyhow::bail!(
+                    "outpoint {}:{} is already spent by signed message {}",
+                    hex::encode(outpoint.0),
+                    outpoint.1,
+                    hex::encode(existing)
+                );
+            }
+        }
+        Ok(())
+    }
+    pub fn insert(&mut self, record: ProcessedMessageRecord) {
+        if let Some(vaa_id) = record.vaa_id {
+            self.vaa_ids.insert(vaa_id, record.message_digest);
+        }
+        for outpoint in record.outpoints.iter() {
+            self.outpoints.insert(*outpoint, record.message_digest);
+        }
+        self.records.insert(record.message_digest, record);
+    }
+    pub fn remove(&mut self, message_digest: &QHash256) -> Option<ProcessedMessageRecord> {
+        let record = self.records.remove(message_digest)?;
+        if let Some(vaa_id) = record.vaa_id.as_ref() {
+            self.vaa_ids.remove(vaa_id);
+        }
+        for outpoint in record.outpoints.iter() {
+            self.outpoints.remove(outpoint);
+        }
+        Some(record)
+    }
+    pub fn is_message_processed(&self, message_digest: &QHash256) -> bool {
+        self.records.contains_key(message_digest)
+    }
+    pub fn get_message_digest_for_vaa_id(&self, vaa_id: &WormholeVAAId) -> Option<QHash256> {
+        self.vaa_ids.get(vaa_id).cloned()
+    }
+    pub fn get_outpoint_reservation(&self, outpoint: &WormholeOutpoint) -> Option<QHash256> {
+        self.outpoints.get(outpoint).cloned()
+    }
+}
+
+fn lock_ledger(ledger: &Mutex<ProcessedMessageLedger>) -> anyhow::Result<std::sync::MutexGuard<'_, ProcessedMessageLedger>> {
+    ledger
+        .lock()
+        .map_err(|_| anyhow::anyhow!("processed message ledger lock was poisoned"))
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryProcessedMessageStore {
+    ledger: Mutex<ProcessedMessageLedger>,
+}
+
+impl MemoryProcessedMessageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ProcessedMessageStore for MemoryProcessedMessageStore {
+    fn is_message_processed(&self, message_digest: &QHash256) -> anyhow::Result<bool> {
+        Ok(lock_ledger(&self.ledger)?.is_message_processed(message_digest))
+    }
+    fn get_message_digest_for_vaa_id(&self, vaa_id: &WormholeVAAId) -> anyhow::Result<Option<QHash256>> {
+        Ok(lock_ledger(&self.ledger)?.get_message_digest_for_vaa_id(vaa_id))
+    }
+    fn get_outpoint_reservation(&self, outpoint: &WormholeOutpoint) -> anyhow::Result<Option<QHash256>> {
+        Ok(lock_ledger(&self.ledger)?.get_outpoint_reservation(outpoint))
+    }
+    fn check_and_record(&self, record: &ProcessedMessageRecord) -> anyhow::Result<()> {
+        let mut ledger = lock_ledger(&self.ledger)?;
+        ledger.check(record)?;
+        ledger.insert(record.clone());
+        Ok(())
+    }
+    fn release(&self, message_digest: &QHash256) -> anyhow::Result<()> {
+        lock_ledger(&self.ledger)?.remove(message_digest);
+        Ok(())
+    }
+}
+
+/*
+Append-only log, one entry per line:
+    + <message digest> <emitter_chain>:<emitter_address>:<sequence> | - <hash>:<index>,<hash>:<index>...
+    - <message digest>
+'+' records a message, '-' releases it. Bytes are hex encoded; a '-' in the vaa id or
+outpoint column means the field is empty.
+*/
+fn encode_record_line(record: &ProcessedMessageRecord) -> String {
+    let vaa_id = match record.vaa_id.as_ref() {
+        Some(vaa_id) => format!(
+            "{}:{}:{}",
+            vaa_id.emitter_chain,
+            hex::encode(vaa_id.emitter_address),
+            vaa_id.sequence
+        ),
+        None => "-".to_string(),
+    };
+    let outpoints = if record.outpoints.is_empty() {
+        "-".to_string()
+    } else {
+        record
+            .outpoints
+            .iter()
+            .map(|(hash, index)| format!("{}:{}", hex::encode(hash), index))
+            .collect::<Vec<String>>()
+            .join(",")
+    };
+    format!("+ {} {} {}\n", hex::encode(record.message_digest), vaa_id, outpoints)
+}
+
+fn decode_hash256(s: &str) -> anyhow::Result<QHash256> {
+    let mut bytes = [0u8; 32];
+    hex::decode_to_slice(s, &mut bytes)?;
+    Ok(bytes)
+}
+
+fn decode_record_line(line: &str) -> anyhow::Result<ProcessedMessageRecord> {
+    let parts = line.split(' ').collect::<Vec<&str>>();
+    if parts.len() != 4 {
+        anyhow::bail!("malformed processed message record: {}", line);
+    }
+    let message_digest = decode_hash256(parts[1])?;
+    let vaa_id = if parts[2] == "-" {
+        None
+    } else {
+        let vaa_id_parts = parts[2].split(':').collect::<Vec<&str>>();
+        if vaa_id_parts.len() != 3 {
+            anyhow::bail!("malformed vaa id in processed message record: {}", parts[2]);
+        }
+        Some(WormholeVAAId {
+            emitter_chain: vaa_id_parts[0].parse()?,
+            emitter_address: decode_hash256(vaa_id_parts[1])?,
+            sequence: vaa_id_parts[2].parse()?,
+        })
+    };
+    let outpoints = if parts[3] == "-" {
+        vec![]
+    } else {
+        parts[3]
+            .split(',')
+            .map(|outpoint| {
+                let (hash, index) = outpoint
+                    .split_once(':')
+                    .ok_or_else(|| anyhow::anyhow!("malformed outpoint in processed message record: {}", outpoint))?;
+                Ok((decode_hash256(hash)?, index.parse()?))
+            })
+            .collect::<anyhow::Result<Vec<WormholeOutpoint>>>()?
+    };
+    Ok(ProcessedMessageRecord {
+        message_digest,
+        vaa_id,
+        outpoints,
+    })
+}
+
+#[derive(Debug)]
+pub struct FileProcessedMessageStore {
+    path: PathBuf,
+    ledger: Mutex<ProcessedMessageLedger>,
+}
+
+impl FileProcessedMessageStore {
+    // opens (or creates) the log at path and replays it into memory
+    pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut ledger = ProcessedMessageLedger::new();
+        if path.exists() {
+            let file = std::fs::File::open(&path)?;
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                if let Some(digest) = line.strip_prefix("- ") {
+                    ledger.remove(&decode_hash256(digest)?);
+                } else if line.starts_with("+ ") {
+                    let record = decode_record_line(line)?;
+                    ledger.check(&record)?;
+                    ledger.insert(record);
+                } else {
+                    anyhow::bail!("malformed processed message log line: {}", line);
+                }
+            }
+        }
+        Ok(Self {
+            path,
+            ledger: Mutex::new(ledger),
+        })
+    }
+    fn append_line(&self, line: &str) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+impl ProcessedMessageStore for FileProcessedMessageStore {
+    fn is_message_processed(&self, message_digest: &QHash256) -> anyhow::Result<bool> {
+        Ok(lock_ledger(&self.ledger)?.is_message_processed(message_digest))
+    }
+    fn get_message_digest_for_vaa_id(&self, vaa_id: &WormholeVAAId) -> anyhow::Result<Option<QHash256>> {
+        Ok(lock_ledger(&self.ledger)?.get_message_digest_for_vaa_id(vaa_id))
+    }
+    fn get_outpoint_reservation(&self, outpoint: &WormholeOutpoint) -> anyhow::Result<Option<QHash256>> {
+        Ok(lock_ledger(&self.ledger)?.get_outpoint_reservation(outpoint))
+    }
+    fn check_and_record(&self, record: &ProcessedMessageRecord) -> anyhow::Result<()> {
+        let mut ledger = lock_ledger(&self.ledger)?;
+        ledger.check(record)?;
+        // persist before updating memory so a crash can never forget a signed message
+        self.append_line(&encode_record_line(record))?;
+        ledger.insert(record.clone());
+        Ok(())
+    }
+    fn release(&self, message_digest: &QHash256) -> anyhow::Result<()> {
+        let mut ledger = lock_ledger(&self.ledger)?;
+        if ledger.is_message_processed(message_digest) {
+            self.append_line(&format!("- {}\n", hex::encode(message_digest)))?;
+            ledger.remove(message_digest);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(digest: u8, sequence: Option<u64>, outpoints: &[(u8, u32)]) -> ProcessedMessageRecord {
+        ProcessedMessageRecord {
+            message_digest: [digest; 32],
+            vaa_id: sequence.map(|sequence| WormholeVAAId {
+                emitter_chain: 1,
+                emitter_address: [7u8; 32],
+                sequence,
+            }),
+            outpoints: outpoints.iter().map(|(h, i)| ([*h; 32], *i)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_file_store_rejects_replays_and_survives_restart() {
+        let path = std::env::temp_dir().join(format!(
+            "psy-doge-processed-messages-{}.log",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        {
+            let store = FileProcessedMessageStore::open(&path).unwrap();
+            store.check_and_record(&record(1, Some(1), &[(10, 0), (10, 1)])).unwrap();
+            store.check_and_record(&record(2, None, &[(11, 0)])).unwrap();
+            store.release(&[2u8; 32]).unwrap();
+        }
+        let store = FileProcessedMessageStore::open(&path).unwrap();
+        // same digest, same vaa id, and an already reserved outpoint are each rejected
+        assert!(store.check_and_record(&record(1, None, &[])).is_err());
+        assert!(store.check_and_record(&record(3, Some(1), &[])).is_err());
+        assert!(store.check_and_record(&record(4, None, &[(10, 1)])).is_err());
+        // the released message's outpoint is free again
+        store.check_and_record(&record(5, Some(2), &[(11, 0)])).unwrap();
+        assert_eq!(
+            store.get_outpoint_reservation(&([10u8; 32], 0)).unwrap(),
+            Some([1u8; 32])
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
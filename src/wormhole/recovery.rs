@@ -0,0 +1,134 @@
+use crate::{
+    dogecoin::{
+        p2sh_spend::{DogeP2SHSpendBuilder, DogeP2SHSpendPath},
+        script::opcodes::OP_FALSE,
+    },
+    wormhole::{
+        p2sh_vaa::{WormholeBitcoinLikeVAAMetadata, WormholeRecoveryPath},
+        template_registry::{WormholeGuardianKey, WormholeScriptTemplateRegistry, get_builtin_script_template_registry},
+    },
+};
+
+// the final sequence disables nLockTime and CHECKLOCKTIMEVERIFY fails for it, so recovery inputs use the one below it
+pub const WORMHOLE_RECOVERY_INPUT_SEQUENCE: u32 = 0xfffffffe;
+
+impl DogeP2SHSpendPath for WormholeRecoveryPath {
+    fn get_public_key_hash(&self) -> &[u8; 20] {
+        &self.recovery_public_key_hash
+    }
+    fn get_branch_script(&self) -> Vec<u8> {
+        vec![OP_FALSE]
+    }
+    fn get_input_sequence(&self) -> u32 {
+        WORMHOLE_RECOVERY_INPUT_SEQUENCE
+    }
+}
+
+// Builds a transaction spending VAA-P2SH outputs through the OP_ELSE recovery branch:
+// scriptSig = <sig> <recovery public key> OP_FALSE <redeem script>
+pub type WormholeRecoverySpendBuilder = DogeP2SHSpendBuilder<WormholeRecoveryPath>;
+
+impl WormholeRecoverySpendBuilder {
+    // P2PK templates need a key with its full public key, see WormholeGuardianKey::new_with_public_key
+    pub fn new(metadata: &WormholeBitcoinLikeVAAMetadata, guardian_key: &WormholeGuardianKey) -> anyhow::Result<Self> {
+        Self::new_with_registry(get_builtin_script_template_registry(), metadata, guardian_key)
+    }
+    pub fn new_with_registry(
+        registry: &WormholeScriptTemplateRegistry,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        guardian_key: &WormholeGuardianKey,
+    ) -> anyhow::Result<Self> {
+        let recovery = metadata
+            .recovery
+            .ok_or_else(|| anyhow::anyhow!("metadata has no recovery branch"))?;
+        Ok(Self::new_for_path(
+            recovery,
+            registry.build_locking_script(metadata, guardian_key)?,
+            recovery.lock_time,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        dogecoin::{
+            address::{AddressToBTCScript, BTCAddress160},
+            constants::DogeTestNetConfig,
+            hash::CommonDogeHashProvider,
+            script::interpreter::{STANDARD_SCRIPT_VERIFY_FLAGS, ScriptError, verify_transaction_input},
+            transaction::{BTCTransaction, BTCTransactionInput, BTCTransactionInputWithoutScript},
+        },
+        secp256k1::signer::{CompressedPublicKeyToP2PKH, SimpleSinglePublicKeySigner, SimpleSingleSigner},
+        wormhole::{
+            guardian_processor::WormholeGuardianProcessorSync, p2sh_vaa::WormholeBitcoinLikeVAAMessage,
+            script_template::{WORMHOLE_VAA_TEMPLATE_V1, WORMHOLE_VAA_TEMPLATE_V1_P2PK},
+            test_utils::example_metadata,
+        },
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+    type Network = DogeTestNetConfig;
+
+    #[test]
+    fn test_recovery_spend_passes_script_verification() {
+        let recovery_signer =
+            SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([9u8; 32]).unwrap();
+        let guardian_signer =
+            || SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([7u8; 32]).unwrap();
+        let guardian_key =
+            WormholeGuardianKey::new_with_public_key::<Hasher>(guardian_signer().get_compressed_public_key().0);
+        let registry = get_builtin_script_template_registry();
+        for template_version in [WORMHOLE_VAA_TEMPLATE_V1, WORMHOLE_VAA_TEMPLATE_V1_P2PK] {
+            let metadata = WormholeBitcoinLikeVAAMetadata {
+                template_version,
+                recovery: Some(WormholeRecoveryPath {
+                    lock_time: 5_000_000,
+                    recovery_public_key_hash: recovery_signer.get_compressed_public_key().to_p2pkh_address::<Hasher>(),
+                }),
+                ..example_metadata()
+            };
+            let address = registry.get_p2sh_address::<Hasher>(&metadata, &guardian_key).unwrap();
+            let spent_output = address.to_btc_output(10_000_000);
+            let funding_transaction =
+                BTCTransaction::from_io(vec![BTCTransactionInput::default()], vec![spent_output.clone()]);
+
+            let builder = WormholeRecoverySpendBuilder::new(&metadata, &guardian_key)
+                .unwrap()
+                .with_input(funding_transaction.get_hash::<Hasher>(), 0)
+                .with_output(BTCAddress160::new_p2pkh([0xbb; 20]).to_btc_output(9_900_000));
+            let transaction = builder.sign::<Hasher, _>(&recovery_signer).unwrap();
+            assert_eq!(transaction.locktime, 5_000_000);
+            verify_transaction_input::<Hasher>(&transaction, 0, &spent_output.script, STANDARD_SCRIPT_VERIFY_FLAGS)
+                .unwrap();
+
+            // before the recovery height a correctly signed spend only fails CHECKLOCKTIMEVERIFY
+            let early = builder.clone().with_locktime(4_999_999).sign::<Hasher, _>(&recovery_signer).unwrap();
+            assert_eq!(
+                verify_transaction_input::<Hasher>(&early, 0, &spent_output.script, STANDARD_SCRIPT_VERIFY_FLAGS),
+                Err(ScriptError::UnsatisfiedLockTime)
+            );
+
+            // the guardian branch of the same address is still spendable right away
+            let message = WormholeBitcoinLikeVAAMessage {
+                metadata: WormholeBitcoinLikeVAAMetadata {
+                    total_output_amount: 9_900_000,
+                    max_doge_transaction_fee: 100_000,
+                    ..metadata
+                },
+                inputs: vec![BTCTransactionInputWithoutScript::new_simple(funding_transaction.get_hash::<Hasher>(), 0)],
+                outputs: vec![BTCAddress160::new_p2pkh([0xbb; 20]).to_btc_output(9_900_000)],
+            };
+            let mut rpc_provider = HashMap::new();
+            rpc_provider.insert(funding_transaction.get_txid::<Hasher>(), funding_transaction);
+            WormholeGuardianProcessorSync::new::<Hasher>(rpc_provider, guardian_signer())
+                .with_script_verification(STANDARD_SCRIPT_VERIFY_FLAGS)
+                .validate_p2sh_vaa_message_and_sign_sync::<Hasher, Network>(message)
+                .unwrap();
+        }
+    }
+}
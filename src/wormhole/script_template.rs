@@ -1,6 +1,9 @@
+use crate::dogecoin::script::num::push_script_num;
+
 // DATA INSTRUCTIONS
 const OP_PUSHBYTES_32: u8 = 0x20;
 const OP_PUSHBYTES_20: u8 = 0x14;
+const OP_PUSHBYTES_33: u8 = 0x21;
 const OP_PUSH1: u8 = 0x01;
 const OP_PUSHDATA1: u8 = 0x4c;
 
@@ -15,6 +18,25 @@ const OP_2DROP: u8 = 0x6d;
 
 const OP_HASH160: u8 = 0xa9;
 const OP_CHECKSIG: u8 = 0xac;
+const OP_CHECKMULTISIG: u8 = 0xae;
+const OP_IF: u8 = 0x63;
+const OP_ELSE: u8 = 0x67;
+const OP_ENDIF: u8 = 0x68;
+const OP_CHECKLOCKTIMEVERIFY: u8 = 0xb1;
+
+// template versions carried in WormholeBitcoinLikeVAAMetadata::template_version
+// the low 7 bits select the header encoding, the high bit replaces the P2PKH tail with a compact P2PK tail
+pub const WORMHOLE_VAA_TEMPLATE_V1: u8 = 1;
+pub const WORMHOLE_VAA_TEMPLATE_P2PK_FLAG: u8 = 0x80;
+pub const WORMHOLE_VAA_TEMPLATE_V1_P2PK: u8 = WORMHOLE_VAA_TEMPLATE_V1 | WORMHOLE_VAA_TEMPLATE_P2PK_FLAG;
+// v2 pushes the emitter chain as a minimal CScriptNum. v1 keeps the legacy btc_script_push_number encoding
+// (chains 128..=255 read back as negative numbers, chains above 255 violate MINIMALDATA) so existing addresses stay valid
+pub const WORMHOLE_VAA_TEMPLATE_V2: u8 = 2;
+pub const WORMHOLE_VAA_TEMPLATE_V2_P2PK: u8 = WORMHOLE_VAA_TEMPLATE_V2 | WORMHOLE_VAA_TEMPLATE_P2PK_FLAG;
+
+// standardness limits for p2sh redeem scripts (MAX_SCRIPT_ELEMENT_SIZE and MAX_P2SH_SIGOPS in dogecoin core)
+pub const MAX_STANDARD_P2SH_REDEEM_SCRIPT_SIZE: usize = 520;
+pub const MAX_STANDARD_P2SH_MULTISIG_KEYS: usize = 15;
 
 /*
 const PLACEHOLDER_EMITTER_ADDRESS: [u8; 32] = [0x69u8; 32];
@@ -70,6 +92,27 @@ const fn btc_script_size_of_push_number(x: u16) -> usize {
 }
 
 
+// <emitter chain> <emitter address> OP_2DROP <seed> OP_DROP, shared by every template so each sub-address gets a unique script
+fn push_wormhole_vaa_script_header(
+    data: &mut Vec<u8>,
+    header_version: u8,
+    emitter_chain: u16,
+    emitter_contract_address: &[u8; 32],
+    sub_address_seed: &[u8; 32],
+) -> anyhow::Result<()> {
+    match header_version {
+        WORMHOLE_VAA_TEMPLATE_V1 => data.extend_from_slice(&btc_script_push_number(emitter_chain)),
+        WORMHOLE_VAA_TEMPLATE_V2 => data.extend_from_slice(&push_script_num(emitter_chain as i64)),
+        _ => anyhow::bail!("unknown wormhole vaa script header version {}", header_version),
+    }
+    data.extend_from_slice(&[OP_PUSHBYTES_32]);
+    data.extend_from_slice(emitter_contract_address);
+    data.extend_from_slice(&[OP_2DROP, OP_PUSHBYTES_32]);
+    data.extend_from_slice(sub_address_seed);
+    data.push(OP_DROP);
+    Ok(())
+}
+
 pub fn construct_wormhole_vaa_script(
     emitter_chain: u16,
     emitter_contract_address: &[u8; 32],
@@ -79,16 +122,302 @@ pub fn construct_wormhole_vaa_script(
     let script_size = SIZE_OF_WORMHOLE_SPENDSCRIPT_TPL+ btc_script_size_of_push_number(emitter_chain);
     let mut data = Vec::with_capacity(script_size);
 
-    data.extend_from_slice(&btc_script_push_number(emitter_chain));
-    data.extend_from_slice(&[OP_PUSHBYTES_32]);
-    data.extend_from_slice(emitter_contract_address);
-    data.extend_from_slice(&[OP_2DROP, OP_PUSHBYTES_32]);
-    data.extend_from_slice(sub_address_seed);
-    data.extend_from_slice(&[OP_DROP,OP_DUP,OP_HASH160,OP_PUSHBYTES_20]);
-    data.extend_from_slice(guardian_public_key_hash);
-    data.extend_from_slice(&[OP_EQUALVERIFY, OP_CHECKSIG]);
+    push_wormhole_vaa_script_header(&mut data, WORMHOLE_VAA_TEMPLATE_V1, emitter_chain, emitter_contract_address, sub_address_seed)
+        .expect("v1 is a known header version");
+    push_p2pkh_tail(&mut data, guardian_public_key_hash);
 
     data
 
 }
 
+// the guardian branch of a VAA template script
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub enum WormholeVAAGuardianTail<'a> {
+    // OP_DUP OP_HASH160 <public key hash> OP_EQUALVERIFY OP_CHECKSIG
+    PublicKeyHash(&'a [u8; 20]),
+    // <public key> OP_CHECKSIG
+    PublicKey(&'a [u8; 33]),
+}
+
+// any header version / guardian tail combination, with an optional (lock time, recovery public key hash) branch
+pub fn construct_wormhole_vaa_template_script(
+    header_version: u8,
+    emitter_chain: u16,
+    emitter_contract_address: &[u8; 32],
+    sub_address_seed: &[u8; 32],
+    guardian_tail: WormholeVAAGuardianTail,
+    recovery: Option<(u32, &[u8; 20])>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut tail = Vec::new();
+    match guardian_tail {
+        WormholeVAAGuardianTail::PublicKeyHash(public_key_hash) => push_p2pkh_tail(&mut tail, public_key_hash),
+        WormholeVAAGuardianTail::PublicKey(public_key) => push_p2pk_tail(&mut tail, public_key),
+    }
+    let mut data = Vec::new();
+    push_wormhole_vaa_script_header(&mut data, header_version, emitter_chain, emitter_contract_address, sub_address_seed)?;
+    match recovery {
+        Some((recovery_lock_time, recovery_public_key_hash)) => {
+            push_recovery_branches(&mut data, &tail, recovery_lock_time, recovery_public_key_hash)
+        }
+        None => data.extend_from_slice(&tail),
+    }
+    Ok(data)
+}
+
+fn construct_v1_script_with_tail(
+    emitter_chain: u16,
+    emitter_contract_address: &[u8; 32],
+    sub_address_seed: &[u8; 32],
+    guardian_tail: WormholeVAAGuardianTail,
+    recovery: Option<(u32, &[u8; 20])>,
+) -> Vec<u8> {
+    construct_wormhole_vaa_template_script(
+        WORMHOLE_VAA_TEMPLATE_V1,
+        emitter_chain,
+        emitter_contract_address,
+        sub_address_seed,
+        guardian_tail,
+        recovery,
+    )
+    .expect("v1 is a known header version")
+}
+
+// OP_DUP OP_HASH160 <public key hash> OP_EQUALVERIFY OP_CHECKSIG
+fn push_p2pkh_tail(data: &mut Vec<u8>, public_key_hash: &[u8; 20]) {
+    data.extend_from_slice(&[OP_DUP, OP_HASH160, OP_PUSHBYTES_20]);
+    data.extend_from_slice(public_key_hash);
+    data.extend_from_slice(&[OP_EQUALVERIFY, OP_CHECKSIG]);
+}
+
+// <public key> OP_CHECKSIG
+fn push_p2pk_tail(data: &mut Vec<u8>, public_key: &[u8; 33]) {
+    data.push(OP_PUSHBYTES_33);
+    data.extend_from_slice(public_key);
+    data.push(OP_CHECKSIG);
+}
+
+// OP_IF <guardian tail> OP_ELSE <lock time> OP_CHECKLOCKTIMEVERIFY OP_DROP <recovery P2PKH tail> OP_ENDIF
+fn push_recovery_branches(
+    data: &mut Vec<u8>,
+    guardian_tail: &[u8],
+    recovery_lock_time: u32,
+    recovery_public_key_hash: &[u8; 20],
+) {
+    data.push(OP_IF);
+    data.extend_from_slice(guardian_tail);
+    data.push(OP_ELSE);
+    data.extend_from_slice(&push_script_num(recovery_lock_time as i64));
+    data.extend_from_slice(&[OP_CHECKLOCKTIMEVERIFY, OP_DROP]);
+    push_p2pkh_tail(data, recovery_public_key_hash);
+    data.push(OP_ENDIF);
+}
+
+// same header as construct_wormhole_vaa_script with a <33 byte guardian public key> OP_CHECKSIG tail,
+// the scriptSig then only carries the signature and the redeem script
+pub fn construct_wormhole_vaa_p2pk_script(
+    emitter_chain: u16,
+    emitter_contract_address: &[u8; 32],
+    sub_address_seed: &[u8; 32],
+    guardian_public_key: &[u8; 33],
+) -> Vec<u8> {
+    construct_v1_script_with_tail(
+        emitter_chain,
+        emitter_contract_address,
+        sub_address_seed,
+        WormholeVAAGuardianTail::PublicKey(guardian_public_key),
+        None,
+    )
+}
+
+/*
+Same header as construct_wormhole_vaa_script with an emergency recovery branch:
+    OP_IF
+        OP_DUP OP_HASH160 <guardian public key hash> OP_EQUALVERIFY OP_CHECKSIG
+    OP_ELSE
+        <recovery lock time> OP_CHECKLOCKTIMEVERIFY OP_DROP
+        OP_DUP OP_HASH160 <recovery public key hash> OP_EQUALVERIFY OP_CHECKSIG
+    OP_ENDIF
+*/
+pub fn construct_wormhole_vaa_recovery_script(
+    emitter_chain: u16,
+    emitter_contract_address: &[u8; 32],
+    sub_address_seed: &[u8; 32],
+    guardian_public_key_hash: &[u8; 20],
+    recovery_lock_time: u32,
+    recovery_public_key_hash: &[u8; 20],
+) -> Vec<u8> {
+    construct_v1_script_with_tail(
+        emitter_chain,
+        emitter_contract_address,
+        sub_address_seed,
+        WormholeVAAGuardianTail::PublicKeyHash(guardian_public_key_hash),
+        Some((recovery_lock_time, recovery_public_key_hash)),
+    )
+}
+
+// construct_wormhole_vaa_recovery_script with the compact P2PK guardian branch
+pub fn construct_wormhole_vaa_p2pk_recovery_script(
+    emitter_chain: u16,
+    emitter_contract_address: &[u8; 32],
+    sub_address_seed: &[u8; 32],
+    guardian_public_key: &[u8; 33],
+    recovery_lock_time: u32,
+    recovery_public_key_hash: &[u8; 20],
+) -> Vec<u8> {
+    construct_v1_script_with_tail(
+        emitter_chain,
+        emitter_contract_address,
+        sub_address_seed,
+        WormholeVAAGuardianTail::PublicKey(guardian_public_key),
+        Some((recovery_lock_time, recovery_public_key_hash)),
+    )
+}
+
+// same header as construct_wormhole_vaa_script with an OP_m <pubkeys> OP_n OP_CHECKMULTISIG tail
+pub fn construct_wormhole_vaa_multisig_script(
+    emitter_chain: u16,
+    emitter_contract_address: &[u8; 32],
+    sub_address_seed: &[u8; 32],
+    required_signatures: usize,
+    public_keys: &[[u8; 33]],
+) -> anyhow::Result<Vec<u8>> {
+    construct_wormhole_vaa_multisig_template_script(
+        WORMHOLE_VAA_TEMPLATE_V1,
+        emitter_chain,
+        emitter_contract_address,
+        sub_address_seed,
+        required_signatures,
+        public_keys,
+    )
+}
+
+// construct_wormhole_vaa_multisig_script for any header version
+pub fn construct_wormhole_vaa_multisig_template_script(
+    header_version: u8,
+    emitter_chain: u16,
+    emitter_contract_address: &[u8; 32],
+    sub_address_seed: &[u8; 32],
+    required_signatures: usize,
+    public_keys: &[[u8; 33]],
+) -> anyhow::Result<Vec<u8>> {
+    if public_keys.is_empty() || public_keys.len() > MAX_STANDARD_P2SH_MULTISIG_KEYS {
+        anyhow::bail!(
+            "multisig must have between 1 and {} public keys, got {}",
+            MAX_STANDARD_P2SH_MULTISIG_KEYS,
+            public_keys.len()
+        );
+    }
+    if required_signatures == 0 || required_signatures > public_keys.len() {
+        anyhow::bail!(
+            "invalid multisig threshold {} of {}",
+            required_signatures,
+            public_keys.len()
+        );
+    }
+    let mut data = Vec::new();
+    push_wormhole_vaa_script_header(&mut data, header_version, emitter_chain, emitter_contract_address, sub_address_seed)?;
+    data.push(OP_1 - 1 + required_signatures as u8);
+    for public_key in public_keys.iter() {
+        data.push(OP_PUSHBYTES_33);
+        data.extend_from_slice(public_key);
+    }
+    data.extend_from_slice(&[OP_1 - 1 + public_keys.len() as u8, OP_CHECKMULTISIG]);
+
+    if data.len() > MAX_STANDARD_P2SH_REDEEM_SCRIPT_SIZE {
+        anyhow::bail!(
+            "multisig redeem script is {} bytes, more than the standard limit of {}",
+            data.len(),
+            MAX_STANDARD_P2SH_REDEEM_SCRIPT_SIZE
+        );
+    }
+    Ok(data)
+}
+
+pub(crate) fn btc_script_read_number(script: &[u8]) -> Option<(u16, usize)> {
+    match *script.first()? {
+        OP_0 => Some((0, 1)),
+        x if (OP_1..(OP_1 + 16)).contains(&x) => Some(((x - OP_1 + 1) as u16, 1)),
+        OP_PUSH1 => Some((*script.get(1)? as u16, 2)),
+        OP_PUSHDATA1 if *script.get(1)? == 0x02 => {
+            Some((u16::from_le_bytes([*script.get(2)?, *script.get(3)?]), 4))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dogecoin::{
+            hash::CommonDogeHashProvider,
+            script::{
+                interpreter::{STANDARD_SCRIPT_VERIFY_FLAGS, ScriptError, TransactionSignatureChecker, eval_script},
+                num::decode_script_num,
+            },
+            transaction::BTCTransaction,
+        },
+        wormhole::template_registry::{WormholeGuardianKeyCommitment, get_builtin_script_template_registry},
+    };
+
+    use super::*;
+
+    fn eval_chain_push(push: &[u8]) -> Result<Vec<Vec<u8>>, ScriptError> {
+        let transaction = BTCTransaction::from_io(vec![], vec![]);
+        let checker = TransactionSignatureChecker::<CommonDogeHashProvider>::new(&transaction, 0);
+        let mut stack = Vec::new();
+        eval_script::<CommonDogeHashProvider, _>(&mut stack, push, STANDARD_SCRIPT_VERIFY_FLAGS, &checker)?;
+        Ok(stack)
+    }
+
+    #[test]
+    fn test_v2_header_pushes_minimal_script_nums() {
+        // v1 bytes are frozen: chain 200 reads back as -72 and chain 300 is a non-minimal push
+        let v1 = construct_wormhole_vaa_script(200, &[1u8; 32], &[2u8; 32], &[3u8; 20]);
+        assert_eq!(v1[..2], [OP_PUSH1, 200]);
+        assert_eq!(decode_script_num(&eval_chain_push(&v1[..2]).unwrap()[0], true, 4), Ok(-72));
+        let v1 = construct_wormhole_vaa_script(300, &[1u8; 32], &[2u8; 32], &[3u8; 20]);
+        assert_eq!(v1[..4], [OP_PUSHDATA1, 0x02, 0x2c, 0x01]);
+        assert_eq!(eval_chain_push(&v1[..4]), Err(ScriptError::MinimalData));
+
+        for (emitter_chain, expected_push) in [(5u16, vec![0x55]), (200, vec![0x02, 200, 0x00]), (300, vec![0x02, 0x2c, 0x01])] {
+            let tail = WormholeVAAGuardianTail::PublicKeyHash(&[3u8; 20]);
+            let v2 = construct_wormhole_vaa_template_script(
+                WORMHOLE_VAA_TEMPLATE_V2,
+                emitter_chain,
+                &[1u8; 32],
+                &[2u8; 32],
+                tail,
+                None,
+            )
+            .unwrap();
+            assert_eq!(v2[..expected_push.len()], expected_push);
+            let stack = eval_chain_push(&expected_push).unwrap();
+            assert_eq!(decode_script_num(&stack[0], true, 4), Ok(emitter_chain as i64));
+            // only the chain push differs from v1
+            let v1 = construct_wormhole_vaa_script(emitter_chain, &[1u8; 32], &[2u8; 32], &[3u8; 20]);
+            assert_eq!(v2[expected_push.len()..], v1[btc_script_push_number(emitter_chain).len()..]);
+        }
+        let tail = WormholeVAAGuardianTail::PublicKeyHash(&[3u8; 20]);
+        assert!(construct_wormhole_vaa_template_script(3, 1, &[1u8; 32], &[2u8; 32], tail, None).is_err());
+    }
+
+    #[test]
+    fn test_registry_parses_v1_scripts() {
+        let registry = get_builtin_script_template_registry();
+        for emitter_chain in [0u16, 1, 16, 17, 255, 256, 0xffff] {
+            let script = construct_wormhole_vaa_script(emitter_chain, &[1u8; 32], &[2u8; 32], &[3u8; 20]);
+            let params = registry.parse_locking_script(&script).unwrap();
+            assert_eq!(params.template_version, WORMHOLE_VAA_TEMPLATE_V1);
+            assert_eq!(params.emitter_chain, emitter_chain);
+            assert_eq!(params.emitter_contract_address, [1u8; 32]);
+            assert_eq!(params.sub_address_seed, [2u8; 32]);
+            assert_eq!(params.guardian_key, WormholeGuardianKeyCommitment::PublicKeyHash([3u8; 20]));
+        }
+        let mut script = construct_wormhole_vaa_script(1, &[1u8; 32], &[2u8; 32], &[3u8; 20]);
+        *script.last_mut().unwrap() = OP_DROP;
+        assert_eq!(registry.parse_locking_script(&script), None);
+        // 5 encoded as OP_PUSH1 0x05 instead of OP_5
+        let script = [vec![OP_PUSH1, 5], construct_wormhole_vaa_script(5, &[1u8; 32], &[2u8; 32], &[3u8; 20])[1..].to_vec()].concat();
+        assert_eq!(registry.parse_locking_script(&script), None);
+    }
+}
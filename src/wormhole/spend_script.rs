@@ -0,0 +1,219 @@
+use crate::{
+    dogecoin::transaction::BTCTransactionInput,
+    secp256k1::signature::decode_der_signature,
+    wormhole::template_registry::{
+        WormholeRedeemScriptParams, WormholeScriptTemplateRegistry, WormholeSpendBranch,
+        get_builtin_script_template_registry,
+    },
+};
+
+// <DER signature || sighash type> as pushed in a scriptSig
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct WormholeVAAScriptSigSignature {
+    pub der_signature: Vec<u8>,
+    pub sighash_type: u8,
+}
+
+impl WormholeVAAScriptSigSignature {
+    pub fn from_push(push: &[u8]) -> anyhow::Result<Self> {
+        let (sighash_type, der_signature) = push
+            .split_last()
+            .ok_or_else(|| anyhow::anyhow!("scriptSig signature is empty"))?;
+        Ok(Self {
+            der_signature: der_signature.to_vec(),
+            sighash_type: *sighash_type,
+        })
+    }
+    // r || s of the signature, fails if it is not strict DER
+    pub fn get_signature_rs(&self) -> anyhow::Result<[u8; 64]> {
+        decode_der_signature(&self.der_signature)
+    }
+}
+
+/*
+A VAA-P2SH spend scriptSig of any registered template:
+    P2PKH guardian branch: <sig> <public key> [OP_TRUE] <redeem script>
+    P2PK guardian branch: <sig> [OP_TRUE] <redeem script>
+    recovery branch: <sig> <recovery public key> OP_FALSE <redeem script>
+    multisig: OP_0 <sig 1> .. <sig m> <redeem script>
+*/
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct WormholeVAASpendScriptSig {
+    pub branch: WormholeSpendBranch,
+    pub signatures: Vec<WormholeVAAScriptSigSignature>,
+    // None for the P2PK guardian branch and multisig spends
+    pub public_key: Option<[u8; 33]>,
+    pub redeem_script: Vec<u8>,
+    pub redeem_script_params: WormholeRedeemScriptParams,
+}
+
+impl WormholeVAASpendScriptSig {
+    // parses scriptSigs of the builtin templates
+    pub fn from_script_sig(script_sig: &[u8]) -> anyhow::Result<Self> {
+        Self::from_script_sig_with_registry(get_builtin_script_template_registry(), script_sig)
+    }
+    // the template is picked by the redeem script, the last push of the scriptSig
+    pub fn from_script_sig_with_registry(
+        registry: &WormholeScriptTemplateRegistry,
+        script_sig: &[u8],
+    ) -> anyhow::Result<Self> {
+        let parsed = registry
+            .parse_script_sig(script_sig)
+            .ok_or_else(|| anyhow::anyhow!("scriptSig does not spend a redeem script of any registered template"))?;
+        Ok(Self {
+            branch: parsed.branch,
+            signatures: parsed
+                .signatures
+                .iter()
+                .map(|push| WormholeVAAScriptSigSignature::from_push(push))
+                .collect::<anyhow::Result<Vec<WormholeVAAScriptSigSignature>>>()?,
+            public_key: parsed.public_key,
+            redeem_script: parsed.redeem_script,
+            redeem_script_params: parsed.redeem_script_params,
+        })
+    }
+    pub fn from_input(input: &BTCTransactionInput) -> anyhow::Result<Self> {
+        Self::from_script_sig(&input.script)
+    }
+    pub fn get_template_version(&self) -> u8 {
+        self.redeem_script_params.get_template_version()
+    }
+    // the emitter, sub-address seed and guardian key(s) committed to by the redeem script
+    pub fn get_redeem_script_params(&self) -> &WormholeRedeemScriptParams {
+        &self.redeem_script_params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dogecoin::{
+            hash::CommonDogeHashProvider, script::opcodes::OP_FALSE, sighash::SIGHASH_ALL,
+            transaction::encode_binary_stack_item,
+        },
+        secp256k1::signer::{SimpleSinglePublicKeySigner, SimpleSingleSigner},
+        wormhole::{
+            p2sh_vaa::{WormholeBitcoinLikeVAAMetadata, WormholeGuardianMultisig, WormholeRecoveryPath},
+            script_template::construct_wormhole_vaa_script,
+            template_registry::{WormholeGuardianKey, WormholeGuardianKeyCommitment},
+            test_utils::example_metadata,
+        },
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+
+    #[test]
+    fn test_parse_vaa_spend_script_sig() {
+        let signer = SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([7u8; 32]).unwrap();
+        let signature = signer.sign_message([5u8; 32]).unwrap();
+        let redeem_script = construct_wormhole_vaa_script(30, &[1u8; 32], &[2u8; 32], &[3u8; 20]);
+        let input = BTCTransactionInput {
+            hash: [0u8; 32],
+            index: 0,
+            script: [signature.to_btc_script(), encode_binary_stack_item(&redeem_script)].concat(),
+            sequence: 0xffffffff,
+        };
+
+        let parsed = WormholeVAASpendScriptSig::from_input(&input).unwrap();
+        assert_eq!(parsed.branch, WormholeSpendBranch::Guardian);
+        assert_eq!(parsed.signatures[0].sighash_type, SIGHASH_ALL as u8);
+        assert_eq!(parsed.public_key, Some(signature.public_key));
+        assert_eq!(parsed.signatures[0].get_signature_rs().unwrap(), signature.with_low_s().signature);
+        let WormholeRedeemScriptParams::Template(params) = parsed.get_redeem_script_params() else {
+            panic!("expected a single key template");
+        };
+        assert_eq!(params.emitter_chain, 30);
+        assert_eq!(params.emitter_contract_address, [1u8; 32]);
+        assert_eq!(params.sub_address_seed, [2u8; 32]);
+        assert_eq!(params.guardian_key, WormholeGuardianKeyCommitment::PublicKeyHash([3u8; 20]));
+
+        assert!(WormholeVAASpendScriptSig::from_script_sig(&signature.to_btc_script()).is_err());
+    }
+
+    #[test]
+    fn test_script_sig_round_trip_for_every_template() {
+        let registry = get_builtin_script_template_registry();
+        let signer = SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([7u8; 32]).unwrap();
+        let signature = signer.sign_message([5u8; 32]).unwrap();
+        let guardian_key = WormholeGuardianKey::new_with_public_key::<Hasher>(signature.public_key);
+        let recovery = WormholeRecoveryPath { lock_time: 5_000_000, recovery_public_key_hash: [9u8; 20] };
+        let base_metadata = WormholeBitcoinLikeVAAMetadata {
+            emitter_chain: 300,
+            template_version: 0,
+            ..example_metadata()
+        };
+
+        for template_version in registry.get_template_versions() {
+            let template = registry.get(template_version).unwrap();
+            for recovery in [None, Some(recovery)] {
+                let metadata = WormholeBitcoinLikeVAAMetadata { template_version, recovery, ..base_metadata };
+                let redeem_script = template.build_locking_script(&metadata, &guardian_key).unwrap();
+
+                let script_sig = template.build_script_sig(&metadata, &signature, &redeem_script);
+                let parsed = WormholeVAASpendScriptSig::from_script_sig(&script_sig).unwrap();
+                assert_eq!(parsed.branch, WormholeSpendBranch::Guardian);
+                assert_eq!(parsed.get_template_version(), template_version);
+                assert_eq!(parsed.redeem_script, redeem_script);
+                assert_eq!(parsed.signatures.len(), 1);
+                assert_eq!(parsed.signatures[0].get_signature_rs().unwrap(), signature.with_low_s().signature);
+                let expected_public_key = (!template.requires_public_key()).then_some(signature.public_key);
+                assert_eq!(parsed.public_key, expected_public_key);
+
+                if recovery.is_none() {
+                    continue;
+                }
+                // the recovery key signs here, any key parses the same
+                let script_sig =
+                    [signature.to_btc_script(), vec![OP_FALSE], encode_binary_stack_item(&redeem_script)].concat();
+                let parsed = WormholeVAASpendScriptSig::from_script_sig(&script_sig).unwrap();
+                assert_eq!(parsed.branch, WormholeSpendBranch::Recovery);
+                assert_eq!(parsed.get_template_version(), template_version);
+                assert_eq!(parsed.public_key, Some(signature.public_key));
+                let WormholeRedeemScriptParams::Template(params) = parsed.get_redeem_script_params() else {
+                    panic!("expected a single key template");
+                };
+                assert_eq!(params.recovery, recovery);
+            }
+        }
+
+        let other_signature = SimpleSinglePublicKeySigner::new_insecure_memory_signer_with_private_key::<Hasher>([8u8; 32])
+            .unwrap()
+            .sign_message([5u8; 32])
+            .unwrap();
+        let multisig = WormholeGuardianMultisig::new(2, vec![signature.public_key, other_signature.public_key]).unwrap();
+        for template_version in registry.get_multisig_template_versions() {
+            let template = registry.get_multisig(template_version).unwrap();
+            let metadata = WormholeBitcoinLikeVAAMetadata { template_version, ..base_metadata };
+            let redeem_script = template.build_locking_script(&metadata, &multisig).unwrap();
+            let script_sig = template.build_script_sig(&[&signature, &other_signature], &redeem_script);
+            let parsed = WormholeVAASpendScriptSig::from_script_sig(&script_sig).unwrap();
+            assert_eq!(parsed.branch, WormholeSpendBranch::Guardian);
+            assert_eq!(parsed.get_template_version(), template_version);
+            assert_eq!(parsed.public_key, None);
+            assert_eq!(
+                parsed
+                    .signatures
+                    .iter()
+                    .map(|signature| signature.get_signature_rs().unwrap())
+                    .collect::<Vec<[u8; 64]>>(),
+                vec![signature.with_low_s().signature, other_signature.with_low_s().signature]
+            );
+            let WormholeRedeemScriptParams::Multisig(params) = parsed.get_redeem_script_params() else {
+                panic!("expected a multisig template");
+            };
+            assert_eq!(params.multisig, multisig);
+
+            // CHECKMULTISIG needs exactly m signatures after the dummy element
+            let script_sig = template.build_script_sig(&[&signature], &redeem_script);
+            assert!(WormholeVAASpendScriptSig::from_script_sig(&script_sig).is_err());
+        }
+
+        // a registry without the template cannot parse its scriptSigs
+        let metadata = WormholeBitcoinLikeVAAMetadata { template_version: 1, ..base_metadata };
+        let redeem_script = metadata.get_locking_script(&guardian_key.public_key_hash).unwrap();
+        let script_sig = [signature.to_btc_script(), encode_binary_stack_item(&redeem_script)].concat();
+        assert!(WormholeVAASpendScriptSig::from_script_sig_with_registry(&WormholeScriptTemplateRegistry::new(), &script_sig).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    dogecoin::{address::BTCAddress160, hash::DogeHashProvider},
+    wormhole::{
+        p2sh_vaa::{WormholeBitcoinLikeVAAMetadata, WormholeRecoveryPath},
+        script_template::WORMHOLE_VAA_TEMPLATE_V1,
+        template_registry::{WormholeGuardianKey, WormholeScriptTemplateRegistry},
+    },
+};
+
+// prefixed to every derivation so the seeds can't collide with hashes used for anything else
+pub const WORMHOLE_SUB_ADDRESS_SEED_DOMAIN: &[u8] = b"psy-doge-bridge-wormhole/sub-address-seed/v1";
+
+// sha256(domain || emitter_chain (u16 BE) || emitter_contract_address || user_public_key || account_index (u32 BE))
+pub fn derive_sub_address_seed<Hasher: DogeHashProvider>(
+    emitter_chain: u16,
+    emitter_contract_address: &[u8; 32],
+    user_public_key: &[u8; 32],
+    account_index: u32,
+) -> [u8; 32] {
+    let mut preimage = Vec::with_capacity(WORMHOLE_SUB_ADDRESS_SEED_DOMAIN.len() + 2 + 32 + 32 + 4);
+    preimage.extend_from_slice(WORMHOLE_SUB_ADDRESS_SEED_DOMAIN);
+    preimage.extend_from_slice(&emitter_chain.to_be_bytes());
+    preimage.extend_from_slice(emitter_contract_address);
+    preimage.extend_from_slice(user_public_key);
+    preimage.extend_from_slice(&account_index.to_be_bytes());
+    Hasher::hash_bytes_sha256(&preimage)
+}
+
+// who a derived sub-address belongs to
+#[derive(PartialEq, Clone, Copy, Debug, Eq, Hash, PartialOrd, Ord)]
+pub struct WormholeSubAddressOwner {
+    pub emitter_chain: u16,
+    pub emitter_contract_address: [u8; 32],
+    // the solana account the deposits are credited to
+    pub user_public_key: [u8; 32],
+    pub account_index: u32,
+}
+
+impl WormholeSubAddressOwner {
+    pub fn get_sub_address_seed<Hasher: DogeHashProvider>(&self) -> [u8; 32] {
+        derive_sub_address_seed::<Hasher>(
+            self.emitter_chain,
+            &self.emitter_contract_address,
+            &self.user_public_key,
+            self.account_index,
+        )
+    }
+    // the metadata of deposits to this sub-address, the amount and fee fields are left to the spend
+    pub fn get_metadata<Hasher: DogeHashProvider>(
+        &self,
+        template_version: u8,
+        recovery: Option<WormholeRecoveryPath>,
+    ) -> WormholeBitcoinLikeVAAMetadata {
+        WormholeBitcoinLikeVAAMetadata {
+            emitter_chain: self.emitter_chain,
+            emitter_contract_address: self.emitter_contract_address,
+            sub_address_seed: self.get_sub_address_seed::<Hasher>(),
+            total_output_amount: 0,
+            max_doge_transaction_fee: 0,
+            min_doge_transaction_fee: 0,
+            template_version,
+            recovery,
+        }
+    }
+}
+
+// maps derived p2sh addresses back to their owner, addresses are always recomputed so a persisted index can't lie
+#[derive(Clone, Debug)]
+pub struct WormholeSubAddressIndex {
+    guardian_key: WormholeGuardianKey,
+    template_version: u8,
+    recovery: Option<WormholeRecoveryPath>,
+    owners: HashMap<BTCAddress160, WormholeSubAddressOwner>,
+    script_template_registry: WormholeScriptTemplateRegistry,
+}
+
+// the registry only derives the addresses, which the owner maps already key on
+impl PartialEq for WormholeSubAddressIndex {
+    fn eq(&self, other: &Self) -> bool {
+        self.guardian_key == other.guardian_key
+            && self.template_version == other.template_version
+            && self.recovery == other.recovery
+            && self.owners == other.owners
+    }
+}
+
+impl Eq for WormholeSubAddressIndex {}
+
+/*
+Text encoding:
+    guardian <guardian public key hash> [<guardian public key>]
+    template <template version>
+    [recovery <lock time> <recovery public key hash>]
+    <emitter_chain> <emitter_contract_address> <user_public_key> <account_index>
+    ...
+Indexes saved before the template line existed are read as v1 without a recovery branch.
+*/
+impl WormholeSubAddressIndex {
+    // v1 P2PKH addresses without a recovery branch
+    pub fn new(guardian_public_key_hash: [u8; 20]) -> Self {
+        Self::new_with_guardian_key(WormholeGuardianKey::new(guardian_public_key_hash))
+    }
+    // a key with its full public key can also derive P2PK template addresses
+    pub fn new_with_guardian_key(guardian_key: WormholeGuardianKey) -> Self {
+        Self {
+            guardian_key,
+            template_version: WORMHOLE_VAA_TEMPLATE_V1,
+            recovery: None,
+            owners: HashMap::new(),
+            script_template_registry: WormholeScriptTemplateRegistry::new_with_builtin_templates(),
+        }
+    }
+    // the address configuration below must be set before any owner is inserted
+    pub fn with_template_version(mut self, template_version: u8) -> Self {
+        self.template_version = template_version;
+        self
+    }
+    pub fn with_recovery(mut self, recovery: WormholeRecoveryPath) -> Self {
+        self.recovery = Some(recovery);
+        self
+    }
+    pub fn with_script_template_registry(mut self, script_template_registry: WormholeScriptTemplateRegistry) -> Self {
+        self.script_template_registry = script_template_registry;
+        self
+    }
+    pub fn get_guardian_public_key_hash(&self) -> &[u8; 20] {
+        &self.guardian_key.public_key_hash
+    }
+    pub fn get_metadata<Hasher: DogeHashProvider>(&self, owner: &WormholeSubAddressOwner) -> WormholeBitcoinLikeVAAMetadata {
+        owner.get_metadata::<Hasher>(self.template_version, self.recovery)
+    }
+    pub fn get_p2sh_address<Hasher: DogeHashProvider>(
+        &self,
+        owner: &WormholeSubAddressOwner,
+    ) -> anyhow::Result<BTCAddress160> {
+        self.script_template_registry
+            .get_p2sh_address::<Hasher>(&self.get_metadata::<Hasher>(owner), &self.guardian_key)
+    }
+    pub fn insert<Hasher: DogeHashProvider>(&mut self, owner: WormholeSubAddressOwner) -> anyhow::Result<BTCAddress160> {
+        let address = self.get_p2sh_address::<Hasher>(&owner)?;
+        self.owners.insert(address, owner);
+        Ok(address)
+    }
+    // derives and indexes account indices [first_account_index, first_account_index + count) of a user
+    pub fn insert_range<Hasher: DogeHashProvider>(
+        &mut self,
+        emitter_chain: u16,
+        emitter_contract_address: [u8; 32],
+        user_public_key: [u8; 32],
+        first_account_index: u32,
+        count: u32,
+    ) -> anyhow::Result<Vec<BTCAddress160>> {
+        let end = first_account_index
+            .checked_add(count)
+            .ok_or_else(|| anyhow::anyhow!("account index range overflows u32"))?;
+        (first_account_index..end)
+            .map(|account_index| {
+                self.insert::<Hasher>(WormholeSubAddressOwner {
+                    emitter_chain,
+                    emitter_contract_address,
+                    user_public_key,
+                    account_index,
+                })
+            })
+            .collect()
+    }
+    pub fn lookup(&self, address: &BTCAddress160) -> Option<&WormholeSubAddressOwner> {
+        self.owners.get(address)
+    }
+    pub fn len(&self) -> usize {
+        self.owners.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_empty()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut owners = self.owners.values().collect::<Vec<&WormholeSubAddressOwner>>();
+        owners.sort();
+        let mut text = format!("guardian {}", hex::encode(self.guardian_key.public_key_hash));
+        if let Some(public_key) = self.guardian_key.public_key {
+            text.push_str(&format!(" {}", hex::encode(public_key)));
+        }
+        text.push_str(&format!("\ntemplate {}\n", self.template_version));
+        if let Some(recovery) = self.recovery {
+            text.push_str(&format!(
+                "recovery {} {}\n",
+                recovery.lock_time,
+                hex::encode(recovery.recovery_public_key_hash)
+            ));
+        }
+        for owner in owners {
+            text.push_str(&format!(
+                "{} {} {} {}\n",
+                owner.emitter_chain,
+                hex::encode(owner.emitter_contract_address),
+                hex::encode(owner.user_public_key),
+                owner.account_index
+            ));
+        }
+        text
+    }
+    pub fn from_text<Hasher: DogeHashProvider>(text: &str) -> anyhow::Result<Self> {
+        Self::from_text_with_registry::<Hasher>(text, WormholeScriptTemplateRegistry::new_with_builtin_templates())
+    }
+    pub fn from_text_with_registry<Hasher: DogeHashProvider>(
+        text: &str,
+        script_template_registry: WormholeScriptTemplateRegistry,
+    ) -> anyhow::Result<Self> {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty()).peekable();
+        let header = lines
+            .next()
+            .and_then(|line| line.strip_prefix("guardian "))
+            .ok_or_else(|| anyhow::anyhow!("sub-address index is missing its guardian header"))?;
+        let guardian_key = match header.split(' ').collect::<Vec<&str>>().as_slice() {
+            [public_key_hash] => {
+                let mut guardian_public_key_hash = [0u8; 20];
+                hex::decode_to_slice(public_key_hash, &mut guardian_public_key_hash)?;
+                WormholeGuardianKey::new(guardian_public_key_hash)
+            }
+            [public_key_hash, public_key] => {
+                let mut guardian_public_key = [0u8; 33];
+                hex::decode_to_slice(public_key, &mut guardian_public_key)?;
+                let guardian_key = WormholeGuardianKey::new_with_public_key::<Hasher>(guardian_public_key);
+                if hex::encode(guardian_key.public_key_hash) != *public_key_hash {
+                    anyhow::bail!("sub-address index guardian public key does not match its hash");
+                }
+                guardian_key
+            }
+            _ => anyhow::bail!("malformed sub-address index guardian header: {}", header),
+        };
+
+        let mut index = Self::new_with_guardian_key(guardian_key).with_script_template_registry(script_template_registry);
+        if let Some(template_version) = lines.next_if(|line| line.starts_with("template ")) {
+            index.template_version = template_version["template ".len()..].parse()?;
+        }
+        if let Some(recovery) = lines.next_if(|line| line.starts_with("recovery ")) {
+            let parts = recovery.split(' ').collect::<Vec<&str>>();
+            if parts.len() != 3 {
+                anyhow::bail!("malformed sub-address index recovery line: {}", recovery);
+            }
+            let mut recovery_public_key_hash = [0u8; 20];
+            hex::decode_to_slice(parts[2], &mut recovery_public_key_hash)?;
+            index.recovery = Some(WormholeRecoveryPath {
+                lock_time: parts[1].parse()?,
+                recovery_public_key_hash,
+            });
+        }
+        for line in lines {
+            let parts = line.split(' ').collect::<Vec<&str>>();
+            if parts.len() != 4 {
+                anyhow::bail!("malformed sub-address index line: {}", line);
+            }
+            let mut emitter_contract_address = [0u8; 32];
+            hex::decode_to_slice(parts[1], &mut emitter_contract_address)?;
+            let mut user_public_key = [0u8; 32];
+            hex::decode_to_slice(parts[2], &mut user_public_key)?;
+            index.insert::<Hasher>(WormholeSubAddressOwner {
+                emitter_chain: parts[0].parse()?,
+                emitter_contract_address,
+                user_public_key,
+                account_index: parts[3].parse()?,
+            })?;
+        }
+        Ok(index)
+    }
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_text())?;
+        Ok(())
+    }
+    pub fn load_from_file<Hasher: DogeHashProvider, P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        Self::from_text::<Hasher>(&std::fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dogecoin::{constants::DogeTestNetConfig, hash::CommonDogeHashProvider},
+        wormhole::script_template::{WORMHOLE_VAA_TEMPLATE_V2, WORMHOLE_VAA_TEMPLATE_V2_P2PK},
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+    type Network = DogeTestNetConfig;
+
+    #[test]
+    fn test_sub_address_index_round_trip() {
+        let seed = derive_sub_address_seed::<Hasher>(1, &[1u8; 32], &[4u8; 32], 0);
+        assert_eq!(seed, derive_sub_address_seed::<Hasher>(1, &[1u8; 32], &[4u8; 32], 0));
+        assert_ne!(seed, derive_sub_address_seed::<Hasher>(1, &[1u8; 32], &[4u8; 32], 1));
+        assert_ne!(seed, derive_sub_address_seed::<Hasher>(2, &[1u8; 32], &[4u8; 32], 0));
+
+        let mut index = WormholeSubAddressIndex::new([3u8; 20]);
+        let addresses = index.insert_range::<Hasher>(1, [1u8; 32], [4u8; 32], 0, 3).unwrap();
+        index.insert_range::<Hasher>(1, [1u8; 32], [5u8; 32], 0, 2).unwrap();
+        assert_eq!(index.len(), 5);
+
+        let owner = index.lookup(&addresses[2]).unwrap();
+        assert_eq!(owner.user_public_key, [4u8; 32]);
+        assert_eq!(owner.account_index, 2);
+
+        let rebuilt = WormholeSubAddressIndex::from_text::<Hasher>(&index.to_text()).unwrap();
+        assert_eq!(rebuilt, index);
+        assert_eq!(rebuilt.lookup(&addresses[2]), Some(owner));
+    }
+
+    #[test]
+    fn test_sub_address_index_honors_template_and_recovery() {
+        let owner = WormholeSubAddressOwner {
+            emitter_chain: 300,
+            emitter_contract_address: [1u8; 32],
+            user_public_key: [4u8; 32],
+            account_index: 0,
+        };
+        let public_key = [0x02u8; 33];
+        let recovery = WormholeRecoveryPath { lock_time: 5_000_000, recovery_public_key_hash: [9u8; 20] };
+        let mut index = WormholeSubAddressIndex::new_with_guardian_key(WormholeGuardianKey::new_with_public_key::<Hasher>(public_key))
+            .with_template_version(WORMHOLE_VAA_TEMPLATE_V2_P2PK)
+            .with_recovery(recovery);
+        let address = index.insert::<Hasher>(owner).unwrap();
+        let metadata = owner.get_metadata::<Hasher>(WORMHOLE_VAA_TEMPLATE_V2_P2PK, Some(recovery));
+        assert_eq!(address, metadata.get_p2sh_address_for_public_key::<Network, Hasher>(&public_key).unwrap());
+
+        let rebuilt = WormholeSubAddressIndex::from_text::<Hasher>(&index.to_text()).unwrap();
+        assert_eq!(rebuilt, index);
+        assert_eq!(rebuilt.lookup(&address), Some(&owner));
+
+        // P2PK templates cannot be derived from the key hash alone, v2 encodes chain 300 differently from v1
+        let mut index = WormholeSubAddressIndex::new([3u8; 20]).with_template_version(WORMHOLE_VAA_TEMPLATE_V2_P2PK);
+        assert!(index.insert::<Hasher>(owner).is_err());
+        let mut index = WormholeSubAddressIndex::new([3u8; 20]).with_template_version(WORMHOLE_VAA_TEMPLATE_V2);
+        let v2_address = index.insert::<Hasher>(owner).unwrap();
+        assert_ne!(v2_address, WormholeSubAddressIndex::new([3u8; 20]).get_p2sh_address::<Hasher>(&owner).unwrap());
+
+        // indexes saved before the template line are read as v1
+        let legacy = WormholeSubAddressIndex::from_text::<Hasher>(&format!(
+            "guardian {}\n300 {} {} 0\n",
+            hex::encode([3u8; 20]),
+            hex::encode([1u8; 32]),
+            hex::encode([4u8; 32])
+        ))
+        .unwrap();
+        let v1_metadata = owner.get_metadata::<Hasher>(WORMHOLE_VAA_TEMPLATE_V1, None);
+        assert_eq!(
+            legacy.lookup(&v1_metadata.get_p2sh_address::<Network, Hasher>(&[3u8; 20]).unwrap()),
+            Some(&owner)
+        );
+    }
+}
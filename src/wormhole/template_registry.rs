@@ -0,0 +1,835 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, OnceLock},
+};
+
+use crate::{
+    dogecoin::{
+        address::BTCAddress160,
+        hash::DogeHashProvider,
+        script::{
+            interpreter::get_script_pushes,
+            num::read_script_num_push,
+            opcodes::{OP_FALSE, OP_TRUE},
+        },
+        transaction::encode_binary_stack_item,
+        transaction_layout::BTCTransactionLayout,
+    },
+    secp256k1::signature::PsyCompressedSecp256K1Signature,
+    wormhole::{
+        p2sh_vaa::{WormholeBitcoinLikeVAAMetadata, WormholeGuardianMultisig, WormholeRecoveryPath},
+        script_template::{
+            WORMHOLE_VAA_TEMPLATE_P2PK_FLAG, WORMHOLE_VAA_TEMPLATE_V1, WORMHOLE_VAA_TEMPLATE_V2,
+            WormholeVAAGuardianTail, btc_script_read_number, construct_wormhole_vaa_multisig_template_script,
+            construct_wormhole_vaa_template_script,
+        },
+    },
+};
+
+// upper bound of a low-s DER signature push: <push len> <71 byte DER signature || sighash type>
+pub const MAX_SIGNATURE_PUSH_SIZE: usize = 73;
+// lower bound used for size ranges: 32 byte r and 31 byte s (shorter integers are negligibly rare)
+pub const MIN_SIGNATURE_PUSH_SIZE: usize = 71;
+// <push len> <33 byte compressed public key>
+const PUBLIC_KEY_PUSH_SIZE: usize = 34;
+
+// The guardian key a locking script is built for, templates pick whichever form they commit to
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct WormholeGuardianKey {
+    pub public_key_hash: [u8; 20],
+    pub public_key: Option<[u8; 33]>,
+}
+
+impl WormholeGuardianKey {
+    pub fn new(public_key_hash: [u8; 20]) -> Self {
+        Self {
+            public_key_hash,
+            public_key: None,
+        }
+    }
+    pub fn new_with_public_key<Hasher: DogeHashProvider>(public_key: [u8; 33]) -> Self {
+        Self {
+            public_key_hash: Hasher::bitcoin_hash160(&public_key),
+            public_key: Some(public_key),
+        }
+    }
+}
+
+// what a parsed locking script commits to for the guardian branch
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub enum WormholeGuardianKeyCommitment {
+    PublicKeyHash([u8; 20]),
+    PublicKey([u8; 33]),
+}
+
+// The parameters committed to by a locking script of any registered template
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct WormholeTemplateScriptParams {
+    pub template_version: u8,
+    pub emitter_chain: u16,
+    pub emitter_contract_address: [u8; 32],
+    pub sub_address_seed: [u8; 32],
+    pub guardian_key: WormholeGuardianKeyCommitment,
+    pub recovery: Option<WormholeRecoveryPath>,
+}
+
+// The parameters committed to by a locking script of a registered multisig template
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct WormholeMultisigScriptParams {
+    pub template_version: u8,
+    pub emitter_chain: u16,
+    pub emitter_contract_address: [u8; 32],
+    pub sub_address_seed: [u8; 32],
+    pub multisig: WormholeGuardianMultisig,
+}
+
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub enum WormholeRedeemScriptParams {
+    Template(WormholeTemplateScriptParams),
+    Multisig(WormholeMultisigScriptParams),
+}
+
+impl WormholeRedeemScriptParams {
+    pub fn get_template_version(&self) -> u8 {
+        match self {
+            Self::Template(params) => params.template_version,
+            Self::Multisig(params) => params.template_version,
+        }
+    }
+}
+
+// the OP_IF branch of the redeem script a scriptSig spends, scripts without a recovery branch only have the guardian one
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub enum WormholeSpendBranch {
+    Guardian,
+    Recovery,
+}
+
+// A VAA-P2SH scriptSig split into its parts
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct WormholeTemplateScriptSig {
+    pub branch: WormholeSpendBranch,
+    // <DER signature || sighash type> pushes in scriptSig order, one for the single key branches
+    pub signatures: Vec<Vec<u8>>,
+    // pushed next to the signature by the P2PKH guardian branch and the recovery branch
+    pub public_key: Option<[u8; 33]>,
+    pub redeem_script: Vec<u8>,
+    pub redeem_script_params: WormholeRedeemScriptParams,
+}
+
+// A VAA-P2SH spend policy, selected by WormholeBitcoinLikeVAAMetadata::template_version
+pub trait WormholeVAAScriptTemplate: Send + Sync {
+    fn get_template_version(&self) -> u8;
+    // true if the locking script embeds the full guardian public key instead of its hash
+    fn requires_public_key(&self) -> bool;
+    fn build_locking_script(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        guardian_key: &WormholeGuardianKey,
+    ) -> anyhow::Result<Vec<u8>>;
+    // the scriptSig spending the guardian branch of redeem_script with signature
+    fn build_script_sig(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        signature: &PsyCompressedSecp256K1Signature,
+        redeem_script: &[u8],
+    ) -> Vec<u8>;
+    // upper bound of the guardian branch scriptSig size
+    fn estimate_script_sig_size(&self, metadata: &WormholeBitcoinLikeVAAMetadata) -> anyhow::Result<usize>;
+    // lower bound of the guardian branch scriptSig size, the guardian branch carries a single signature
+    fn estimate_min_script_sig_size(&self, metadata: &WormholeBitcoinLikeVAAMetadata) -> anyhow::Result<usize> {
+        Ok(self.estimate_script_sig_size(metadata)? - (MAX_SIGNATURE_PUSH_SIZE - MIN_SIGNATURE_PUSH_SIZE))
+    }
+    // upper bound of the serialized size of one signed guardian input (outpoint, scriptSig and sequence)
+    fn estimate_signed_input_size(&self, metadata: &WormholeBitcoinLikeVAAMetadata) -> anyhow::Result<usize> {
+        Ok(BTCTransactionLayout::new(vec![self.estimate_script_sig_size(metadata)?], vec![]).get_total_size()
+            - BTCTransactionLayout::default().get_total_size())
+    }
+    // the inverse of build_locking_script, returns None if the script was not built by this template
+    fn parse_locking_script(&self, script: &[u8]) -> Option<WormholeTemplateScriptParams>;
+    // the inverse of build_script_sig and of the recovery spend scriptSig (see recovery::WormholeRecoverySpendBuilder)
+    fn parse_script_sig(&self, script_sig: &[u8]) -> Option<WormholeTemplateScriptSig> {
+        parse_template_script_sig(self, script_sig)
+    }
+}
+
+// A VAA-P2SH m-of-n guardian multisig policy, selected by template_version like WormholeVAAScriptTemplate
+pub trait WormholeVAAMultisigScriptTemplate: Send + Sync {
+    fn get_template_version(&self) -> u8;
+    fn build_locking_script(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        multisig: &WormholeGuardianMultisig,
+    ) -> anyhow::Result<Vec<u8>>;
+    // signatures must follow the order of the multisig keys, CHECKMULTISIG walks them in order
+    fn build_script_sig(&self, signatures: &[&PsyCompressedSecp256K1Signature], redeem_script: &[u8]) -> Vec<u8>;
+    // upper bound of the scriptSig size
+    fn estimate_script_sig_size(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        multisig: &WormholeGuardianMultisig,
+    ) -> anyhow::Result<usize>;
+    // lower bound of the scriptSig size, every one of the m signatures has the shortest DER encoding
+    fn estimate_min_script_sig_size(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        multisig: &WormholeGuardianMultisig,
+    ) -> anyhow::Result<usize> {
+        Ok(self.estimate_script_sig_size(metadata, multisig)?
+            - multisig.required_signatures * (MAX_SIGNATURE_PUSH_SIZE - MIN_SIGNATURE_PUSH_SIZE))
+    }
+    // the inverse of build_locking_script, returns None if the script was not built by this template
+    fn parse_locking_script(&self, script: &[u8]) -> Option<WormholeMultisigScriptParams>;
+    // the inverse of build_script_sig
+    fn parse_script_sig(&self, script_sig: &[u8]) -> Option<WormholeTemplateScriptSig> {
+        parse_multisig_script_sig(self, script_sig)
+    }
+}
+
+// pushed before the redeem script to select the OP_IF branch, empty for scripts without a recovery branch
+fn get_guardian_branch_selector(metadata: &WormholeBitcoinLikeVAAMetadata) -> Vec<u8> {
+    if metadata.recovery.is_some() { vec![OP_TRUE] } else { vec![] }
+}
+
+fn estimate_guardian_script_sig_size(
+    metadata: &WormholeBitcoinLikeVAAMetadata,
+    redeem_script: &[u8],
+    public_key_push_size: usize,
+) -> usize {
+    MAX_SIGNATURE_PUSH_SIZE
+        + public_key_push_size
+        + get_guardian_branch_selector(metadata).len()
+        + encode_binary_stack_item(redeem_script).len()
+}
+
+// the stack item pushed by OP_TRUE
+const OP_TRUE_ITEM: u8 = 1;
+
+// CHECKLOCKTIMEVERIFY reads lock times as 5 byte script numbers
+const LOCK_TIME_MAX_NUM_SIZE: usize = 5;
+
+fn build_template_script(
+    metadata: &WormholeBitcoinLikeVAAMetadata,
+    header_version: u8,
+    guardian_tail: WormholeVAAGuardianTail,
+) -> anyhow::Result<Vec<u8>> {
+    construct_wormhole_vaa_template_script(
+        header_version,
+        metadata.emitter_chain,
+        &metadata.emitter_contract_address,
+        &metadata.sub_address_seed,
+        guardian_tail,
+        metadata
+            .recovery
+            .as_ref()
+            .map(|recovery| (recovery.lock_time, &recovery.recovery_public_key_hash)),
+    )
+}
+
+/*
+Splits a VAA template script into its parts:
+    <emitter chain> PUSH32 <emitter> OP_2DROP PUSH32 <seed> OP_DROP <guardian tail>
+or, with a recovery branch:
+    ... OP_DROP OP_IF <guardian tail> OP_ELSE <lock time> OP_CHECKLOCKTIMEVERIFY OP_DROP
+        OP_DUP OP_HASH160 PUSH20 <recovery key hash> OP_EQUALVERIFY OP_CHECKSIG OP_ENDIF
+Only offsets are read here, the caller rebuilds the script to check every opcode.
+*/
+struct WormholeTemplateScriptParts<'a> {
+    emitter_chain: u16,
+    emitter_contract_address: [u8; 32],
+    sub_address_seed: [u8; 32],
+    guardian_tail: &'a [u8],
+    recovery: Option<WormholeRecoveryPath>,
+}
+
+// (emitter chain, emitter, seed, everything after the header)
+type WormholeTemplateHeader<'a> = (u16, [u8; 32], [u8; 32], &'a [u8]);
+
+fn split_template_header(script: &[u8], header_version: u8) -> Option<WormholeTemplateHeader<'_>> {
+    let (emitter_chain, offset) = match header_version {
+        WORMHOLE_VAA_TEMPLATE_V1 => btc_script_read_number(script)?,
+        _ => {
+            let (emitter_chain, offset) = read_script_num_push(script, 3)?;
+            (emitter_chain.try_into().ok()?, offset)
+        }
+    };
+    let rest = script.get(offset..)?;
+    let emitter_contract_address = rest.get(1..33)?.try_into().ok()?;
+    let sub_address_seed = rest.get(35..67)?.try_into().ok()?;
+    Some((emitter_chain, emitter_contract_address, sub_address_seed, rest.get(68..)?))
+}
+
+fn split_template_script(
+    script: &[u8],
+    header_version: u8,
+    guardian_tail_size: usize,
+) -> Option<WormholeTemplateScriptParts<'_>> {
+    let (emitter_chain, emitter_contract_address, sub_address_seed, tail) =
+        split_template_header(script, header_version)?;
+    if tail.len() == guardian_tail_size {
+        return Some(WormholeTemplateScriptParts {
+            emitter_chain,
+            emitter_contract_address,
+            sub_address_seed,
+            guardian_tail: tail,
+            recovery: None,
+        });
+    }
+    let guardian_tail = tail.get(1..1 + guardian_tail_size)?;
+    let (lock_time, lock_time_size) =
+        read_script_num_push(tail.get(2 + guardian_tail_size..)?, LOCK_TIME_MAX_NUM_SIZE)?;
+    let recovery_offset = 2 + guardian_tail_size + lock_time_size + 5;
+    let recovery = WormholeRecoveryPath {
+        lock_time: lock_time.try_into().ok()?,
+        recovery_public_key_hash: tail.get(recovery_offset..recovery_offset + 20)?.try_into().ok()?,
+    };
+    Some(WormholeTemplateScriptParts {
+        emitter_chain,
+        emitter_contract_address,
+        sub_address_seed,
+        guardian_tail,
+        recovery: Some(recovery),
+    })
+}
+
+// the metadata fields a locking script commits to, used to rebuild parsed scripts
+fn get_script_metadata(
+    template_version: u8,
+    emitter_chain: u16,
+    emitter_contract_address: [u8; 32],
+    sub_address_seed: [u8; 32],
+    recovery: Option<WormholeRecoveryPath>,
+) -> WormholeBitcoinLikeVAAMetadata {
+    WormholeBitcoinLikeVAAMetadata {
+        emitter_chain,
+        emitter_contract_address,
+        sub_address_seed,
+        total_output_amount: 0,
+        max_doge_transaction_fee: 0,
+        min_doge_transaction_fee: 0,
+        template_version,
+        recovery,
+    }
+}
+
+fn parse_with_template<Template: WormholeVAAScriptTemplate + ?Sized>(
+    template: &Template,
+    script: &[u8],
+    header_version: u8,
+    guardian_tail_size: usize,
+    read_guardian_key: impl Fn(&[u8]) -> Option<WormholeGuardianKeyCommitment>,
+) -> Option<WormholeTemplateScriptParams> {
+    let WormholeTemplateScriptParts {
+        emitter_chain,
+        emitter_contract_address,
+        sub_address_seed,
+        guardian_tail,
+        recovery,
+    } = split_template_script(script, header_version, guardian_tail_size)?;
+    let params = WormholeTemplateScriptParams {
+        template_version: template.get_template_version(),
+        emitter_chain,
+        emitter_contract_address,
+        sub_address_seed,
+        guardian_key: read_guardian_key(guardian_tail)?,
+        recovery,
+    };
+    let metadata = get_script_metadata(
+        params.template_version,
+        emitter_chain,
+        emitter_contract_address,
+        sub_address_seed,
+        recovery,
+    );
+    let guardian_key = match params.guardian_key {
+        WormholeGuardianKeyCommitment::PublicKeyHash(public_key_hash) => WormholeGuardianKey::new(public_key_hash),
+        // the P2PK templates never read the hash
+        WormholeGuardianKeyCommitment::PublicKey(public_key) => WormholeGuardianKey {
+            public_key_hash: [0u8; 20],
+            public_key: Some(public_key),
+        },
+    };
+    // rebuilding the script checks every opcode and that all numbers used the canonical encoding
+    let expected = template.build_locking_script(&metadata, &guardian_key).ok()?;
+    if expected == script { Some(params) } else { None }
+}
+
+/*
+Guardian branch: <sig> [<public key> for P2PKH tails] [OP_TRUE if the script has a recovery branch] <redeem script>
+Recovery branch: <sig> <recovery public key> OP_FALSE <redeem script>
+*/
+fn parse_template_script_sig<Template: WormholeVAAScriptTemplate + ?Sized>(
+    template: &Template,
+    script_sig: &[u8],
+) -> Option<WormholeTemplateScriptSig> {
+    let mut pushes = get_script_pushes(script_sig).ok()?;
+    let redeem_script = pushes.pop()?;
+    let params = template.parse_locking_script(&redeem_script)?;
+    let branch = match params.recovery {
+        None => WormholeSpendBranch::Guardian,
+        Some(_) => match pushes.pop()?.as_slice() {
+            [OP_TRUE_ITEM] => WormholeSpendBranch::Guardian,
+            [] => WormholeSpendBranch::Recovery,
+            _ => return None,
+        },
+    };
+    // the recovery branch always has a P2PKH tail
+    let public_key = if branch == WormholeSpendBranch::Recovery || !template.requires_public_key() {
+        Some(pushes.pop()?.try_into().ok()?)
+    } else {
+        None
+    };
+    let [signature]: [Vec<u8>; 1] = pushes.try_into().ok()?;
+    Some(WormholeTemplateScriptSig {
+        branch,
+        signatures: vec![signature],
+        public_key,
+        redeem_script,
+        redeem_script_params: WormholeRedeemScriptParams::Template(params),
+    })
+}
+
+// OP_0 <sig 1> .. <sig m> <redeem script>
+fn parse_multisig_script_sig<Template: WormholeVAAMultisigScriptTemplate + ?Sized>(
+    template: &Template,
+    script_sig: &[u8],
+) -> Option<WormholeTemplateScriptSig> {
+    let mut pushes = get_script_pushes(script_sig).ok()?;
+    let redeem_script = pushes.pop()?;
+    let params = template.parse_locking_script(&redeem_script)?;
+    let (dummy, signatures) = pushes.split_first()?;
+    if !dummy.is_empty() || signatures.len() != params.multisig.required_signatures {
+        return None;
+    }
+    Some(WormholeTemplateScriptSig {
+        branch: WormholeSpendBranch::Guardian,
+        signatures: signatures.to_vec(),
+        public_key: None,
+        redeem_script,
+        redeem_script_params: WormholeRedeemScriptParams::Multisig(params),
+    })
+}
+
+// the VAA header with the OP_DUP OP_HASH160 <key hash> OP_EQUALVERIFY OP_CHECKSIG tail
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct WormholeVAAP2PKHTemplate {
+    header_version: u8,
+}
+
+impl WormholeVAAP2PKHTemplate {
+    pub fn new_v1() -> Self {
+        Self { header_version: WORMHOLE_VAA_TEMPLATE_V1 }
+    }
+    pub fn new_v2() -> Self {
+        Self { header_version: WORMHOLE_VAA_TEMPLATE_V2 }
+    }
+}
+
+impl WormholeVAAScriptTemplate for WormholeVAAP2PKHTemplate {
+    fn get_template_version(&self) -> u8 {
+        self.header_version
+    }
+    fn requires_public_key(&self) -> bool {
+        false
+    }
+    fn build_locking_script(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        guardian_key: &WormholeGuardianKey,
+    ) -> anyhow::Result<Vec<u8>> {
+        build_template_script(
+            metadata,
+            self.header_version,
+            WormholeVAAGuardianTail::PublicKeyHash(&guardian_key.public_key_hash),
+        )
+    }
+    // <sig> <public key> [branch] <redeem script>
+    fn build_script_sig(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        signature: &PsyCompressedSecp256K1Signature,
+        redeem_script: &[u8],
+    ) -> Vec<u8> {
+        let mut script_sig = signature.to_btc_script();
+        script_sig.extend_from_slice(&get_guardian_branch_selector(metadata));
+        script_sig.extend_from_slice(&encode_binary_stack_item(redeem_script));
+        script_sig
+    }
+    fn estimate_script_sig_size(&self, metadata: &WormholeBitcoinLikeVAAMetadata) -> anyhow::Result<usize> {
+        let redeem_script = self.build_locking_script(metadata, &WormholeGuardianKey::new([0u8; 20]))?;
+        Ok(estimate_guardian_script_sig_size(metadata, &redeem_script, PUBLIC_KEY_PUSH_SIZE))
+    }
+    fn parse_locking_script(&self, script: &[u8]) -> Option<WormholeTemplateScriptParams> {
+        parse_with_template(self, script, self.header_version, 25, |tail| {
+            Some(WormholeGuardianKeyCommitment::PublicKeyHash(tail.get(3..23)?.try_into().ok()?))
+        })
+    }
+}
+
+// the VAA header with the compact <33 byte public key> OP_CHECKSIG tail
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct WormholeVAAP2PKTemplate {
+    header_version: u8,
+}
+
+impl WormholeVAAP2PKTemplate {
+    pub fn new_v1() -> Self {
+        Self { header_version: WORMHOLE_VAA_TEMPLATE_V1 }
+    }
+    pub fn new_v2() -> Self {
+        Self { header_version: WORMHOLE_VAA_TEMPLATE_V2 }
+    }
+}
+
+impl WormholeVAAScriptTemplate for WormholeVAAP2PKTemplate {
+    fn get_template_version(&self) -> u8 {
+        self.header_version | WORMHOLE_VAA_TEMPLATE_P2PK_FLAG
+    }
+    fn requires_public_key(&self) -> bool {
+        true
+    }
+    fn build_locking_script(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        guardian_key: &WormholeGuardianKey,
+    ) -> anyhow::Result<Vec<u8>> {
+        let public_key = guardian_key.public_key.as_ref().ok_or_else(|| {
+            anyhow::anyhow!(
+                "template version {} commits to the full guardian public key, not its hash",
+                self.get_template_version()
+            )
+        })?;
+        build_template_script(metadata, self.header_version, WormholeVAAGuardianTail::PublicKey(public_key))
+    }
+    // <sig> [branch] <redeem script>
+    fn build_script_sig(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        signature: &PsyCompressedSecp256K1Signature,
+        redeem_script: &[u8],
+    ) -> Vec<u8> {
+        let mut script_sig = signature.to_btc_signature_push();
+        script_sig.extend_from_slice(&get_guardian_branch_selector(metadata));
+        script_sig.extend_from_slice(&encode_binary_stack_item(redeem_script));
+        script_sig
+    }
+    fn estimate_script_sig_size(&self, metadata: &WormholeBitcoinLikeVAAMetadata) -> anyhow::Result<usize> {
+        // script sizes do not depend on the key bytes
+        let guardian_key = WormholeGuardianKey {
+            public_key_hash: [0u8; 20],
+            public_key: Some([0x02u8; 33]),
+        };
+        let redeem_script = self.build_locking_script(metadata, &guardian_key)?;
+        Ok(estimate_guardian_script_sig_size(metadata, &redeem_script, 0))
+    }
+    fn parse_locking_script(&self, script: &[u8]) -> Option<WormholeTemplateScriptParams> {
+        parse_with_template(self, script, self.header_version, 35, |tail| {
+            Some(WormholeGuardianKeyCommitment::PublicKey(tail.get(1..34)?.try_into().ok()?))
+        })
+    }
+}
+
+// the VAA header with an OP_m <public keys> OP_n OP_CHECKMULTISIG tail, without a recovery branch
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct WormholeVAAMultisigTemplate {
+    header_version: u8,
+}
+
+impl WormholeVAAMultisigTemplate {
+    pub fn new_v1() -> Self {
+        Self { header_version: WORMHOLE_VAA_TEMPLATE_V1 }
+    }
+    pub fn new_v2() -> Self {
+        Self { header_version: WORMHOLE_VAA_TEMPLATE_V2 }
+    }
+}
+
+impl WormholeVAAMultisigScriptTemplate for WormholeVAAMultisigTemplate {
+    fn get_template_version(&self) -> u8 {
+        self.header_version
+    }
+    fn build_locking_script(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        multisig: &WormholeGuardianMultisig,
+    ) -> anyhow::Result<Vec<u8>> {
+        if metadata.recovery.is_some() {
+            anyhow::bail!("multisig template version {} has no recovery branch", self.header_version);
+        }
+        construct_wormhole_vaa_multisig_template_script(
+            self.header_version,
+            metadata.emitter_chain,
+            &metadata.emitter_contract_address,
+            &metadata.sub_address_seed,
+            multisig.required_signatures,
+            &multisig.public_keys,
+        )
+    }
+    fn build_script_sig(&self, signatures: &[&PsyCompressedSecp256K1Signature], redeem_script: &[u8]) -> Vec<u8> {
+        // the dummy element popped by the CHECKMULTISIG off-by-one bug, must be empty under NULLDUMMY
+        let mut script_sig = vec![OP_FALSE];
+        for signature in signatures.iter() {
+            script_sig.extend_from_slice(&signature.to_btc_signature_push());
+        }
+        script_sig.extend_from_slice(&encode_binary_stack_item(redeem_script));
+        script_sig
+    }
+    fn estimate_script_sig_size(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        multisig: &WormholeGuardianMultisig,
+    ) -> anyhow::Result<usize> {
+        let redeem_script = self.build_locking_script(metadata, multisig)?;
+        Ok(1 + multisig.required_signatures * MAX_SIGNATURE_PUSH_SIZE + encode_binary_stack_item(&redeem_script).len())
+    }
+    fn parse_locking_script(&self, script: &[u8]) -> Option<WormholeMultisigScriptParams> {
+        let (emitter_chain, emitter_contract_address, sub_address_seed, tail) =
+            split_template_header(script, self.header_version)?;
+        // OP_m (PUSH33 <public key>)* OP_n OP_CHECKMULTISIG
+        let key_count = tail.len().checked_sub(3)? / PUBLIC_KEY_PUSH_SIZE;
+        let required_signatures = tail.first()?.checked_sub(OP_TRUE - 1)? as usize;
+        let public_keys = tail
+            .get(1..1 + key_count * PUBLIC_KEY_PUSH_SIZE)?
+            .chunks(PUBLIC_KEY_PUSH_SIZE)
+            .map(|push| push.get(1..)?.try_into().ok())
+            .collect::<Option<Vec<[u8; 33]>>>()?;
+        let params = WormholeMultisigScriptParams {
+            template_version: self.header_version,
+            emitter_chain,
+            emitter_contract_address,
+            sub_address_seed,
+            multisig: WormholeGuardianMultisig {
+                required_signatures,
+                public_keys,
+            },
+        };
+        let metadata =
+            get_script_metadata(self.header_version, emitter_chain, emitter_contract_address, sub_address_seed, None);
+        // rebuilding the script checks every opcode, the key count and the threshold
+        let expected = self.build_locking_script(&metadata, &params.multisig).ok()?;
+        if expected == script { Some(params) } else { None }
+    }
+}
+
+// The script templates a guardian accepts, keyed by template version
+#[derive(Clone, Default)]
+pub struct WormholeScriptTemplateRegistry {
+    templates: BTreeMap<u8, Arc<dyn WormholeVAAScriptTemplate>>,
+    // multisig deployments look their template version up here instead
+    multisig_templates: BTreeMap<u8, Arc<dyn WormholeVAAMultisigScriptTemplate>>,
+}
+
+impl WormholeScriptTemplateRegistry {
+    pub fn new() -> Self {
+        Self {
+            templates: BTreeMap::new(),
+            multisig_templates: BTreeMap::new(),
+        }
+    }
+    // the templates shipped with this crate
+    pub fn new_with_builtin_templates() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register(WormholeVAAP2PKHTemplate::new_v1())
+            .and_then(|_| registry.register(WormholeVAAP2PKTemplate::new_v1()))
+            .and_then(|_| registry.register(WormholeVAAP2PKHTemplate::new_v2()))
+            .and_then(|_| registry.register(WormholeVAAP2PKTemplate::new_v2()))
+            .and_then(|_| registry.register_multisig(WormholeVAAMultisigTemplate::new_v1()))
+            .and_then(|_| registry.register_multisig(WormholeVAAMultisigTemplate::new_v2()))
+            .expect("builtin template versions are unique");
+        registry
+    }
+    pub fn register<Template: WormholeVAAScriptTemplate + 'static>(&mut self, template: Template) -> anyhow::Result<()> {
+        let version = template.get_template_version();
+        if self.templates.contains_key(&version) {
+            anyhow::bail!("script template version {} is already registered", version);
+        }
+        self.templates.insert(version, Arc::new(template));
+        Ok(())
+    }
+    pub fn get(&self, template_version: u8) -> anyhow::Result<&dyn WormholeVAAScriptTemplate> {
+        self.templates
+            .get(&template_version)
+            .map(|template| template.as_ref())
+            .ok_or_else(|| anyhow::anyhow!("unknown wormhole vaa script template version {}", template_version))
+    }
+    pub fn get_template_versions(&self) -> Vec<u8> {
+        self.templates.keys().copied().collect()
+    }
+    pub fn register_multisig<Template: WormholeVAAMultisigScriptTemplate + 'static>(
+        &mut self,
+        template: Template,
+    ) -> anyhow::Result<()> {
+        let version = template.get_template_version();
+        if self.multisig_templates.contains_key(&version) {
+            anyhow::bail!("multisig script template version {} is already registered", version);
+        }
+        self.multisig_templates.insert(version, Arc::new(template));
+        Ok(())
+    }
+    pub fn get_multisig(&self, template_version: u8) -> anyhow::Result<&dyn WormholeVAAMultisigScriptTemplate> {
+        self.multisig_templates
+            .get(&template_version)
+            .map(|template| template.as_ref())
+            .ok_or_else(|| anyhow::anyhow!("unknown wormhole vaa multisig script template version {}", template_version))
+    }
+    pub fn get_multisig_template_versions(&self) -> Vec<u8> {
+        self.multisig_templates.keys().copied().collect()
+    }
+
+    pub fn build_locking_script(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        guardian_key: &WormholeGuardianKey,
+    ) -> anyhow::Result<Vec<u8>> {
+        self.get(metadata.template_version)?.build_locking_script(metadata, guardian_key)
+    }
+    pub fn get_p2sh_address<Hasher: DogeHashProvider>(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        guardian_key: &WormholeGuardianKey,
+    ) -> anyhow::Result<BTCAddress160> {
+        Ok(BTCAddress160::new_p2sh(Hasher::bitcoin_hash160(
+            &self.build_locking_script(metadata, guardian_key)?,
+        )))
+    }
+    pub fn estimate_script_sig_size(&self, metadata: &WormholeBitcoinLikeVAAMetadata) -> anyhow::Result<usize> {
+        self.get(metadata.template_version)?.estimate_script_sig_size(metadata)
+    }
+    pub fn estimate_signed_input_size(&self, metadata: &WormholeBitcoinLikeVAAMetadata) -> anyhow::Result<usize> {
+        self.get(metadata.template_version)?.estimate_signed_input_size(metadata)
+    }
+    pub fn build_multisig_locking_script(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        multisig: &WormholeGuardianMultisig,
+    ) -> anyhow::Result<Vec<u8>> {
+        self.get_multisig(metadata.template_version)?.build_locking_script(metadata, multisig)
+    }
+    pub fn get_multisig_p2sh_address<Hasher: DogeHashProvider>(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        multisig: &WormholeGuardianMultisig,
+    ) -> anyhow::Result<BTCAddress160> {
+        Ok(BTCAddress160::new_p2sh(Hasher::bitcoin_hash160(
+            &self.build_multisig_locking_script(metadata, multisig)?,
+        )))
+    }
+
+    // tries every registered template, the first match wins
+    pub fn parse_locking_script(&self, script: &[u8]) -> Option<WormholeTemplateScriptParams> {
+        self.templates
+            .values()
+            .find_map(|template| template.parse_locking_script(script))
+    }
+    // tries every registered single key and multisig template, the first match wins
+    pub fn parse_script_sig(&self, script_sig: &[u8]) -> Option<WormholeTemplateScriptSig> {
+        self.templates
+            .values()
+            .find_map(|template| template.parse_script_sig(script_sig))
+            .or_else(|| {
+                self.multisig_templates
+                    .values()
+                    .find_map(|template| template.parse_script_sig(script_sig))
+            })
+    }
+}
+
+impl std::fmt::Debug for WormholeScriptTemplateRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WormholeScriptTemplateRegistry")
+            .field("template_versions", &self.get_template_versions())
+            .field("multisig_template_versions", &self.get_multisig_template_versions())
+            .finish()
+    }
+}
+
+// the builtin registry used by the WormholeBitcoinLikeVAAMetadata helpers
+pub fn get_builtin_script_template_registry() -> &'static WormholeScriptTemplateRegistry {
+    static REGISTRY: OnceLock<WormholeScriptTemplateRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(WormholeScriptTemplateRegistry::new_with_builtin_templates)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dogecoin::{constants::DogeTestNetConfig, hash::CommonDogeHashProvider},
+        wormhole::{script_template::construct_wormhole_vaa_multisig_script, test_utils::example_metadata},
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+
+    #[test]
+    fn test_registry_parses_every_builtin_template() {
+        let registry = WormholeScriptTemplateRegistry::new_with_builtin_templates();
+        let guardian_key = WormholeGuardianKey::new_with_public_key::<Hasher>([0x02; 33]);
+        for template_version in registry.get_template_versions() {
+            for recovery in [
+                None,
+                Some(WormholeRecoveryPath { lock_time: 16, recovery_public_key_hash: [9u8; 20] }),
+                Some(WormholeRecoveryPath { lock_time: 5_000_000, recovery_public_key_hash: [9u8; 20] }),
+            ] {
+                let metadata = WormholeBitcoinLikeVAAMetadata {
+                    emitter_chain: 300,
+                    template_version,
+                    recovery,
+                    ..example_metadata()
+                };
+                let template = registry.get(template_version).unwrap();
+                let script = template.build_locking_script(&metadata, &guardian_key).unwrap();
+                let params = registry.parse_locking_script(&script).unwrap();
+                assert_eq!(params.template_version, template_version);
+                assert_eq!(params.emitter_chain, 300);
+                assert_eq!(params.recovery, recovery);
+                let expected_key = if template.requires_public_key() {
+                    WormholeGuardianKeyCommitment::PublicKey([0x02; 33])
+                } else {
+                    WormholeGuardianKeyCommitment::PublicKeyHash(guardian_key.public_key_hash)
+                };
+                assert_eq!(params.guardian_key, expected_key);
+            }
+        }
+        assert!(registry.get(0x7f).is_err());
+        assert!(registry.clone().register(WormholeVAAP2PKTemplate::new_v1()).is_err());
+        assert_eq!(registry.parse_locking_script(&[0x51, 0xac]), None);
+    }
+
+    #[test]
+    fn test_registry_multisig_templates() {
+        let registry = WormholeScriptTemplateRegistry::new_with_builtin_templates();
+        let multisig = WormholeGuardianMultisig::new(2, vec![[0x02; 33], [0x03; 33], [0x04; 33]]).unwrap();
+        let metadata = WormholeBitcoinLikeVAAMetadata { emitter_chain: 300, ..example_metadata() };
+        assert_eq!(
+            registry.build_multisig_locking_script(&metadata, &multisig).unwrap(),
+            construct_wormhole_vaa_multisig_script(300, &[1u8; 32], &[2u8; 32], 2, &multisig.public_keys).unwrap()
+        );
+        for template_version in registry.get_multisig_template_versions() {
+            let metadata = WormholeBitcoinLikeVAAMetadata { template_version, ..metadata };
+            let template = registry.get_multisig(template_version).unwrap();
+            let script = template.build_locking_script(&metadata, &multisig).unwrap();
+            let params = template.parse_locking_script(&script).unwrap();
+            assert_eq!(params.template_version, template_version);
+            assert_eq!(params.emitter_chain, 300);
+            assert_eq!(params.multisig, multisig);
+            // single key templates never claim a multisig script
+            assert_eq!(registry.parse_locking_script(&script), None);
+        }
+        let recovery = Some(WormholeRecoveryPath { lock_time: 16, recovery_public_key_hash: [9u8; 20] });
+        let recovery_metadata = WormholeBitcoinLikeVAAMetadata { recovery, ..metadata };
+        assert!(registry.build_multisig_locking_script(&recovery_metadata, &multisig).is_err());
+
+        // a registry without v1 templates rejects v1 addresses instead of falling back to the builtin ones
+        let mut registry = WormholeScriptTemplateRegistry::new();
+        registry.register(WormholeVAAP2PKHTemplate::new_v2()).unwrap();
+        let guardian_key = WormholeGuardianKey::new([3u8; 20]);
+        assert!(registry.get_p2sh_address::<Hasher>(&metadata, &guardian_key).is_err());
+        assert!(registry.build_multisig_locking_script(&metadata, &multisig).is_err());
+        let metadata = WormholeBitcoinLikeVAAMetadata { template_version: WORMHOLE_VAA_TEMPLATE_V2, ..metadata };
+        assert_eq!(
+            registry.get_p2sh_address::<Hasher>(&metadata, &guardian_key).unwrap(),
+            metadata.get_p2sh_address::<DogeTestNetConfig, Hasher>(&[3u8; 20]).unwrap()
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{
+    dogecoin::{
+        address::{AddressToBTCScript, BTCAddress160},
+        hash::DogeHashProvider,
+        transaction::{BTCTransaction, BTCTransactionInputWithoutScript},
+    },
+    psy_doge_link::data::{BTCUTXO, BTCUTXOStatus},
+    wormhole::{
+        p2sh_vaa::{WormholeBitcoinLikeVAAMessage, WormholeBitcoinLikeVAAMetadata},
+        script_template::WORMHOLE_VAA_TEMPLATE_V1,
+    },
+};
+
+// the guardian key hash the example addresses are derived for
+pub(crate) const EXAMPLE_GUARDIAN_PUBLIC_KEY_HASH: [u8; 20] = [3u8; 20];
+
+// v1 template without recovery, amounts and fees left at zero for the test to fill in
+pub(crate) fn example_metadata() -> WormholeBitcoinLikeVAAMetadata {
+    WormholeBitcoinLikeVAAMetadata {
+        emitter_chain: 1,
+        emitter_contract_address: [1u8; 32],
+        sub_address_seed: [2u8; 32],
+        total_output_amount: 0,
+        max_doge_transaction_fee: 0,
+        min_doge_transaction_fee: 0,
+        template_version: WORMHOLE_VAA_TEMPLATE_V1,
+        recovery: None,
+    }
+}
+
+// two inputs paying one p2pkh output, for encoding round trips
+pub(crate) fn example_message() -> WormholeBitcoinLikeVAAMessage {
+    WormholeBitcoinLikeVAAMessage {
+        metadata: WormholeBitcoinLikeVAAMetadata {
+            total_output_amount: 900_000,
+            max_doge_transaction_fee: 1_000_000,
+            min_doge_transaction_fee: 100_000,
+            ..example_metadata()
+        },
+        inputs: vec![
+            BTCTransactionInputWithoutScript::new_simple([3u8; 32], 0),
+            BTCTransactionInputWithoutScript::new([4u8; 32], 7, 0xfffffffe),
+        ],
+        outputs: vec![BTCAddress160::new_p2pkh([0xbb; 20]).to_btc_output(900_000)],
+    }
+}
+
+// output vout of a funding transaction as a confirmed utxo
+pub(crate) fn get_confirmed_utxo<Hasher: DogeHashProvider>(transaction: &BTCTransaction, vout: u32) -> BTCUTXO {
+    BTCUTXO {
+        status: BTCUTXOStatus { block_hash: [0u8; 32], block_height: 10, block_time: 0, confirmed: true },
+        txid: transaction.get_txid::<Hasher>(),
+        value: transaction.outputs[vout as usize].value,
+        vout,
+    }
+}
+
+// the funding transactions spent by message, in input order
+pub(crate) fn get_input_transactions<Hasher: DogeHashProvider>(
+    message: &WormholeBitcoinLikeVAAMessage,
+    funding_transactions: &[BTCTransaction],
+) -> Vec<BTCTransaction> {
+    message
+        .inputs
+        .iter()
+        .map(|input| {
+            funding_transactions
+                .iter()
+                .find(|tx| tx.get_hash::<Hasher>() == input.hash)
+                .unwrap()
+                .clone()
+        })
+        .collect()
+}
@@ -0,0 +1,120 @@
+use crate::dogecoin::hash::DogeHashProvider;
+
+// A guardian TSS key the processor accepts deposits for, identified by hash160(compressed public key)
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct WormholeTSSKey {
+    pub public_key_hash: [u8; 20],
+    // the full compressed key, required to spend outputs of the P2PK VAA templates
+    pub public_key: Option<[u8; 33]>,
+    // unix timestamp (seconds) from which utxos locked to this key can be spent
+    pub activation_time: u32,
+    // unix timestamp (seconds) after which the key is no longer used, 0 means it never expires
+    pub expiration_time: u32,
+}
+
+impl WormholeTSSKey {
+    pub fn new(public_key_hash: [u8; 20]) -> Self {
+        Self {
+            public_key_hash,
+            public_key: None,
+            activation_time: 0,
+            expiration_time: 0,
+        }
+    }
+    pub fn new_with_public_key<Hasher: DogeHashProvider>(public_key: [u8; 33]) -> Self {
+        Self {
+            public_key: Some(public_key),
+            ..Self::new(Hasher::bitcoin_hash160(&public_key))
+        }
+    }
+    pub fn is_active(&self, now: u32) -> bool {
+        now >= self.activation_time && (self.expiration_time == 0 || now < self.expiration_time)
+    }
+}
+
+// The guardian TSS keys that are valid during a key rotation window
+#[derive(PartialEq, Clone, Debug, Eq, Default)]
+pub struct WormholeTSSKeySet {
+    keys: Vec<WormholeTSSKey>,
+}
+
+impl WormholeTSSKeySet {
+    pub fn new() -> Self {
+        Self { keys: Vec::new() }
+    }
+    // a set with one key that is always active
+    pub fn new_single(public_key_hash: [u8; 20]) -> Self {
+        Self {
+            keys: vec![WormholeTSSKey::new(public_key_hash)],
+        }
+    }
+    pub fn add_key(&mut self, key: WormholeTSSKey) -> anyhow::Result<()> {
+        if self.get_key(&key.public_key_hash).is_some() {
+            anyhow::bail!("tss key {} is already registered", hex::encode(key.public_key_hash));
+        }
+        if key.expiration_time != 0 && key.expiration_time <= key.activation_time {
+            anyhow::bail!("tss key {} expires before it activates", hex::encode(key.public_key_hash));
+        }
+        self.keys.push(key);
+        Ok(())
+    }
+    // marks a key as retired at the given time, once the rotation to its successor is complete
+    pub fn set_key_expiration(&mut self, public_key_hash: &[u8; 20], expiration_time: u32) -> anyhow::Result<()> {
+        let key = self
+            .keys
+            .iter_mut()
+            .find(|key| key.public_key_hash == *public_key_hash)
+            .ok_or_else(|| anyhow::anyhow!("unknown tss key {}", hex::encode(public_key_hash)))?;
+        key.expiration_time = expiration_time;
+        Ok(())
+    }
+    pub fn get_key(&self, public_key_hash: &[u8; 20]) -> Option<&WormholeTSSKey> {
+        self.keys.iter().find(|key| key.public_key_hash == *public_key_hash)
+    }
+    pub fn get_keys(&self) -> &[WormholeTSSKey] {
+        &self.keys
+    }
+    pub fn get_active_keys(&self, now: u32) -> Vec<WormholeTSSKey> {
+        self.keys.iter().filter(|key| key.is_active(now)).copied().collect()
+    }
+    pub fn get_active_key_hashes(&self, now: u32) -> Vec<[u8; 20]> {
+        self.keys
+            .iter()
+            .filter(|key| key.is_active(now))
+            .map(|key| key.public_key_hash)
+            .collect()
+    }
+    // the most recently activated key, new deposit addresses should be built with this one
+    pub fn get_current_key_hash(&self, now: u32) -> Option<[u8; 20]> {
+        self.keys
+            .iter()
+            .filter(|key| key.is_active(now))
+            .max_by_key(|key| key.activation_time)
+            .map(|key| key.public_key_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_set_rotation_window() {
+        let mut key_set = WormholeTSSKeySet::new_single([1u8; 20]);
+        key_set
+            .add_key(WormholeTSSKey {
+                public_key_hash: [2u8; 20],
+                public_key: None,
+                activation_time: 1000,
+                expiration_time: 0,
+            })
+            .unwrap();
+        assert!(key_set.add_key(WormholeTSSKey::new([2u8; 20])).is_err());
+        key_set.set_key_expiration(&[1u8; 20], 2000).unwrap();
+
+        assert_eq!(key_set.get_active_key_hashes(999), vec![[1u8; 20]]);
+        assert_eq!(key_set.get_active_key_hashes(1500), vec![[1u8; 20], [2u8; 20]]);
+        assert_eq!(key_set.get_current_key_hash(1500), Some([2u8; 20]));
+        assert_eq!(key_set.get_active_key_hashes(2000), vec![[2u8; 20]]);
+    }
+}
@@ -37,4 +37,31 @@ impl<T: SimpleSingleSigner> WormholeTSSSignerSync for T {
     fn sign_message_tss_and_broadcast_to_other_guardians_and_wait_for_signature_sync(&self, message: QHash256) -> anyhow::Result<PsyCompressedSecp256K1Signature> {
         self.sign_message(message)
     }
-}
\ No newline at end of file
+}
+
+// for deployments without a tss: collects individual guardian signatures for an m-of-n CHECKMULTISIG redeem script
+#[async_trait::async_trait]
+pub trait WormholeMultisigSignerAsync {
+    // returns one signature per guardian key that signed, in any order
+    async fn collect_multisig_signatures(&self, message: QHash256) -> anyhow::Result<Vec<PsyCompressedSecp256K1Signature>>;
+}
+
+pub trait WormholeMultisigSignerSync {
+    // returns one signature per guardian key that signed, in any order
+    fn collect_multisig_signatures_sync(&self, message: QHash256) -> anyhow::Result<Vec<PsyCompressedSecp256K1Signature>>;
+}
+
+// for local development/debugging, every key of the multisig is held in this process
+#[async_trait::async_trait]
+impl<T: SimpleSingleSigner + Sync> WormholeMultisigSignerAsync for Vec<T> {
+    async fn collect_multisig_signatures(&self, message: QHash256) -> anyhow::Result<Vec<PsyCompressedSecp256K1Signature>> {
+        self.iter().map(|signer| signer.sign_message(message)).collect()
+    }
+}
+
+// for local development/debugging, every key of the multisig is held in this process
+impl<T: SimpleSingleSigner> WormholeMultisigSignerSync for Vec<T> {
+    fn collect_multisig_signatures_sync(&self, message: QHash256) -> anyhow::Result<Vec<PsyCompressedSecp256K1Signature>> {
+        self.iter().map(|signer| signer.sign_message(message)).collect()
+    }
+}
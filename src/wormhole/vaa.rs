@@ -0,0 +1,267 @@
+use crate::{
+    dogecoin::hash::{Keccak256HashProvider, QHash256},
+    wormhole::{
+        p2sh_vaa::{WormholeBitcoinLikeVAAMessage, WormholeBitcoinLikeVAAMessageKind},
+        payload::decode_bitcoin_like_vaa_payload_with_kind,
+    },
+};
+
+pub const WORMHOLE_VAA_VERSION: u8 = 1;
+pub const WORMHOLE_VAA_SIGNATURE_SIZE: usize = 65;
+
+// version (1) + guardian set index (4) + signature count (1)
+const SIZE_OF_VAA_HEADER_PREFIX: usize = 6;
+// timestamp (4) + nonce (4) + emitter chain (2) + emitter address (32) + sequence (8) + consistency level (1)
+const SIZE_OF_VAA_BODY_HEADER: usize = 51;
+
+// big endian cursor used for wormhole wire formats (VAA envelopes and payloads)
+pub(crate) struct WormholeBytesReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> WormholeBytesReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len() - self.offset
+    }
+    pub(crate) fn read_slice(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if self.remaining() < len {
+            anyhow::bail!(
+                "unexpected end of data, needed {} bytes at offset {} but only {} remain",
+                len,
+                self.offset,
+                self.remaining()
+            );
+        }
+        let slice = &self.data[self.offset..(self.offset + len)];
+        self.offset += len;
+        Ok(slice)
+    }
+    pub(crate) fn read_array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        let mut result = [0u8; N];
+        result.copy_from_slice(self.read_slice(N)?);
+        Ok(result)
+    }
+    pub(crate) fn read_u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.read_array::<1>()?[0])
+    }
+    pub(crate) fn read_u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_be_bytes(self.read_array()?))
+    }
+    pub(crate) fn read_u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_be_bytes(self.read_array()?))
+    }
+    pub(crate) fn read_u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_be_bytes(self.read_array()?))
+    }
+    pub(crate) fn read_rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.offset..];
+        self.offset = self.data.len();
+        rest
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Eq)]
+pub struct WormholeVAAGuardianSignature {
+    pub guardian_index: u8,
+    // r (32) || s (32) || recovery id (1)
+    pub signature: [u8; WORMHOLE_VAA_SIGNATURE_SIZE],
+}
+
+// (emitter_chain, emitter_address, sequence) uniquely identifies a message published through wormhole
+#[derive(PartialEq, Clone, Copy, Debug, Eq, Hash, PartialOrd, Ord)]
+pub struct WormholeVAAId {
+    pub emitter_chain: u16,
+    pub emitter_address: [u8; 32],
+    pub sequence: u64,
+}
+
+// A version 1 Wormhole VAA envelope
+#[derive(PartialEq, Clone, Debug, Eq)]
+pub struct WormholeVAA {
+    pub version: u8,
+    pub guardian_set_index: u32,
+    pub signatures: Vec<WormholeVAAGuardianSignature>,
+    pub timestamp: u32,
+    pub nonce: u32,
+    pub emitter_chain: u16,
+    pub emitter_address: [u8; 32],
+    pub sequence: u64,
+    pub consistency_level: u8,
+    pub payload: Vec<u8>,
+}
+
+impl WormholeVAA {
+    pub fn from_bytes(data: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = WormholeBytesReader::new(data);
+        let version = reader.read_u8()?;
+        if version != WORMHOLE_VAA_VERSION {
+            anyhow::bail!(
+                "unsupported VAA version {}, expected {}",
+                version,
+                WORMHOLE_VAA_VERSION
+            );
+        }
+        let guardian_set_index = reader.read_u32()?;
+        let signature_count = reader.read_u8()? as usize;
+        let mut signatures = Vec::with_capacity(signature_count);
+        for _ in 0..signature_count {
+            let guardian_index = reader.read_u8()?;
+            let signature = reader.read_array::<WORMHOLE_VAA_SIGNATURE_SIZE>()?;
+            signatures.push(WormholeVAAGuardianSignature {
+                guardian_index,
+                signature,
+            });
+        }
+        let timestamp = reader.read_u32()?;
+        let nonce = reader.read_u32()?;
+        let emitter_chain = reader.read_u16()?;
+        let emitter_address = reader.read_array::<32>()?;
+        let sequence = reader.read_u64()?;
+        let consistency_level = reader.read_u8()?;
+        let payload = reader.read_rest().to_vec();
+        Ok(Self {
+            version,
+            guardian_set_index,
+            signatures,
+            timestamp,
+            nonce,
+            emitter_chain,
+            emitter_address,
+            sequence,
+            consistency_level,
+            payload,
+        })
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            SIZE_OF_VAA_HEADER_PREFIX
+                + self.signatures.len() * (1 + WORMHOLE_VAA_SIGNATURE_SIZE)
+                + SIZE_OF_VAA_BODY_HEADER
+                + self.payload.len(),
+        );
+        bytes.push(self.version);
+        bytes.extend_from_slice(&self.guardian_set_index.to_be_bytes());
+        bytes.push(self.signatures.len() as u8);
+        for signature in self.signatures.iter() {
+            bytes.push(signature.guardian_index);
+            bytes.extend_from_slice(&signature.signature);
+        }
+        bytes.extend_from_slice(&self.get_body_bytes());
+        bytes
+    }
+    // the signed portion of the VAA (everything after the signatures)
+    pub fn get_body_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(SIZE_OF_VAA_BODY_HEADER + self.payload.len());
+        body.extend_from_slice(&self.timestamp.to_be_bytes());
+        body.extend_from_slice(&self.nonce.to_be_bytes());
+        body.extend_from_slice(&self.emitter_chain.to_be_bytes());
+        body.extend_from_slice(&self.emitter_address);
+        body.extend_from_slice(&self.sequence.to_be_bytes());
+        body.push(self.consistency_level);
+        body.extend_from_slice(&self.payload);
+        body
+    }
+    // keccak256(body), the identifier wormhole uses for a VAA
+    pub fn get_body_hash<Hasher: Keccak256HashProvider>(&self) -> QHash256 {
+        Hasher::hash_bytes_keccak256(&self.get_body_bytes())
+    }
+    // keccak256(keccak256(body)), the digest the guardians sign
+    pub fn get_signing_digest<Hasher: Keccak256HashProvider>(&self) -> QHash256 {
+        Hasher::hash_bytes_keccak256(&self.get_body_hash::<Hasher>())
+    }
+    pub fn get_vaa_id(&self) -> WormholeVAAId {
+        WormholeVAAId {
+            emitter_chain: self.emitter_chain,
+            emitter_address: self.emitter_address,
+            sequence: self.sequence,
+        }
+    }
+    pub fn to_bitcoin_like_vaa_message(&self) -> anyhow::Result<WormholeBitcoinLikeVAAMessage> {
+        match self.to_bitcoin_like_vaa_message_with_kind()? {
+            (WormholeBitcoinLikeVAAMessageKind::Spend, message) => Ok(message),
+            (kind, _) => anyhow::bail!("expected a spend VAA but got a {:?} VAA", kind),
+        }
+    }
+    pub fn to_bitcoin_like_vaa_message_with_kind(
+        &self,
+    ) -> anyhow::Result<(WormholeBitcoinLikeVAAMessageKind, WormholeBitcoinLikeVAAMessage)> {
+        let (kind, message) = decode_bitcoin_like_vaa_payload_with_kind(&self.payload)?;
+        // the payload must not be able to claim funds locked to a different emitter than the one that published it
+        if message.metadata.emitter_chain != self.emitter_chain
+            || message.metadata.emitter_contract_address != self.emitter_address
+        {
+            anyhow::bail!(
+                "VAA payload emitter (chain={}, address={}) does not match the VAA emitter (chain={}, address={})",
+                message.metadata.emitter_chain,
+                hex::encode(message.metadata.emitter_contract_address),
+                self.emitter_chain,
+                hex::encode(self.emitter_address)
+            );
+        }
+        Ok((kind, message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wormhole::{payload::encode_bitcoin_like_vaa_payload, test_utils::example_message};
+
+    use super::*;
+
+    #[test]
+    fn test_vaa_round_trip_to_bitcoin_like_message() {
+        let message = example_message();
+        let vaa = WormholeVAA {
+            version: WORMHOLE_VAA_VERSION,
+            guardian_set_index: 4,
+            signatures: vec![
+                WormholeVAAGuardianSignature {
+                    guardian_index: 0,
+                    signature: [0x11; WORMHOLE_VAA_SIGNATURE_SIZE],
+                },
+                WormholeVAAGuardianSignature {
+                    guardian_index: 3,
+                    signature: [0x22; WORMHOLE_VAA_SIGNATURE_SIZE],
+                },
+            ],
+            timestamp: 1_700_000_000,
+            nonce: 42,
+            emitter_chain: message.metadata.emitter_chain,
+            emitter_address: message.metadata.emitter_contract_address,
+            sequence: 1337,
+            consistency_level: 32,
+            payload: encode_bitcoin_like_vaa_payload(&message).unwrap(),
+        };
+        let raw_vaa = vaa.to_bytes();
+        let decoded = WormholeVAA::from_bytes(&raw_vaa).unwrap();
+        assert_eq!(decoded, vaa);
+        assert_eq!(decoded.to_bitcoin_like_vaa_message().unwrap(), message);
+
+        let mut spoofed = vaa.clone();
+        spoofed.emitter_address = [9u8; 32];
+        assert!(spoofed.to_bitcoin_like_vaa_message().is_err());
+    }
+
+    #[test]
+    fn test_vaa_rejects_truncated_envelope() {
+        let vaa = WormholeVAA {
+            version: WORMHOLE_VAA_VERSION,
+            guardian_set_index: 0,
+            signatures: vec![],
+            timestamp: 0,
+            nonce: 0,
+            emitter_chain: 1,
+            emitter_address: [0u8; 32],
+            sequence: 0,
+            consistency_level: 1,
+            payload: vec![],
+        };
+        let raw_vaa = vaa.to_bytes();
+        assert!(WormholeVAA::from_bytes(&raw_vaa[..raw_vaa.len() - 1]).is_err());
+        assert!(WormholeVAA::from_bytes(&[2u8]).is_err());
+    }
+}
@@ -0,0 +1,457 @@
+use std::collections::VecDeque;
+
+use crate::{
+    dogecoin::{
+        address::{AddressToBTCScript, BTCAddress160},
+        constants::DogeNetworkConfig,
+        hash::DogeHashProvider,
+        transaction::{BTCTransactionInputWithoutScript, BTCTransactionOutput},
+    },
+    psy_doge_link::{
+        coin_selection::{CoinSelection, CoinSelectionParams, CoinSelector, LargestFirstCoinSelector, select_coins},
+        data::BTCUTXO,
+        traits::PsyBitcoinAPIAsync,
+    },
+    wormhole::{
+        fee_estimator::{WormholeVAAFeeBounds, WormholeVAASpendSize},
+        p2sh_vaa::{WormholeBitcoinLikeVAAMessage, WormholeBitcoinLikeVAAMetadata},
+        template_registry::{WormholeGuardianKey, WormholeScriptTemplateRegistry},
+    },
+};
+
+// A withdrawal spread over several messages, each published as its own VAA
+#[derive(PartialEq, Clone, Debug, Eq, Default)]
+pub struct WormholeWithdrawalPlan {
+    pub messages: Vec<WormholeBitcoinLikeVAAMessage>,
+    // sum of the fees paid by the messages
+    pub total_fee: u64,
+}
+
+impl WormholeWithdrawalPlan {
+    // the amount paid to script across all messages, change outputs included
+    pub fn get_amount_paid_to_script(&self, script: &[u8]) -> u64 {
+        self.messages
+            .iter()
+            .flat_map(|message| message.outputs.iter())
+            .filter(|output| output.script == script)
+            .fold(0u64, |sum, output| sum.saturating_add(output.value))
+    }
+}
+
+// Builds a message paying destination outputs from the VAA-P2SH address of a guardian key,
+// with change returned to the same address.
+#[derive(Clone, Debug)]
+pub struct WormholeWithdrawalPlanner<Selector: CoinSelector = LargestFirstCoinSelector> {
+    guardian_key: WormholeGuardianKey,
+    // koinu per byte
+    fee_rate: u64,
+    max_inputs: usize,
+    include_unconfirmed: bool,
+    coin_selector: Selector,
+    script_template_registry: WormholeScriptTemplateRegistry,
+}
+
+impl WormholeWithdrawalPlanner {
+    // P2PK templates need a key with its full public key, see WormholeGuardianKey::new_with_public_key
+    pub fn new(guardian_key: WormholeGuardianKey, fee_rate: u64) -> Self {
+        Self {
+            guardian_key,
+            fee_rate,
+            max_inputs: 100,
+            include_unconfirmed: false,
+            coin_selector: LargestFirstCoinSelector,
+            script_template_registry: WormholeScriptTemplateRegistry::new_with_builtin_templates(),
+        }
+    }
+}
+
+impl<Selector: CoinSelector> WormholeWithdrawalPlanner<Selector> {
+    pub fn with_max_inputs(mut self, max_inputs: usize) -> Self {
+        self.max_inputs = max_inputs.max(1);
+        self
+    }
+    pub fn with_unconfirmed_utxos(mut self, include_unconfirmed: bool) -> Self {
+        self.include_unconfirmed = include_unconfirmed;
+        self
+    }
+    pub fn with_coin_selector<NewSelector: CoinSelector>(
+        self,
+        coin_selector: NewSelector,
+    ) -> WormholeWithdrawalPlanner<NewSelector> {
+        WormholeWithdrawalPlanner {
+            guardian_key: self.guardian_key,
+            fee_rate: self.fee_rate,
+            max_inputs: self.max_inputs,
+            include_unconfirmed: self.include_unconfirmed,
+            coin_selector,
+            script_template_registry: self.script_template_registry,
+        }
+    }
+    // the templates addresses are derived and spends are sized with, the builtin ones by default
+    pub fn with_script_template_registry(mut self, script_template_registry: WormholeScriptTemplateRegistry) -> Self {
+        self.script_template_registry = script_template_registry;
+        self
+    }
+    pub fn get_guardian_key(&self) -> &WormholeGuardianKey {
+        &self.guardian_key
+    }
+    pub fn get_p2sh_address<Hasher: DogeHashProvider>(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+    ) -> anyhow::Result<BTCAddress160> {
+        self.script_template_registry
+            .get_p2sh_address::<Hasher>(metadata, &self.guardian_key)
+    }
+
+    fn get_fee_bounds<N: DogeNetworkConfig>(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        input_count: usize,
+        outputs: &[BTCTransactionOutput],
+    ) -> anyhow::Result<WormholeVAAFeeBounds> {
+        let output_scripts = outputs.iter().map(|output| output.script.clone()).collect::<Vec<Vec<u8>>>();
+        Ok(WormholeVAASpendSize::estimate_with_registry(&self.script_template_registry, metadata, input_count, &output_scripts)?
+            .get_fee_bounds(&N::RELAY_POLICY, self.fee_rate)
+            .with_dust_fee(&N::RELAY_POLICY, outputs))
+    }
+
+    fn get_coin_selection_params<N: DogeNetworkConfig>(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        outputs: &[BTCTransactionOutput],
+        change_output: &BTCTransactionOutput,
+    ) -> anyhow::Result<CoinSelectionParams> {
+        Ok(CoinSelectionParams::new(
+            outputs.iter().fold(0u64, |sum, output| sum.saturating_add(output.value)),
+            self.script_template_registry.estimate_script_sig_size(metadata)?,
+            outputs.iter().map(|output| output.script.len()).collect(),
+            change_output.script.len(),
+            self.fee_rate,
+        )
+        .with_relay_policy(N::RELAY_POLICY)
+        .with_max_inputs(self.max_inputs)
+        .with_dust_fee(N::RELAY_POLICY.get_dust_fee(outputs)))
+    }
+
+    fn check_destination_outputs<N: DogeNetworkConfig>(&self, outputs: &[BTCTransactionOutput]) -> anyhow::Result<()> {
+        if outputs.is_empty() {
+            anyhow::bail!("a withdrawal needs at least one destination output");
+        }
+        outputs.iter().try_fold(0u64, |sum, output| {
+            sum.checked_add(output.value)
+                .ok_or_else(|| anyhow::anyhow!("u64 overflow in addition"))
+        })?;
+        N::RELAY_POLICY.check_outputs(outputs)
+    }
+
+    fn get_spendable_utxos(&self, utxos: &[BTCUTXO]) -> Vec<BTCUTXO> {
+        utxos
+            .iter()
+            .filter(|utxo| self.include_unconfirmed || utxo.status.confirmed)
+            .copied()
+            .collect()
+    }
+
+    // one message paying outputs, returns the message and the coin selection funding it
+    fn plan_message<N: DogeNetworkConfig, S: CoinSelector + ?Sized>(
+        &self,
+        coin_selector: &S,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        utxos: &[BTCUTXO],
+        outputs: &[BTCTransactionOutput],
+        change_output: &BTCTransactionOutput,
+    ) -> anyhow::Result<(WormholeBitcoinLikeVAAMessage, CoinSelection<BTCUTXO>)> {
+        let params = self.get_coin_selection_params::<N>(metadata, outputs, change_output)?;
+        let selection = select_coins(coin_selector, utxos, &params)?;
+
+        let mut final_outputs = outputs.to_vec();
+        if selection.has_change() {
+            final_outputs.push(BTCTransactionOutput {
+                value: selection.change_amount,
+                ..change_output.clone()
+            });
+        }
+        let fee_bounds = self.get_fee_bounds::<N>(metadata, selection.selected.len(), &final_outputs)?;
+        let fee_bounds = WormholeVAAFeeBounds {
+            max_doge_transaction_fee: fee_bounds.max_doge_transaction_fee.max(selection.fee),
+            ..fee_bounds
+        };
+        let message = WormholeBitcoinLikeVAAMessage {
+            metadata: WormholeBitcoinLikeVAAMetadata {
+                total_output_amount: params.target_amount + selection.change_amount,
+                ..fee_bounds.apply_to_metadata(metadata)
+            },
+            inputs: selection.selected.iter().map(BTCTransactionInputWithoutScript::from).collect(),
+            outputs: final_outputs,
+        };
+        Ok((message, selection))
+    }
+
+    /*
+    I/O free planning, utxos must belong to the VAA-P2SH address of metadata and the guardian key.
+    Inputs are picked by the coin selector, sized for the worst case signing of the template and
+    capped by the standard transaction size. Change below the soft dust limit is left to the fee, the fee
+    ceiling of the message is raised to match.
+    */
+    pub fn plan_for_utxos<N: DogeNetworkConfig, Hasher: DogeHashProvider>(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        utxos: &[BTCUTXO],
+        outputs: &[BTCTransactionOutput],
+    ) -> anyhow::Result<WormholeBitcoinLikeVAAMessage> {
+        self.check_destination_outputs::<N>(outputs)?;
+        let change_output = self.get_p2sh_address::<Hasher>(metadata)?.to_btc_output(0);
+        let (message, _) = self.plan_message::<N, _>(&self.coin_selector, metadata, &self.get_spendable_utxos(utxos), outputs, &change_output)?;
+        Ok(message)
+    }
+
+    /*
+    Same as plan_for_utxos for withdrawals too large for one standard transaction: destination outputs
+    are packed in order into messages that the largest remaining utxos can fund within the size limit.
+    An output that does not fit is split, the part paid in each message stays above the soft dust limit.
+    Messages are funded largest first whatever the configured coin selector, as their capacity assumes it.
+    */
+    pub fn plan_split_for_utxos<N: DogeNetworkConfig, Hasher: DogeHashProvider>(
+        &self,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        utxos: &[BTCUTXO],
+        outputs: &[BTCTransactionOutput],
+    ) -> anyhow::Result<WormholeWithdrawalPlan> {
+        self.check_destination_outputs::<N>(outputs)?;
+        let change_output = self.get_p2sh_address::<Hasher>(metadata)?.to_btc_output(0);
+        // parts of a split output are kept clear of the soft dust fee
+        let min_part = N::RELAY_POLICY.get_min_output_value();
+        let mut pool = self.get_spendable_utxos(utxos);
+        pool.sort_by_key(|utxo| (std::cmp::Reverse(utxo.value), utxo.txid, utxo.vout));
+
+        let mut pending = outputs.iter().cloned().collect::<VecDeque<BTCTransactionOutput>>();
+        let mut plan = WormholeWithdrawalPlan::default();
+        while !pending.is_empty() {
+            // what the largest remaining utxos can pay to group, after the fee of a message with change
+            let get_capacity = |group: &[BTCTransactionOutput]| -> anyhow::Result<u64> {
+                let params = self.get_coin_selection_params::<N>(metadata, group, &change_output)?;
+                let input_count = params.get_max_input_count().min(pool.len());
+                let total = pool[0..input_count].iter().fold(0u64, |sum, utxo| sum.saturating_add(utxo.value));
+                Ok(total.saturating_sub(params.get_fee(input_count, true)))
+            };
+            let mut group: Vec<BTCTransactionOutput> = Vec::new();
+            let mut group_amount = 0u64;
+            while let Some(output) = pending.front_mut() {
+                let candidate = [group.as_slice(), std::slice::from_ref(output)].concat();
+                let capacity = get_capacity(&candidate)?;
+                if group_amount + output.value <= capacity {
+                    group_amount += output.value;
+                    group = candidate;
+                    pending.pop_front();
+                    continue;
+                }
+                let part = (capacity.saturating_sub(group_amount)).min(output.value.saturating_sub(min_part));
+                if part >= min_part {
+                    group.push(BTCTransactionOutput {
+                        value: part,
+                        ..output.clone()
+                    });
+                    output.value -= part;
+                }
+                break;
+            }
+            if group.is_empty() {
+                anyhow::bail!(
+                    "insufficient funds: {} remaining utxos cannot pay {} more destination outputs",
+                    pool.len(),
+                    pending.len()
+                );
+            }
+            let (message, selection) =
+                self.plan_message::<N, _>(&LargestFirstCoinSelector, metadata, &pool, &group, &change_output)?;
+            pool.retain(|utxo| !selection.selected.contains(utxo));
+            plan.total_fee += selection.fee;
+            plan.messages.push(message);
+        }
+        Ok(plan)
+    }
+
+    pub async fn plan<N: DogeNetworkConfig, Hasher: DogeHashProvider, API: PsyBitcoinAPIAsync>(
+        &self,
+        api: &API,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        outputs: &[BTCTransactionOutput],
+    ) -> anyhow::Result<WormholeBitcoinLikeVAAMessage> {
+        let address = self.get_p2sh_address::<Hasher>(metadata)?;
+        let utxos = api.get_utxos(address).await?;
+        self.plan_for_utxos::<N, Hasher>(metadata, &utxos, outputs)
+    }
+
+    pub async fn plan_split<N: DogeNetworkConfig, Hasher: DogeHashProvider, API: PsyBitcoinAPIAsync>(
+        &self,
+        api: &API,
+        metadata: &WormholeBitcoinLikeVAAMetadata,
+        outputs: &[BTCTransactionOutput],
+    ) -> anyhow::Result<WormholeWithdrawalPlan> {
+        let address = self.get_p2sh_address::<Hasher>(metadata)?;
+        let utxos = api.get_utxos(address).await?;
+        self.plan_split_for_utxos::<N, Hasher>(metadata, &utxos, outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dogecoin::{
+            address::BTCAddress160,
+            constants::DogeTestNetConfig,
+            hash::CommonDogeHashProvider,
+            transaction::{BTCTransaction, BTCTransactionInput},
+        },
+        psy_doge_link::{
+            coin_selection::{BranchAndBoundCoinSelector, OldestFirstCoinSelector},
+            data::BTCUTXOStatus,
+        },
+        wormhole::test_utils::{EXAMPLE_GUARDIAN_PUBLIC_KEY_HASH, example_metadata, get_confirmed_utxo, get_input_transactions},
+    };
+
+    use super::*;
+
+    type Hasher = CommonDogeHashProvider;
+    type Network = DogeTestNetConfig;
+
+    #[test]
+    fn test_withdrawal_messages_validate_with_and_without_change() {
+        let guardian_key = EXAMPLE_GUARDIAN_PUBLIC_KEY_HASH;
+        let metadata = example_metadata();
+        let address = metadata.get_p2sh_address::<Network, Hasher>(&guardian_key).unwrap();
+        let funding_transactions = [5_000_000u64, 20_000_000, 8_000_000]
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                BTCTransaction::from_io(
+                    vec![BTCTransactionInput { hash: [i as u8; 32], ..Default::default() }],
+                    vec![address.to_btc_output(*value)],
+                )
+            })
+            .collect::<Vec<BTCTransaction>>();
+        let utxos = funding_transactions
+            .iter()
+            .map(|tx| get_confirmed_utxo::<Hasher>(tx, 0))
+            .collect::<Vec<BTCUTXO>>();
+        let destination = BTCAddress160::new_p2pkh([9u8; 20]);
+        let planner = WormholeWithdrawalPlanner::new(WormholeGuardianKey::new(guardian_key), 1000);
+
+        let validate = |message: &WormholeBitcoinLikeVAAMessage| {
+            let input_transactions = get_input_transactions::<Hasher>(message, &funding_transactions);
+            message
+                .validate_and_get_sighashes_with_inputs::<Hasher, Network>(&input_transactions, &[guardian_key])
+                .unwrap();
+        };
+
+        // the largest utxo covers the payment, the rest comes back as change
+        let message = planner
+            .plan_for_utxos::<Network, Hasher>(&metadata, &utxos, &[destination.to_btc_output(15_000_000)])
+            .unwrap();
+        validate(&message);
+        assert_eq!(message.inputs.len(), 1);
+        assert_eq!(message.outputs.len(), 2);
+        assert_eq!(message.outputs[1].get_output_address().unwrap(), address);
+
+        // two inputs whose leftover after the fee is dust, it is paid to the miner
+        let fee = planner
+            .get_fee_bounds::<Network>(&metadata, 2, &[destination.to_btc_output(0)])
+            .unwrap()
+            .max_doge_transaction_fee;
+        let message = planner
+            .plan_for_utxos::<Network, Hasher>(&metadata, &utxos, &[destination.to_btc_output(28_000_000 - fee - 1)])
+            .unwrap();
+        validate(&message);
+        assert_eq!((message.inputs.len(), message.outputs.len()), (2, 1));
+        assert_eq!(message.metadata.max_doge_transaction_fee, fee + 1);
+
+        // branch and bound finds the changeless pair of the two smaller utxos
+        let message = planner
+            .clone()
+            .with_coin_selector(BranchAndBoundCoinSelector::new())
+            .plan_for_utxos::<Network, Hasher>(&metadata, &utxos, &[destination.to_btc_output(13_000_000 - fee)])
+            .unwrap();
+        validate(&message);
+        assert_eq!((message.inputs.len(), message.outputs.len()), (2, 1));
+        assert_eq!(message.metadata.max_doge_transaction_fee, fee);
+
+        assert!(planner
+            .plan_for_utxos::<Network, Hasher>(&metadata, &utxos, &[destination.to_btc_output(33_000_000)])
+            .is_err());
+        assert!(planner
+            .plan_for_utxos::<Network, Hasher>(&metadata, &utxos, &[destination.to_btc_output(1)])
+            .is_err());
+    }
+
+    #[test]
+    fn test_split_withdrawal_stays_under_standard_size() {
+        let guardian_key = EXAMPLE_GUARDIAN_PUBLIC_KEY_HASH;
+        let metadata = example_metadata();
+        let address = metadata.get_p2sh_address::<Network, Hasher>(&guardian_key).unwrap();
+        let funding_transactions = (0..700u32)
+            .map(|i| {
+                BTCTransaction::from_io(
+                    vec![BTCTransactionInput { hash: [0u8; 32], index: i, ..Default::default() }],
+                    vec![address.to_btc_output(1_000_000)],
+                )
+            })
+            .collect::<Vec<BTCTransaction>>();
+        let utxos = funding_transactions
+            .iter()
+            .map(|tx| get_confirmed_utxo::<Hasher>(tx, 0))
+            .collect::<Vec<BTCUTXO>>();
+        let destinations = [[7u8; 20], [8u8; 20], [9u8; 20]].map(BTCAddress160::new_p2pkh);
+        let amounts = [300_000_000u64, 150_000_000, 500_000];
+        let outputs = destinations
+            .iter()
+            .zip(amounts)
+            .map(|(destination, amount)| destination.to_btc_output(amount))
+            .collect::<Vec<BTCTransactionOutput>>();
+
+        let planner = WormholeWithdrawalPlanner::new(WormholeGuardianKey::new(guardian_key), 1000).with_max_inputs(1000);
+        // a single message would need more than 100 kB of inputs
+        assert!(planner.plan_for_utxos::<Network, Hasher>(&metadata, &utxos, &outputs).is_err());
+        let plan = planner.plan_split_for_utxos::<Network, Hasher>(&metadata, &utxos, &outputs).unwrap();
+        assert!(plan.messages.len() > 1);
+
+        let mut input_count = 0;
+        for message in plan.messages.iter() {
+            let input_transactions = get_input_transactions::<Hasher>(message, &funding_transactions);
+            message
+                .validate_and_get_sighashes_with_inputs::<Hasher, Network>(&input_transactions, &[guardian_key])
+                .unwrap();
+            input_count += message.inputs.len();
+        }
+        for (destination, amount) in destinations.iter().zip(amounts) {
+            assert_eq!(plan.get_amount_paid_to_script(&destination.to_btc_script()), amount);
+        }
+        let change_amount = plan.get_amount_paid_to_script(&address.to_btc_script());
+        assert_eq!(
+            input_count as u64 * 1_000_000,
+            amounts.iter().sum::<u64>() + change_amount + plan.total_fee
+        );
+
+        // the capacity of a message is that of its largest utxos, another selector must not fund it differently
+        let utxos_oldest_last = utxos
+            .iter()
+            .enumerate()
+            .map(|(i, utxo)| BTCUTXO {
+                status: BTCUTXOStatus { block_height: 1000 - i as u64, ..utxo.status },
+                ..*utxo
+            })
+            .collect::<Vec<BTCUTXO>>();
+        assert_eq!(
+            planner
+                .clone()
+                .with_coin_selector(OldestFirstCoinSelector)
+                .plan_split_for_utxos::<Network, Hasher>(&metadata, &utxos_oldest_last, &outputs)
+                .unwrap(),
+            plan
+        );
+
+        // more than the utxos can pay after fees
+        assert!(planner
+            .plan_split_for_utxos::<Network, Hasher>(&metadata, &utxos, &[destinations[0].to_btc_output(700_000_000)])
+            .is_err());
+    }
+}